@@ -0,0 +1,143 @@
+use crate::models::CompressionConfig;
+use std::io::Write;
+
+// 订阅响应支持的内容编码
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    Identity,
+    Gzip,
+    Brotli,
+    Zstd,
+}
+
+impl Encoding {
+    // Content-Encoding / Accept-Encoding 中使用的名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Identity => "identity",
+            Encoding::Gzip => "gzip",
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+        }
+    }
+
+    // 同等权重时的服务端偏好，数值越大越优先
+    fn preference(&self) -> u8 {
+        match self {
+            Encoding::Identity => 0,
+            Encoding::Gzip => 1,
+            Encoding::Brotli => 2,
+            Encoding::Zstd => 3,
+        }
+    }
+
+    fn from_token(token: &str) -> Option<Self> {
+        match token.to_ascii_lowercase().as_str() {
+            "identity" => Some(Encoding::Identity),
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            "br" => Some(Encoding::Brotli),
+            "zstd" => Some(Encoding::Zstd),
+            _ => None,
+        }
+    }
+
+    // 当前配置下使用的压缩级别，用于区分缓存
+    pub fn level(&self, config: &CompressionConfig) -> i32 {
+        match self {
+            Encoding::Identity => 0,
+            Encoding::Gzip => config.gzip_level as i32,
+            Encoding::Brotli => config.brotli_level as i32,
+            Encoding::Zstd => config.zstd_level,
+        }
+    }
+}
+
+// 解析Accept-Encoding头中的q值，格式错误时按1处理
+fn parse_quality(params: &str) -> f32 {
+    params
+        .split(';')
+        .filter_map(|param| param.trim().strip_prefix("q="))
+        .find_map(|q| q.trim().parse::<f32>().ok())
+        .unwrap_or(1.0)
+        .clamp(0.0, 1.0)
+}
+
+// 根据Accept-Encoding协商内容编码
+// 权重最高者优先，权重相同时按 zstd > br > gzip 选择；q=0表示拒绝该编码
+pub fn negotiate(accept_encoding: Option<&str>) -> Encoding {
+    let Some(header) = accept_encoding else {
+        return Encoding::Identity;
+    };
+
+    let mut wildcard: Option<f32> = None;
+    let mut explicit: Vec<(Encoding, f32)> = Vec::new();
+    for item in header.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+        let (token, params) = item.split_once(';').unwrap_or((item, ""));
+        let quality = parse_quality(params);
+        let token = token.trim();
+        if token == "*" {
+            wildcard = Some(quality);
+        } else if let Some(encoding) = Encoding::from_token(token) {
+            explicit.push((encoding, quality));
+        }
+    }
+
+    let quality_of = |encoding: Encoding| {
+        explicit
+            .iter()
+            .find(|(e, _)| *e == encoding)
+            .map(|(_, q)| *q)
+            .or(wildcard)
+    };
+
+    [Encoding::Zstd, Encoding::Brotli, Encoding::Gzip]
+        .into_iter()
+        .filter_map(|encoding| {
+            quality_of(encoding)
+                .filter(|q| *q > 0.0)
+                .map(|q| (encoding, q))
+        })
+        .max_by(|(a, qa), (b, qb)| {
+            qa.total_cmp(qb)
+                .then_with(|| a.preference().cmp(&b.preference()))
+        })
+        .map(|(encoding, _)| encoding)
+        .unwrap_or(Encoding::Identity)
+}
+
+// 按指定编码压缩数据
+pub fn compress(
+    encoding: Encoding,
+    data: &[u8],
+    config: &CompressionConfig,
+) -> Result<Vec<u8>, String> {
+    match encoding {
+        Encoding::Identity => Ok(data.to_vec()),
+        Encoding::Zstd => {
+            zstd::encode_all(data, config.zstd_level).map_err(|e| format!("zstd压缩失败: {}", e))
+        }
+        Encoding::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(
+                Vec::new(),
+                flate2::Compression::new(config.gzip_level.min(9)),
+            );
+            encoder
+                .write_all(data)
+                .and_then(|_| encoder.finish())
+                .map_err(|e| format!("gzip压缩失败: {}", e))
+        }
+        Encoding::Brotli => {
+            // lgwin=22为brotli默认窗口大小，into_inner时写入结束标记
+            let mut writer =
+                brotli::CompressorWriter::new(Vec::new(), 4096, config.brotli_level.min(11), 22);
+            writer
+                .write_all(data)
+                .map_err(|e| format!("brotli压缩失败: {}", e))?;
+            Ok(writer.into_inner())
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use crate::handlers::common::decode_key;
+use crate::models::CredentialsConfig;
+use crate::users::UserEntry;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+
+// 支持按用户生成密码的协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Protocol {
+    #[serde(rename = "trojan")]
+    Trojan,
+    #[serde(rename = "ss")]
+    Shadowsocks,
+    #[serde(rename = "hysteria2")]
+    Hysteria2,
+    #[serde(rename = "tuic")]
+    Tuic,
+}
+
+impl Protocol {
+    // 与代理节点type字段一致的名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Protocol::Trojan => "trojan",
+            Protocol::Shadowsocks => "ss",
+            Protocol::Hysteria2 => "hysteria2",
+            Protocol::Tuic => "tuic",
+        }
+    }
+
+    // 根据代理节点的type字段识别协议
+    pub fn from_proxy_type(proxy_type: &str) -> Option<Self> {
+        match proxy_type {
+            "trojan" => Some(Protocol::Trojan),
+            "ss" => Some(Protocol::Shadowsocks),
+            "hysteria2" => Some(Protocol::Hysteria2),
+            "tuic" => Some(Protocol::Tuic),
+            _ => None,
+        }
+    }
+}
+
+// shadowsocks 2022 加密方式要求的密钥长度，其他加密方式返回None
+pub fn ss2022_key_len(cipher: &str) -> Option<usize> {
+    match cipher {
+        "2022-blake3-aes-128-gcm" => Some(16),
+        "2022-blake3-aes-256-gcm" | "2022-blake3-chacha20-poly1305" => Some(32),
+        _ => None,
+    }
+}
+
+// 模板中需要按用户替换的密码
+#[derive(Debug, Clone)]
+pub struct PasswordSlot {
+    pub protocol: Protocol,
+    // ss节点的加密方式
+    pub cipher: Option<String>,
+    // 模板中的原始密码，协议未启用时原样输出；ss 2022中为服务端密钥
+    pub original: String,
+}
+
+impl PasswordSlot {
+    // 识别代理节点中可以按用户替换的密码字段
+    pub fn from_proxy(proxy: &HashMap<String, serde_yaml_ng::Value>) -> Option<Self> {
+        let protocol = Protocol::from_proxy_type(proxy.get("type")?.as_str()?)?;
+        let original = proxy.get("password")?.as_str()?.to_string();
+        let cipher = proxy
+            .get("cipher")
+            .and_then(|v| v.as_str())
+            .map(|cipher| cipher.to_string());
+        Some(Self {
+            protocol,
+            cipher,
+            original,
+        })
+    }
+}
+
+// 检查short-id格式: 不超过16位的十六进制字符串，长度为偶数
+pub fn check_short_id(short_id: &str) -> Result<(), String> {
+    if short_id.len() > 16
+        || !short_id.len().is_multiple_of(2)
+        || !short_id.bytes().all(|b| b.is_ascii_hexdigit())
+    {
+        return Err(format!(
+            "short-id应为不超过16位、长度为偶数的十六进制字符串: {}",
+            short_id
+        ));
+    }
+    Ok(())
+}
+
+// 以 "用途:UUID" 为消息计算HMAC-SHA256
+fn hmac_digest(key: &[u8], label: &str, uuid: &str) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC可以使用任意长度的密钥");
+    mac.update(label.as_bytes());
+    mac.update(b":");
+    mac.update(uuid.to_ascii_lowercase().as_bytes());
+    mac.finalize().into_bytes().into()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// 由UUID和HMAC密钥确定性地生成密码
+// ss 2022 生成对应长度的Base64密钥，其他协议生成32位十六进制字符串
+pub fn derive_password(key: &[u8], protocol: Protocol, uuid: &str, cipher: Option<&str>) -> String {
+    let digest = hmac_digest(key, protocol.as_str(), uuid);
+    match cipher.and_then(ss2022_key_len) {
+        Some(len) => STANDARD.encode(&digest[..len]),
+        None => to_hex(&digest[..16]),
+    }
+}
+
+// 由UUID和HMAC密钥确定性地生成reality short-id，length为十六进制字符数
+pub fn derive_short_id(key: &[u8], uuid: &str, length: usize) -> String {
+    let digest = hmac_digest(key, "reality", uuid);
+    to_hex(&digest[..length / 2])
+}
+
+// 单个用户的密码生成器
+pub struct Credentials<'a> {
+    key: Vec<u8>,
+    config: &'a CredentialsConfig,
+    // users.yml中的用户信息，其中指定的密码和short-id优先于生成的值
+    user: Option<&'a UserEntry>,
+}
+
+impl<'a> Credentials<'a> {
+    pub fn new(
+        config: &'a CredentialsConfig,
+        encryption_key: &str,
+        user: Option<&'a UserEntry>,
+    ) -> Result<Self, String> {
+        let key = match config.key.as_deref().filter(|key| !key.is_empty()) {
+            Some(key) => key.as_bytes().to_vec(),
+            None => decode_key(encryption_key)?,
+        };
+        Ok(Self { key, config, user })
+    }
+
+    // 协议是否启用按用户生成密码
+    pub fn enabled(&self, protocol: Protocol) -> bool {
+        self.config.protocols.contains(&protocol)
+    }
+
+    // 是否启用按用户生成reality short-id
+    pub fn short_id_enabled(&self) -> bool {
+        self.config.short_id
+    }
+
+    // 用户在服务端配置中的密码，ss 2022为用户密钥
+    pub fn user_password(&self, protocol: Protocol, uuid: &str, cipher: Option<&str>) -> String {
+        match self.user.and_then(|user| user.passwords.get(&protocol)) {
+            Some(password) => password.clone(),
+            None => derive_password(&self.key, protocol, uuid, cipher),
+        }
+    }
+
+    // 客户端配置中使用的密码，协议未启用时保留模板中的原始密码
+    // ss 2022 多用户模式的客户端密码格式为 "服务端密钥:用户密钥"
+    pub fn client_password(&self, slot: &PasswordSlot, uuid: &str) -> String {
+        if !self.enabled(slot.protocol) {
+            return slot.original.clone();
+        }
+        let cipher = slot.cipher.as_deref();
+        let password = self.user_password(slot.protocol, uuid, cipher);
+        if cipher.and_then(ss2022_key_len).is_none() {
+            return password;
+        }
+        match slot
+            .original
+            .split(':')
+            .next()
+            .filter(|psk| !psk.is_empty())
+        {
+            Some(server_key) => format!("{}:{}", server_key, password),
+            None => password,
+        }
+    }
+
+    // 用户的reality short-id
+    pub fn short_id(&self, uuid: &str) -> String {
+        match self.user.and_then(|user| user.short_id.as_ref()) {
+            Some(short_id) => short_id.clone(),
+            None => derive_short_id(&self.key, uuid, self.config.short_id_length),
+        }
+    }
+
+    // 客户端配置中使用的short-id，未启用时保留模板中的原始值
+    pub fn client_short_id(&self, original: &str, uuid: &str) -> String {
+        if !self.short_id_enabled() {
+            return original.to_string();
+        }
+        self.short_id(uuid)
+    }
+}
@@ -0,0 +1,294 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+
+// geosite.dat和geoip.dat所在目录，与v2ray/Xray使用的文件格式相同
+pub const GEO_DIR: &str = "config/geo";
+const GEOSITE_FILE: &str = "geosite.dat";
+const GEOIP_FILE: &str = "geoip.dat";
+
+// geosite中的域名类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DomainKind {
+    // 包含关键字
+    Keyword,
+    // 正则表达式
+    Regex,
+    // 域名及其子域名
+    Suffix,
+    // 完整匹配
+    Full,
+}
+
+// geosite分类中的一条域名
+#[derive(Debug, Clone)]
+pub struct SiteDomain {
+    pub kind: DomainKind,
+    pub value: String,
+    attributes: Vec<String>,
+}
+
+// geo数据文件，按需读取，同一次加载中每个文件只读取一次
+#[derive(Default)]
+pub struct GeoFiles {
+    geosite: Option<Vec<u8>>,
+    geoip: Option<Vec<u8>>,
+}
+
+impl GeoFiles {
+    // geosite分类中的域名，分类名不区分大小写
+    // 支持 分类@属性 只取带有该属性的域名，分类@!属性 排除带有该属性的域名，可以写多个
+    pub fn site(&mut self, reference: &str) -> Result<Vec<SiteDomain>, String> {
+        let mut parts = reference.split('@');
+        let category = parts.next().unwrap_or_default();
+        let filters: Vec<(bool, String)> = parts
+            .map(|attribute| match attribute.strip_prefix('!') {
+                Some(attribute) => (false, attribute.to_lowercase()),
+                None => (true, attribute.to_lowercase()),
+            })
+            .collect();
+        if category.is_empty() || filters.iter().any(|(_, attribute)| attribute.is_empty()) {
+            return Err(format!("geosite分类格式错误: {}", reference));
+        }
+
+        let data = read(&mut self.geosite, GEOSITE_FILE)?;
+        let entry = find_entry(data, category)
+            .map_err(|e| format!("{}: {}", GEOSITE_FILE, e))?
+            .ok_or_else(|| format!("{}中没有分类: {}", GEOSITE_FILE, category))?;
+        let mut domains = Vec::new();
+        for field in Fields::new(entry) {
+            let (number, value) = field.map_err(|e| format!("{}: {}", GEOSITE_FILE, e))?;
+            if number != 2 {
+                continue;
+            }
+            let domain =
+                site_domain(value.bytes()?).map_err(|e| format!("{}: {}", GEOSITE_FILE, e))?;
+            let matched = filters.iter().all(|(include, attribute)| {
+                domain.attributes.iter().any(|a| a == attribute) == *include
+            });
+            if matched {
+                domains.push(domain);
+            }
+        }
+        Ok(domains)
+    }
+
+    // geoip分类中的CIDR，分类名不区分大小写
+    pub fn ip(&mut self, category: &str) -> Result<Vec<String>, String> {
+        let data = read(&mut self.geoip, GEOIP_FILE)?;
+        let entry = find_entry(data, category)
+            .map_err(|e| format!("{}: {}", GEOIP_FILE, e))?
+            .ok_or_else(|| format!("{}中没有分类: {}", GEOIP_FILE, category))?;
+        let mut cidrs = Vec::new();
+        for field in Fields::new(entry) {
+            let (number, value) = field.map_err(|e| format!("{}: {}", GEOIP_FILE, e))?;
+            match number {
+                2 => {
+                    cidrs.push(cidr(value.bytes()?).map_err(|e| format!("{}: {}", GEOIP_FILE, e))?)
+                }
+                3 if value.varint()? != 0 => {
+                    return Err(format!(
+                        "{}: 不支持reverse_match的分类: {}",
+                        GEOIP_FILE, category
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Ok(cidrs)
+    }
+}
+
+// 读取geo数据文件，读取后缓存
+fn read<'a>(cache: &'a mut Option<Vec<u8>>, file: &str) -> Result<&'a [u8], String> {
+    if cache.is_none() {
+        let path = Path::new(GEO_DIR).join(file);
+        let data =
+            std::fs::read(&path).map_err(|e| format!("无法读取{}: {}", path.display(), e))?;
+        *cache = Some(data);
+    }
+    Ok(cache.as_deref().unwrap_or_default())
+}
+
+// 在GeoSiteList/GeoIPList中查找分类，两者的结构相同:
+// 字段1为重复的分类条目，分类条目的字段1为分类名
+fn find_entry<'a>(data: &'a [u8], category: &str) -> Result<Option<&'a [u8]>, String> {
+    for field in Fields::new(data) {
+        let (number, value) = field?;
+        if number != 1 {
+            continue;
+        }
+        let entry = value.bytes()?;
+        for field in Fields::new(entry) {
+            let (number, value) = field?;
+            if number == 1 {
+                if std::str::from_utf8(value.bytes()?)
+                    .is_ok_and(|code| code.eq_ignore_ascii_case(category))
+                {
+                    return Ok(Some(entry));
+                }
+                break;
+            }
+        }
+    }
+    Ok(None)
+}
+
+// Domain: 字段1为类型，字段2为值，字段3为属性（属性的字段1为名称）
+fn site_domain(data: &[u8]) -> Result<SiteDomain, String> {
+    let mut kind = DomainKind::Keyword;
+    let mut value = String::new();
+    let mut attributes = Vec::new();
+    for field in Fields::new(data) {
+        let (number, field) = field?;
+        match number {
+            1 => {
+                kind = match field.varint()? {
+                    0 => DomainKind::Keyword,
+                    1 => DomainKind::Regex,
+                    2 => DomainKind::Suffix,
+                    3 => DomainKind::Full,
+                    other => return Err(format!("未知的域名类型: {}", other)),
+                }
+            }
+            2 => value = text(field.bytes()?)?,
+            3 => {
+                for attribute in Fields::new(field.bytes()?) {
+                    let (number, key) = attribute?;
+                    if number == 1 {
+                        attributes.push(text(key.bytes()?)?.to_lowercase());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(SiteDomain {
+        kind,
+        value,
+        attributes,
+    })
+}
+
+// CIDR: 字段1为4或16字节的地址，字段2为前缀长度
+fn cidr(data: &[u8]) -> Result<String, String> {
+    let mut address = None;
+    let mut prefix = 0;
+    for field in Fields::new(data) {
+        let (number, value) = field?;
+        match number {
+            1 => {
+                address = Some(match value.bytes()? {
+                    &[a, b, c, d] => IpAddr::V4(Ipv4Addr::new(a, b, c, d)),
+                    bytes => IpAddr::V6(Ipv6Addr::from(
+                        <[u8; 16]>::try_from(bytes).map_err(|_| "IP地址长度无效".to_string())?,
+                    )),
+                })
+            }
+            2 => prefix = value.varint()?,
+            _ => {}
+        }
+    }
+    let address = address.ok_or_else(|| "CIDR缺少地址".to_string())?;
+    Ok(format!("{}/{}", address, prefix))
+}
+
+fn text(bytes: &[u8]) -> Result<String, String> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| "字符串不是有效的UTF-8".to_string())
+}
+
+// protobuf字段值，只保留用到的两种类型
+enum FieldValue<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Other,
+}
+
+impl<'a> FieldValue<'a> {
+    fn varint(&self) -> Result<u64, String> {
+        match self {
+            FieldValue::Varint(value) => Ok(*value),
+            _ => Err("字段类型错误，应为整数".to_string()),
+        }
+    }
+
+    fn bytes(&self) -> Result<&'a [u8], String> {
+        match self {
+            FieldValue::Bytes(bytes) => Ok(bytes),
+            _ => Err("字段类型错误，应为字节串".to_string()),
+        }
+    }
+}
+
+// 依次读取protobuf消息中的字段: (字段编号, 值)
+struct Fields<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Fields<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self
+                .data
+                .split_first()
+                .ok_or_else(|| "数据不完整".to_string())?;
+            self.data = rest;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("整数编码无效".to_string())
+    }
+
+    fn take(&mut self, len: u64) -> Result<&'a [u8], String> {
+        let len = usize::try_from(len)
+            .ok()
+            .filter(|len| *len <= self.data.len())
+            .ok_or_else(|| "数据不完整".to_string())?;
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn field(&mut self) -> Result<(u64, FieldValue<'a>), String> {
+        let key = self.varint()?;
+        let value = match key & 7 {
+            0 => FieldValue::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                FieldValue::Other
+            }
+            2 => {
+                let len = self.varint()?;
+                FieldValue::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                FieldValue::Other
+            }
+            wire_type => return Err(format!("不支持的protobuf字段类型: {}", wire_type)),
+        };
+        Ok((key >> 3, value))
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<(u64, FieldValue<'a>), String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let field = self.field();
+        // 出错后不再继续读取
+        if field.is_err() {
+            self.data = &[];
+        }
+        Some(field)
+    }
+}
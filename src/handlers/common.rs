@@ -1,26 +1,106 @@
+use crate::models::{AppConfig, RuleFormat};
 use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
 use chacha20poly1305::{
     ChaCha20Poly1305, Key, Nonce,
     aead::{Aead, KeyInit},
 };
 use futures_util::StreamExt;
-use ntex::web::HttpRequest;
+use ntex::web::{HttpRequest, HttpResponse};
 use ntex_multipart::Multipart;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use tracing::{debug, warn};
+use uuid::Uuid;
 
 // 查询参数结构体
 #[derive(Deserialize)]
 pub struct SubscriptionQuery {
     #[serde(default)]
     pub zstd: bool,
-    pub secret: Option<String>, // 加密的UUID
+    pub secret: Option<String>,  // 加密的UUID
+    pub profile: Option<String>, // 命名模板，需在users.yml的profiles允许列表中
+}
+
+// 规则集查询参数结构体
+#[derive(Deserialize)]
+pub struct RuleProviderQuery {
+    pub secret: Option<String>,     // 加密的UUID
+    pub profile: Option<String>,    // 命名模板，需在users.yml的profiles允许列表中
+    pub format: Option<RuleFormat>, // 输出格式，默认为yaml
+}
+
+// 托管规则集查询参数结构体
+#[derive(Deserialize)]
+pub struct RuleSetQuery {
+    pub format: Option<RuleFormat>, // 输出格式，默认为yaml
 }
 
 // 配置管理查询参数结构体
 #[derive(Deserialize)]
 pub struct ConfigQuery {
     pub auth: Option<String>, // 加密的管理员密码
+    pub file: Option<String>, // 要编辑的文件，默认为clash.yml
+}
+
+// 配置校验查询参数结构体
+#[derive(Deserialize)]
+pub struct ValidateQuery {
+    pub file: Option<String>, // 请求体对应的文件，默认为clash.yml
+}
+
+// 热重载查询参数结构体
+#[derive(Deserialize)]
+pub struct ReloadQuery {
+    pub message: Option<String>, // 修改说明，记录到历史版本
+}
+
+// 历史版本对比查询参数结构体
+#[derive(Deserialize)]
+pub struct HistoryDiffQuery {
+    pub from: String,       // 旧版本ID
+    pub to: Option<String>, // 新版本ID，不提供时与当前配置对比
+}
+
+// 历史版本回滚查询参数结构体
+#[derive(Deserialize)]
+pub struct RollbackQuery {
+    pub id: String,              // 回滚到的版本ID
+    pub message: Option<String>, // 回滚说明
+}
+
+// 节点密码导出查询参数结构体
+#[derive(Deserialize)]
+pub struct CredentialsQuery {
+    pub uuid: Option<String>, // 只导出指定用户，不提供时导出users.yml中的所有用户
+}
+
+// 合并结果查询参数结构体
+#[derive(Deserialize)]
+pub struct MergedQuery {
+    pub uuid: Option<String>,     // 使用该用户的命名模板、套餐和覆盖配置
+    pub profile: Option<String>,  // 命名模板，提供uuid时需在允许列表中
+    pub plan: Option<String>,     // 不提供uuid时使用的套餐
+    pub overlays: Option<String>, // 不提供uuid时使用的覆盖配置，逗号分隔
+}
+
+// 规则模拟查询参数结构体
+#[derive(Deserialize)]
+pub struct SimulateQuery {
+    pub domain: Option<String>,   // 目标域名
+    pub ip: Option<String>,       // 目标IP，同时提供域名时视为域名的解析结果
+    pub port: Option<String>,     // 目标端口
+    pub network: Option<String>,  // tcp或udp，默认tcp
+    pub uuid: Option<String>,     // 使用该用户的命名模板、套餐和覆盖配置
+    pub profile: Option<String>,  // 命名模板，提供uuid时需在允许列表中
+    pub plan: Option<String>,     // 不提供uuid时使用的套餐
+    pub overlays: Option<String>, // 不提供uuid时使用的覆盖配置，逗号分隔
+}
+
+// 节点导入请求体
+#[derive(Deserialize)]
+pub struct ImportRequest {
+    pub links: String,           // 分享链接，每行一个，或Base64编码的链接列表
+    pub content: Option<String>, // 编辑器中的内容，提供时返回追加节点后的内容
 }
 
 // Multipart表单数据结构
@@ -28,6 +108,9 @@ pub struct ConfigQuery {
 pub struct ConfigFormData {
     pub auth_token: String,
     pub config_content: String,
+    pub message: Option<String>,   // 修改说明，记录到历史版本
+    pub base_hash: Option<String>, // 编辑器加载时的内容哈希
+    pub file: Option<String>,      // 编辑的文件，默认为clash.yml
 }
 
 // 解析multipart表单数据的辅助函数
@@ -75,15 +158,21 @@ pub async fn parse_multipart_form(mut multipart: Multipart) -> Result<ConfigForm
         .ok_or("缺少config_content字段")?
         .clone();
 
+    let message = fields.get("message").cloned();
+    let base_hash = fields.get("base_hash").cloned();
+    let file = fields.get("file").cloned();
+
     Ok(ConfigFormData {
         auth_token,
         config_content,
+        message,
+        base_hash,
+        file,
     })
 }
 
-// ChaCha20Poly1305解密函数
-pub fn decrypt_secret(encrypted_secret: &str, key_base64: &str) -> Result<String, String> {
-    // 1. 解码Base64编码的密钥
+// 解码Base64编码的32字节密钥
+pub fn decode_key(key_base64: &str) -> Result<Vec<u8>, String> {
     let key_bytes = BASE64
         .decode(key_base64)
         .map_err(|e| format!("密钥Base64解码失败: {}", e))?;
@@ -95,6 +184,14 @@ pub fn decrypt_secret(encrypted_secret: &str, key_base64: &str) -> Result<String
         ));
     }
 
+    Ok(key_bytes)
+}
+
+// ChaCha20Poly1305解密函数
+pub fn decrypt_secret(encrypted_secret: &str, key_base64: &str) -> Result<String, String> {
+    // 1. 解码Base64编码的密钥
+    let key_bytes = decode_key(key_base64)?;
+
     let key = Key::from_slice(&key_bytes);
     let cipher = ChaCha20Poly1305::new(key);
 
@@ -131,22 +228,98 @@ pub fn decrypt_secret(encrypted_secret: &str, key_base64: &str) -> Result<String
     String::from_utf8(plaintext).map_err(|e| format!("解密结果不是有效的UTF-8字符串: {}", e))
 }
 
+// 解密订阅请求的secret得到用户UUID，失败时记录日志
+pub fn subscriber_uuid(
+    secret: Option<&str>,
+    app_config: &AppConfig,
+    client_ip: &str,
+) -> Option<Uuid> {
+    // 检查是否提供了secret参数
+    let Some(encrypted_secret) = secret else {
+        debug!("[{}] 缺少secret参数，访问被禁止", client_ip);
+        return None;
+    };
+
+    // 解密secret获得uuid
+    let uuid_str = match decrypt_secret(encrypted_secret, &app_config.encryption_key) {
+        Ok(decrypted) => {
+            debug!("[{}] 成功解密secret获得uuid: {}", client_ip, decrypted);
+            decrypted
+        }
+        Err(e) => {
+            warn!("[{}] secret解密失败，访问被禁止: {}", client_ip, e);
+            return None;
+        }
+    };
+
+    // 验证解密后的UUID格式
+    match Uuid::parse_str(&uuid_str) {
+        Ok(uuid) => Some(uuid),
+        Err(_) => {
+            warn!(
+                "[{}] 解密后的数据不是有效UUID，访问被禁止: {}",
+                client_ip, uuid_str
+            );
+            None
+        }
+    }
+}
+
+// 从Authorization头中提取Bearer token
+pub fn extract_bearer_token(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get("Authorization")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|auth_str| auth_str.strip_prefix("Bearer "))
+        .map(|token| token.to_string())
+}
+
+// 验证加密的管理员token，失败时返回原因
+pub fn verify_admin_token(token: &str, app_config: &AppConfig) -> Result<(), String> {
+    let decrypted_password = decrypt_secret(token, &app_config.encryption_key)?;
+    if decrypted_password != app_config.admin_password {
+        return Err("与管理员密码不符".to_string());
+    }
+    Ok(())
+}
+
+// 校验管理接口请求的Bearer token，失败时记录日志
+pub fn authorize_admin(req: &HttpRequest, app_config: &AppConfig, client_ip: &str) -> bool {
+    let token = match extract_bearer_token(req) {
+        Some(token) => token,
+        None => {
+            warn!(
+                "[{}] {} 缺少Bearer token，访问被禁止",
+                client_ip,
+                req.path()
+            );
+            return false;
+        }
+    };
+
+    if let Err(e) = verify_admin_token(&token, app_config) {
+        warn!("[{}] {} 管理员验证失败: {}", client_ip, req.path(), e);
+        return false;
+    }
+
+    true
+}
+
 // 获取客户端真实IP地址
 pub fn get_client_ip(req: &HttpRequest) -> String {
     // 优先检查代理头部 (X-Forwarded-For, X-Real-IP等)
-    if let Some(xff) = req.headers().get("X-Forwarded-For") {
-        if let Ok(xff_str) = xff.to_str() {
-            // X-Forwarded-For 可能包含多个IP，取第一个
-            if let Some(first_ip) = xff_str.split(',').next() {
-                return first_ip.trim().to_string();
-            }
-        }
+    if let Some(xff) = req.headers().get("X-Forwarded-For")
+        && let Ok(xff_str) = xff.to_str()
+        // X-Forwarded-For 可能包含多个IP，取第一个
+        && let Some(first_ip) = xff_str.split(',').next()
+    {
+        return first_ip.trim().to_string();
     }
 
-    if let Some(real_ip) = req.headers().get("X-Real-IP") {
-        if let Ok(real_ip_str) = real_ip.to_str() {
-            return real_ip_str.to_string();
-        }
+    if let Some(real_ip) = req.headers().get("X-Real-IP")
+        && let Ok(real_ip_str) = real_ip.to_str()
+    {
+        return real_ip_str.to_string();
     }
 
     // 如果没有代理头部，使用连接信息
@@ -157,6 +330,19 @@ pub fn get_client_ip(req: &HttpRequest) -> String {
     "未知".to_string()
 }
 
+// 构造JSON响应
+pub fn json_response<T: Serialize>(value: &T) -> HttpResponse {
+    match serde_json::to_string(value) {
+        Ok(json) => HttpResponse::Ok()
+            .content_type("application/json; charset=utf-8")
+            .header("Cache-Control", "no-cache")
+            .body(json),
+        Err(e) => HttpResponse::InternalServerError()
+            .content_type("text/plain; charset=utf-8")
+            .body(format!("JSON序列化失败: {}", e)),
+    }
+}
+
 // HTML转义函数
 pub fn html_escape(text: &str) -> String {
     text.replace('&', "&amp;")
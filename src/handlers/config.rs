@@ -1,11 +1,24 @@
 use crate::handlers::common::{
-    ConfigQuery, decrypt_secret, get_client_ip, html_escape, parse_multipart_form,
+    ConfigQuery, ReloadQuery, ValidateQuery, authorize_admin, decrypt_secret, get_client_ip,
+    html_escape, json_response, parse_multipart_form,
 };
+use crate::history;
+use crate::include::{self, INCLUDE_DIR, MAIN_FILE};
 use crate::models::AppState;
+use crate::reload::{
+    CLASH_CONFIG_PATH, ReloadOutcome, log_app_config_changes, publish, reload_app_config,
+    reload_from_disk,
+};
+use crate::ruleset::{self, EDITOR_PREFIX, NEW_RULE_SET, RULESETS_DIR};
+use crate::snapshot::Snapshot;
+use crate::storage::{content_hash, parse_etag, write_atomic};
+use crate::validation::{validate_content, validate_files, validate_live, validate_rule_set};
 use ntex_multipart::Multipart;
 
+use ntex::util::Bytes;
 use ntex::web::types::{Query, State};
 use ntex::web::{HttpRequest, HttpResponse, Responder};
+use std::path::Path;
 use tracing::{debug, error, info, warn};
 
 // 配置编辑页面处理函数 (GET)
@@ -15,6 +28,7 @@ pub async fn handle_config_get(
     state: State<AppState>,
 ) -> impl Responder {
     let client_ip = get_client_ip(&req);
+    let app_config = state.app_config.load_full();
 
     // 检查是否提供了auth参数
     let encrypted_auth = match &query.auth {
@@ -26,8 +40,7 @@ pub async fn handle_config_get(
     };
 
     // 解密auth获得管理员密码
-    let decrypted_password = match decrypt_secret(&encrypted_auth, &state.app_config.encryption_key)
-    {
+    let decrypted_password = match decrypt_secret(encrypted_auth, &app_config.encryption_key) {
         Ok(decrypted) => {
             debug!("[{}] /config 成功解密auth", client_ip);
             decrypted
@@ -39,24 +52,82 @@ pub async fn handle_config_get(
     };
 
     // 验证解密后的密码是否与admin_password匹配
-    if decrypted_password != state.app_config.admin_password {
+    if decrypted_password != app_config.admin_password {
         warn!("[{}] /config 管理员密码验证失败，访问被禁止", client_ip);
         return HttpResponse::NoContent().finish();
     }
 
     info!("[{}] 📝 管理员访问配置编辑页面", client_ip);
 
+    // 确定要编辑的文件，只能是clash.yml、它引用的文件或托管规则集
+    let rule_sets: Vec<String> = state
+        .snapshot
+        .load()
+        .hosted_rule_sets
+        .keys()
+        .map(|name| format!("{}{}.yml", EDITOR_PREFIX, name))
+        .collect();
+    let (file, files) = match editable_file(query.file.as_deref(), rule_sets).await {
+        Ok(editable) => editable,
+        Err(e) => {
+            warn!("[{}] /config 无法打开文件: {}", client_ip, e);
+            return HttpResponse::BadRequest()
+                .content_type("text/html; charset=utf-8")
+                .body(format!("<h1>错误</h1><p>{}</p>", html_escape(&e)));
+        }
+    };
+
     // 读取当前配置文件内容
-    let config_content = match tokio::fs::read_to_string("config/clash.yml").await {
+    let config_path = Path::new(INCLUDE_DIR).join(&file);
+    let config_content = match tokio::fs::read_to_string(&config_path).await {
         Ok(content) => content,
+        // 打开尚不存在的规则集时新建
+        Err(e)
+            if e.kind() == std::io::ErrorKind::NotFound
+                && ruleset::editor_name(&file).is_some() =>
+        {
+            NEW_RULE_SET.to_string()
+        }
         Err(e) => {
-            error!("[{}] 读取配置文件失败: {}", client_ip, e);
+            error!("[{}] 读取配置文件{}失败: {}", client_ip, file, e);
             return HttpResponse::InternalServerError()
                 .content_type("text/html; charset=utf-8")
                 .body("<h1>错误</h1><p>无法读取配置文件</p>");
         }
     };
 
+    // 文件切换链接: clash.yml、引用的文件和托管规则集，新建的规则集保存前也显示在列表中
+    let mut names: Vec<&str> = std::iter::once(MAIN_FILE)
+        .chain(files.iter().map(String::as_str))
+        .collect();
+    if !names.contains(&file.as_str()) {
+        names.push(&file);
+    }
+    let links = names
+        .into_iter()
+        .map(|name| {
+            format!(
+                r#"<a href="/config?auth={}&file={}"{}>{}</a>"#,
+                encrypted_auth,
+                html_escape(name),
+                if name == file {
+                    r#" class="current""#
+                } else {
+                    ""
+                },
+                html_escape(name)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("");
+    let file_links = format!(
+        r#"<div class="files">📂 配置文件: {}<a href="javascript:newRuleSet()">➕ 新建规则集</a></div>"#,
+        links
+    );
+
+    // 内容哈希作为ETag，保存时用于检测并发修改
+    let config_hash = content_hash(&config_content);
+
     // 生成HTML编辑页面
     let html_content = format!(
         r#"<!DOCTYPE html>
@@ -130,6 +201,22 @@ pub async fn handle_config_get(
         .reset-btn:hover {{
             background-color: #545b62;
         }}
+        .files {{
+            margin-bottom: 20px;
+        }}
+        .files a {{
+            display: inline-block;
+            margin: 0 6px 6px 0;
+            padding: 4px 10px;
+            border: 1px solid #bee5eb;
+            border-radius: 4px;
+            color: #007bff;
+            text-decoration: none;
+        }}
+        .files a.current {{
+            background-color: #007bff;
+            color: white;
+        }}
         .info {{
             background-color: #e7f3ff;
             border: 1px solid #bee5eb;
@@ -144,6 +231,134 @@ pub async fn handle_config_get(
             padding: 15px;
             margin-bottom: 20px;
         }}
+        .editor {{
+            display: flex;
+            height: 98vh;
+            border: 1px solid #ddd;
+            border-radius: 4px;
+            overflow: hidden;
+        }}
+        .editor textarea {{
+            height: 100%;
+            border: none;
+            border-radius: 0;
+            resize: none;
+            white-space: pre;
+            overflow: auto;
+        }}
+        .gutter {{
+            width: 64px;
+            flex-shrink: 0;
+            overflow: hidden;
+            padding: 15px 0;
+            box-sizing: border-box;
+            background-color: #f8f9fa;
+            border-right: 1px solid #ddd;
+            font-family: 'Courier New', monospace;
+            font-size: 14px;
+            line-height: 1.5;
+            text-align: right;
+            color: #999;
+            user-select: none;
+        }}
+        .gutter div {{
+            height: 21px;
+            padding-right: 8px;
+        }}
+        .gutter .error {{
+            background-color: #f8d7da;
+            color: #721c24;
+            font-weight: bold;
+            cursor: help;
+        }}
+        .gutter .warning {{
+            background-color: #fff3cd;
+            color: #856404;
+            border: none;
+            border-radius: 0;
+            padding: 0 8px 0 0;
+            margin: 0;
+            cursor: help;
+        }}
+        .validation {{
+            margin-top: 10px;
+            padding: 8px 12px;
+            border-radius: 4px;
+            background-color: #e9ecef;
+            color: #555;
+        }}
+        .validation.valid {{
+            background-color: #d4edda;
+            color: #155724;
+        }}
+        .validation.invalid {{
+            background-color: #f8d7da;
+            color: #721c24;
+        }}
+        .diagnostics {{
+            list-style: none;
+            padding: 0;
+            margin: 10px 0 0 0;
+            font-family: 'Courier New', monospace;
+            font-size: 13px;
+        }}
+        .diagnostics li {{
+            padding: 4px 8px;
+            cursor: pointer;
+            border-left: 4px solid transparent;
+        }}
+        .diagnostics li:hover {{
+            background-color: #f1f3f5;
+        }}
+        .diagnostics li.error {{
+            border-left-color: #dc3545;
+        }}
+        .diagnostics li.warning {{
+            border-left-color: #ffc107;
+        }}
+        .message-input {{
+            width: 100%;
+            padding: 10px;
+            border: 1px solid #ddd;
+            border-radius: 4px;
+            box-sizing: border-box;
+            font-size: 14px;
+        }}
+        .history {{
+            margin-top: 40px;
+        }}
+        .history table {{
+            width: 100%;
+            border-collapse: collapse;
+            font-size: 14px;
+        }}
+        .history th, .history td {{
+            padding: 8px;
+            border-bottom: 1px solid #eee;
+            text-align: left;
+        }}
+        .history td button {{
+            padding: 4px 10px;
+            font-size: 13px;
+            margin: 0 4px 0 0;
+        }}
+        .diff {{
+            background-color: #f8f9fa;
+            border: 1px solid #ddd;
+            border-radius: 4px;
+            padding: 15px;
+            max-height: 500px;
+            overflow: auto;
+            font-size: 13px;
+        }}
+        .diff .add {{
+            color: #155724;
+            background-color: #e6ffed;
+        }}
+        .diff .del {{
+            color: #721c24;
+            background-color: #ffeef0;
+        }}
     </style>
 </head>
 <body>
@@ -154,24 +369,464 @@ pub async fn handle_config_get(
             <strong>📋 使用说明：</strong>
             <ul>
                 <li>保存后会自动重载配置到内存</li>
-                <li>请确保YAML格式正确，否则可能导致服务异常</li>
+                <li>编辑时会自动校验配置，错误和警告（包括重复、被覆盖和MATCH之后的规则）会标注在对应行号上</li>
+                <li>覆盖配置位于 config/overlays/ 目录，由 users.yml 中的套餐和用户选择</li>
+                <li>clash.yml 可以通过 !include 或 includes 引用其他文件，保存单个文件时会校验整体配置</li>
+                <li>可以粘贴分享链接导入节点，追加到编辑器后检查并保存</li>
+                <li>规则集位于 config/rulesets/ 目录，规则中通过 RULE-SET,名称,策略 引用，会自动生成对应的 rule-providers</li>
+                <li>规则模拟可以查看域名或IP按当前配置命中的规则和代理组</li>
             </ul>
         </div>
-        
+        {}
         <form method="POST" action="/config" id="configForm" enctype="multipart/form-data">
             <input type="hidden" name="auth_token" value="{}">
+            <input type="hidden" name="base_hash" id="base_hash" value="{}">
+            <input type="hidden" name="file" id="config_file" value="{}">
             <div class="form-group">
-                <label for="config_content">配置内容 (config/clash.yml)：</label>
-                <textarea name="config_content" id="config_content" required style="height: 98vh">{}</textarea>
+                <label for="config_content">配置内容 (config/{})：</label>
+                <div class="editor">
+                    <div class="gutter" id="gutter"></div>
+                    <textarea name="config_content" id="config_content" required wrap="off" spellcheck="false">{}</textarea>
+                </div>
+                <div class="validation" id="validation_status">⏳ 正在校验...</div>
+                <ul class="diagnostics" id="diagnostics"></ul>
+            </div>
+            <div class="form-group">
+                <label for="message">修改说明 (可选，记录到历史版本)：</label>
+                <input type="text" name="message" id="message" class="message-input" placeholder="例如：新增香港节点">
             </div>
             <div class="button-group">
                 <button type="submit">💾 保存配置</button>
                 <button type="button" class="reset-btn" onclick="location.reload()">🔄 重置</button>
             </div>
         </form>
+
+        <div class="history">
+            <h2>📥 导入节点</h2>
+            <div class="form-group">
+                <label for="import_links">分享链接 (vless、vmess、trojan、ss、hysteria2、tuic，每行一个，或Base64编码的链接列表)：</label>
+                <textarea id="import_links" class="message-input" rows="5" wrap="off" spellcheck="false"></textarea>
+            </div>
+            <div class="button-group">
+                <button type="button" onclick="previewImport()">👀 预览</button>
+                <button type="button" id="import_apply" onclick="applyImport()" disabled>➕ 追加到编辑器</button>
+            </div>
+            <pre class="diff" id="import_result" style="display: none"></pre>
+        </div>
+
+        <div class="history">
+            <h2>🕘 历史版本</h2>
+            <table>
+                <thead>
+                    <tr><th>版本</th><th>时间</th><th>操作</th><th>来源IP</th><th>说明</th><th>大小</th><th></th></tr>
+                </thead>
+                <tbody id="history_rows">
+                    <tr><td colspan="7">⏳ 正在加载...</td></tr>
+                </tbody>
+            </table>
+            <pre class="diff" id="history_diff" style="display: none"></pre>
+        </div>
+
+        <div class="history">
+            <h2>🧩 覆盖配置</h2>
+            <table>
+                <thead>
+                    <tr><th>名称 (config/overlays/)</th><th>使用者</th><th></th></tr>
+                </thead>
+                <tbody id="overlay_rows">
+                    <tr><td colspan="3">⏳ 正在加载...</td></tr>
+                </tbody>
+            </table>
+            <div class="form-group" style="margin-top: 15px">
+                <label for="merged_uuid">查看用户实际得到的配置 (合并覆盖配置并按套餐过滤)：</label>
+                <input type="text" id="merged_uuid" class="message-input" placeholder="用户UUID">
+            </div>
+            <div class="button-group">
+                <button type="button" onclick="showMerged({{ uuid: document.getElementById('merged_uuid').value.trim() }})">🔍 查看合并结果</button>
+            </div>
+            <pre class="diff" id="merged_result" style="display: none"></pre>
+        </div>
+
+        <div class="history">
+            <h2>🧭 规则模拟</h2>
+            <div class="form-group">
+                <label for="simulate_domain">域名和IP至少填写一个，同时填写时IP视为域名的解析结果：</label>
+                <input type="text" id="simulate_domain" class="message-input" placeholder="域名，例如 www.google.com">
+                <input type="text" id="simulate_ip" class="message-input" placeholder="IP，例如 8.8.8.8">
+                <input type="text" id="simulate_port" class="message-input" placeholder="端口，例如 443">
+                <select id="simulate_network" class="message-input">
+                    <option value="tcp">TCP</option>
+                    <option value="udp">UDP</option>
+                </select>
+                <input type="text" id="simulate_uuid" class="message-input" placeholder="用户UUID (可选，使用该用户实际得到的配置)">
+            </div>
+            <div class="button-group">
+                <button type="button" onclick="simulateRule()">🧭 模拟</button>
+            </div>
+            <pre class="diff" id="simulate_result" style="display: none"></pre>
+        </div>
     </div>
 
     <script>
+        const editor = document.getElementById('config_content');
+        const gutter = document.getElementById('gutter');
+        const statusBox = document.getElementById('validation_status');
+        const diagnosticsList = document.getElementById('diagnostics');
+        const authToken = document.querySelector('input[name="auth_token"]').value;
+        const configFile = document.getElementById('config_file').value;
+        const LINE_HEIGHT = 21;
+        let markers = {{}};
+        let validateTimer = null;
+        let lastLineCount = 0;
+
+        function escapeHtml(text) {{
+            return text.replace(/&/g, '&amp;').replace(/</g, '&lt;').replace(/>/g, '&gt;').replace(/"/g, '&quot;');
+        }}
+
+        // 渲染行号和诊断标记
+        function renderGutter() {{
+            const lineCount = editor.value.split('\n').length;
+            let html = '';
+            for (let i = 1; i <= lineCount; i++) {{
+                const marker = markers[i];
+                if (marker) {{
+                    const icon = marker.severity === 'error' ? '✖' : '⚠';
+                    html += `<div class="${{marker.severity}}" title="${{escapeHtml(marker.messages.join('\n'))}}">${{icon}} ${{i}}</div>`;
+                }} else {{
+                    html += `<div>${{i}}</div>`;
+                }}
+            }}
+            gutter.innerHTML = html;
+            gutter.scrollTop = editor.scrollTop;
+            lastLineCount = lineCount;
+        }}
+
+        // 跳转到指定行并选中
+        function jumpToLine(line) {{
+            const lines = editor.value.split('\n');
+            let start = 0;
+            for (let i = 0; i < line - 1 && i < lines.length; i++) {{
+                start += lines[i].length + 1;
+            }}
+            const end = start + (lines[line - 1] || '').length;
+            editor.focus();
+            editor.setSelectionRange(start, end);
+            editor.scrollTop = Math.max(0, (line - 1) * LINE_HEIGHT - editor.clientHeight / 2);
+        }}
+
+        function showReport(report) {{
+            markers = {{}};
+            diagnosticsList.innerHTML = '';
+            report.diagnostics.forEach(d => {{
+                if (d.line) {{
+                    const marker = markers[d.line] || {{ severity: d.severity, messages: [] }};
+                    if (d.severity === 'error') {{
+                        marker.severity = 'error';
+                    }}
+                    marker.messages.push(d.message);
+                    markers[d.line] = marker;
+                }}
+                const item = document.createElement('li');
+                item.className = d.severity;
+                const location = d.line ? `第${{d.line}}行${{d.column ? ' 第' + d.column + '列' : ''}}: ` : '';
+                item.textContent = `${{d.severity === 'error' ? '✖' : '⚠'}} ${{location}}${{d.message}}`;
+                if (d.line) {{
+                    item.addEventListener('click', () => jumpToLine(d.line));
+                }}
+                diagnosticsList.appendChild(item);
+            }});
+            if (report.valid) {{
+                statusBox.className = 'validation valid';
+                statusBox.textContent = report.warnings > 0
+                    ? `✅ 配置有效，${{report.warnings}} 个警告`
+                    : '✅ 配置有效';
+            }} else {{
+                statusBox.className = 'validation invalid';
+                statusBox.textContent = `❌ ${{report.errors}} 个错误，${{report.warnings}} 个警告`;
+            }}
+            renderGutter();
+        }}
+
+        // 调用校验接口
+        function validateConfig() {{
+            statusBox.className = 'validation';
+            statusBox.textContent = '⏳ 正在校验...';
+            return fetch(`/config/validate?file=${{encodeURIComponent(configFile)}}`, {{
+                method: 'POST',
+                headers: {{
+                    'Authorization': `Bearer ${{authToken}}`,
+                    'Content-Type': 'text/plain; charset=utf-8'
+                }},
+                body: editor.value
+            }})
+            .then(response => {{
+                if (!response.ok) {{
+                    throw new Error(`HTTP ${{response.status}}`);
+                }}
+                return response.json();
+            }})
+            .then(showReport)
+            .catch(error => {{
+                statusBox.className = 'validation invalid';
+                statusBox.textContent = '⚠️ 校验请求失败: ' + error.message;
+            }});
+        }}
+
+        editor.addEventListener('input', function() {{
+            if (editor.value.split('\n').length !== lastLineCount) {{
+                renderGutter();
+            }}
+            clearTimeout(validateTimer);
+            validateTimer = setTimeout(validateConfig, 500);
+        }});
+        editor.addEventListener('scroll', function() {{
+            gutter.scrollTop = editor.scrollTop;
+        }});
+
+        renderGutter();
+        validateConfig();
+
+        const historyRows = document.getElementById('history_rows');
+        const historyDiff = document.getElementById('history_diff');
+        const ACTION_NAMES = {{ startup: '启动', save: '保存', reload: '重载', rollback: '回滚' }};
+
+        // 加载历史版本列表
+        function loadHistory() {{
+            fetch('/config/history', {{ headers: {{ 'Authorization': `Bearer ${{authToken}}` }} }})
+            .then(response => {{
+                if (!response.ok) {{
+                    throw new Error(`HTTP ${{response.status}}`);
+                }}
+                return response.json();
+            }})
+            .then(versions => {{
+                if (versions.length === 0) {{
+                    historyRows.innerHTML = '<tr><td colspan="7">暂无历史版本</td></tr>';
+                    return;
+                }}
+                historyRows.innerHTML = versions.map((v, i) => {{
+                    const previous = versions[i + 1];
+                    return `<tr>
+                        <td>${{escapeHtml(v.id)}}</td>
+                        <td>${{escapeHtml(v.timestamp)}}</td>
+                        <td>${{ACTION_NAMES[v.action] || escapeHtml(v.action)}}</td>
+                        <td>${{escapeHtml(v.client_ip)}}</td>
+                        <td>${{escapeHtml(v.message || '')}}</td>
+                        <td title="${{escapeHtml((v.files || []).join('\n'))}}">${{v.size}} byte${{v.files ? ` + ${{v.files.length}} 个引用文件` : ''}}</td>
+                        <td>
+                            <button type="button" onclick="showDiff('${{v.id}}', 'current')">与当前对比</button>
+                            ${{previous ? `<button type="button" onclick="showDiff('${{previous.id}}', '${{v.id}}')">与上一版本对比</button>` : ''}}
+                            <button type="button" class="reset-btn" onclick="rollback('${{v.id}}')">回滚</button>
+                        </td>
+                    </tr>`;
+                }}).join('');
+            }})
+            .catch(error => {{
+                historyRows.innerHTML = `<tr><td colspan="7">⚠️ 加载失败: ${{escapeHtml(error.message)}}</td></tr>`;
+            }});
+        }}
+
+        // 显示两个版本之间的diff
+        function showDiff(from, to) {{
+            fetch(`/config/history/diff?from=${{encodeURIComponent(from)}}&to=${{encodeURIComponent(to)}}`, {{
+                headers: {{ 'Authorization': `Bearer ${{authToken}}` }}
+            }})
+            .then(response => response.text())
+            .then(diff => {{
+                historyDiff.style.display = 'block';
+                if (!diff) {{
+                    historyDiff.textContent = `${{from}} 与 ${{to}} 内容相同`;
+                    return;
+                }}
+                historyDiff.innerHTML = diff.split('\n').map(line => {{
+                    const cls = line.startsWith('+') ? 'add' : (line.startsWith('-') ? 'del' : '');
+                    return `<span class="${{cls}}">${{escapeHtml(line)}}</span>`;
+                }}).join('\n');
+                historyDiff.scrollIntoView({{ behavior: 'smooth' }});
+            }});
+        }}
+
+        // 回滚到指定版本
+        function rollback(id) {{
+            if (!confirm(`确定要回滚到版本 ${{id}} 吗？当前配置会被替换并立即生效。`)) {{
+                return;
+            }}
+            fetch(`/config/history/rollback?id=${{encodeURIComponent(id)}}`, {{
+                method: 'POST',
+                headers: {{ 'Authorization': `Bearer ${{authToken}}` }}
+            }})
+            .then(response => {{
+                if (!response.ok) {{
+                    return response.text().then(text => {{
+                        throw new Error(`HTTP ${{response.status}}: ${{text}}`);
+                    }});
+                }}
+                alert(`已回滚到版本 ${{id}}`);
+                location.reload();
+            }})
+            .catch(error => alert('回滚失败:\n\n' + error.message));
+        }}
+
+        loadHistory();
+
+        const overlayRows = document.getElementById('overlay_rows');
+        const mergedResult = document.getElementById('merged_result');
+
+        // 加载覆盖配置列表
+        function loadOverlays() {{
+            fetch('/config/overlays', {{ headers: {{ 'Authorization': `Bearer ${{authToken}}` }} }})
+            .then(response => {{
+                if (!response.ok) {{
+                    throw new Error(`HTTP ${{response.status}}`);
+                }}
+                return response.json();
+            }})
+            .then(overlays => {{
+                if (overlays.length === 0) {{
+                    overlayRows.innerHTML = '<tr><td colspan="3">暂无覆盖配置</td></tr>';
+                    return;
+                }}
+                overlayRows.innerHTML = overlays.map(o => `<tr>
+                    <td>${{escapeHtml(o.name)}}</td>
+                    <td>${{escapeHtml(o.used_by.join(', ') || '未使用')}}</td>
+                    <td><button type="button" onclick="showMerged({{ overlays: '${{encodeURIComponent(o.name)}}' }})">查看合并结果</button></td>
+                </tr>`).join('');
+            }})
+            .catch(error => {{
+                overlayRows.innerHTML = `<tr><td colspan="3">⚠️ 加载失败: ${{escapeHtml(error.message)}}</td></tr>`;
+            }});
+        }}
+
+        // 显示合并结果，params为 {{ uuid }} 或 {{ overlays }}
+        function showMerged(params) {{
+            const query = params.uuid
+                ? `uuid=${{encodeURIComponent(params.uuid)}}`
+                : `overlays=${{params.overlays}}`;
+            fetch(`/config/merged?${{query}}`, {{
+                headers: {{ 'Authorization': `Bearer ${{authToken}}` }}
+            }})
+            .then(response => response.text().then(text => {{
+                mergedResult.style.display = 'block';
+                mergedResult.textContent = response.ok ? text : `⚠️ ${{text}}`;
+                mergedResult.scrollIntoView({{ behavior: 'smooth' }});
+            }}));
+        }}
+
+        loadOverlays();
+
+        const simulateResult = document.getElementById('simulate_result');
+
+        // 模拟连接命中的规则和代理组链
+        function simulateRule() {{
+            const params = new URLSearchParams();
+            for (const name of ['domain', 'ip', 'port', 'network', 'uuid']) {{
+                const value = document.getElementById(`simulate_${{name}}`).value.trim();
+                if (value) {{
+                    params.set(name, value);
+                }}
+            }}
+            fetch(`/config/simulate?${{params}}`, {{
+                headers: {{ 'Authorization': `Bearer ${{authToken}}` }}
+            }})
+            .then(response => response.ok
+                ? response.json().then(showSimulation)
+                : response.text().then(text => {{ throw new Error(text || `HTTP ${{response.status}}`); }}))
+            .catch(error => {{
+                simulateResult.style.display = 'block';
+                simulateResult.textContent = `⚠️ ${{error.message}}`;
+            }});
+        }}
+
+        function describeRule(r) {{
+            const position = r.source === 'rules' ? `第${{r.index}}条` : `子规则 ${{r.source}} 第${{r.index}}条`;
+            return `${{position}} ${{r.rule}}${{r.detail ? ` (${{r.detail}})` : ''}}`;
+        }}
+
+        function showSimulation(result) {{
+            const lines = [];
+            if (result.matched.length === 0) {{
+                lines.push('❌ 没有命中任何规则');
+            }} else {{
+                lines.push('✅ 命中:');
+                result.matched.forEach(r => lines.push(`   ${{describeRule(r)}}`));
+                lines.push('', `➡️ 策略: ${{result.target}}`);
+                result.chain.forEach(step => {{
+                    const members = step.members ? ` [${{step.members.join(', ')}}]` : '';
+                    const note = step.note ? ` - ${{step.note}}` : '';
+                    lines.push(`   ${{step.name}} (${{step.kind}})${{members}}${{note}}`);
+                }});
+            }}
+            if (result.undetermined.length > 0) {{
+                lines.push('', '⚠️ 之前无法在服务端判断的规则，实际可能先命中:');
+                result.undetermined.forEach(r => lines.push(`   ${{describeRule(r)}}`));
+            }}
+            if (result.shadowed.length > 0) {{
+                lines.push('', '🔇 同样会命中但被遮蔽的规则:');
+                result.shadowed.forEach(r => lines.push(`   ${{describeRule(r)}}`));
+            }}
+            simulateResult.style.display = 'block';
+            simulateResult.textContent = lines.join('\n');
+            simulateResult.scrollIntoView({{ behavior: 'smooth' }});
+        }}
+
+        const importResult = document.getElementById('import_result');
+        const importApply = document.getElementById('import_apply');
+        let importedContent = null;
+
+        // 解析分享链接，预览生成的节点和追加节点后的编辑器内容
+        function previewImport() {{
+            importedContent = null;
+            importApply.disabled = true;
+            fetch('/config/import', {{
+                method: 'POST',
+                headers: {{
+                    'Authorization': `Bearer ${{authToken}}`,
+                    'Content-Type': 'application/json'
+                }},
+                body: JSON.stringify({{
+                    links: document.getElementById('import_links').value,
+                    content: editor.value
+                }})
+            }})
+            .then(response => {{
+                if (!response.ok) {{
+                    return response.text().then(text => {{ throw new Error(text || `HTTP ${{response.status}}`); }});
+                }}
+                return response.json();
+            }})
+            .then(result => {{
+                let text = `✅ 解析出 ${{result.count}} 个节点\n\n${{result.proxies}}`;
+                if (result.skipped.length > 0) {{
+                    text += `\n⚠️ 无法解析的链接:\n${{result.skipped.join('\n')}}\n`;
+                }}
+                if (result.error) {{
+                    text += `\n⚠️ 无法追加到编辑器: ${{result.error}}\n`;
+                }}
+                importResult.style.display = 'block';
+                importResult.textContent = text;
+                importedContent = result.content;
+                importApply.disabled = !importedContent;
+            }})
+            .catch(error => {{
+                importResult.style.display = 'block';
+                importResult.textContent = `⚠️ ${{error.message}}`;
+            }});
+        }}
+
+        // 将节点追加到编辑器，保存时与手动编辑一样经过校验
+        function applyImport() {{
+            if (!importedContent) {{
+                return;
+            }}
+            editor.value = importedContent;
+            importedContent = null;
+            importApply.disabled = true;
+            renderGutter();
+            validateConfig();
+            editor.scrollIntoView({{ behavior: 'smooth' }});
+            alert('节点已追加到编辑器，请检查后点击保存配置');
+        }}
+
         // 简单的保存确认和Authorization Bearer处理
         document.querySelector('form').addEventListener('submit', function(e) {{
             if (!confirm('确定要保存配置吗？这将重启配置服务。')) {{
@@ -181,18 +836,26 @@ pub async fn handle_config_get(
             
             // 使用fetch API发送带有Authorization头的请求
             e.preventDefault();
-            
-            const formData = new FormData(this);
-            const authToken = formData.get('auth_token');
-            
+            saveConfig(this);
+        }});
+
+        // 保存配置，携带加载时的内容哈希用于并发冲突检测
+        function saveConfig(form) {{
+            const formData = new FormData(form);
+            const baseHash = document.getElementById('base_hash').value;
+
             fetch('/config', {{
                 method: 'POST',
                 headers: {{
-                    'Authorization': `Bearer ${{authToken}}`
+                    'Authorization': `Bearer ${{authToken}}`,
+                    'If-Match': `"${{baseHash}}"`
                 }},
                 body: formData
             }})
             .then(response => {{
+                if (response.status === 409) {{
+                    return response.text().then(diff => handleConflict(form, diff, response.headers.get('ETag')));
+                }}
                 if (!response.ok) {{
                     return response.text().then(text => {{
                         throw new Error(`HTTP ${{response.status}}: ${{text}}`);
@@ -201,16 +864,49 @@ pub async fn handle_config_get(
                 return response.text();
             }})
             .then(html => {{
+                if (html === undefined) {{
+                    return;
+                }}
                 document.open();
                 document.write(html);
                 document.close();
             }})
             .catch(error => {{
                 console.error('保存配置失败:', error);
-                alert('保存配置时发生错误:\\n\\n' + error.message + '\\n\\n请查看控制台获取更多信息');
+                validateConfig();
+                alert('保存配置时发生错误:\\n\\n' + error.message + '\\n\\n错误位置已在编辑器中标注');
             }});
-        }});
+        }}
+
+        // 配置在加载后被他人修改，展示期间的变化并询问是否覆盖
+        function handleConflict(form, diff, etag) {{
+            historyDiff.style.display = 'block';
+            historyDiff.innerHTML = '<strong>⚠️ 加载之后配置已被修改，以下是期间发生的变化：</strong>\n\n' +
+                diff.split('\n').map(line => {{
+                    const cls = line.startsWith('+') ? 'add' : (line.startsWith('-') ? 'del' : '');
+                    return `<span class="${{cls}}">${{escapeHtml(line)}}</span>`;
+                }}).join('\n');
+            historyDiff.scrollIntoView({{ behavior: 'smooth' }});
+            loadHistory();
+            if (etag && confirm('配置已被其他管理员修改，变化已显示在页面下方。\n\n是否仍要用当前编辑内容覆盖？')) {{
+                document.getElementById('base_hash').value = etag.replace(/^W\//, '').replace(/"/g, '');
+                saveConfig(form);
+            }}
+            return undefined;
+        }}
         
+        // 新建规则集: 打开尚不存在的规则集文件，保存后生效
+        function newRuleSet() {{
+            const name = prompt('规则集名称（字母、数字、-和_）：');
+            if (name === null) return;
+            if (!/^[A-Za-z0-9_-]+$/.test(name)) {{
+                alert('规则集名称只能包含字母、数字、-和_');
+                return;
+            }}
+            const auth = document.querySelector('input[name="auth_token"]').value;
+            location.href = `/config?auth=${{encodeURIComponent(auth)}}&file=${{encodeURIComponent('{}' + name + '.yml')}}`;
+        }}
+
         // Ctrl+S 快捷键保存
         document.addEventListener('keydown', function(e) {{
             if (e.ctrlKey && e.key === 's') {{
@@ -223,12 +919,18 @@ pub async fn handle_config_get(
     </script>
 </body>
 </html>"#,
+        file_links,
         encrypted_auth,
-        html_escape(&config_content)
+        config_hash,
+        html_escape(&file),
+        html_escape(&file),
+        html_escape(&config_content),
+        EDITOR_PREFIX
     );
 
     HttpResponse::Ok()
         .content_type("text/html; charset=utf-8")
+        .header("ETag", format!("\"{}\"", config_hash))
         .body(html_content)
 }
 
@@ -239,6 +941,7 @@ pub async fn handle_config_post(
     state: State<AppState>,
 ) -> impl Responder {
     let client_ip = get_client_ip(&req);
+    let app_config = state.app_config.load_full();
 
     // 解析multipart表单数据
     let form_data = match parse_multipart_form(multipart).await {
@@ -247,7 +950,7 @@ pub async fn handle_config_post(
             warn!("[{}] /config POST 解析multipart数据失败: {}", client_ip, e);
             return HttpResponse::BadRequest()
                 .content_type("text/html; charset=utf-8")
-                .body(&format!(
+                .body(format!(
                     "<h1>错误</h1><p>表单数据解析失败: {}</p>",
                     html_escape(&e)
                 ));
@@ -271,7 +974,7 @@ pub async fn handle_config_post(
     };
 
     // 解密token获得管理员密码
-    let decrypted_password = match decrypt_secret(auth_token, &state.app_config.encryption_key) {
+    let decrypted_password = match decrypt_secret(auth_token, &app_config.encryption_key) {
         Ok(decrypted) => {
             debug!("[{}] /config POST 成功解密token", client_ip);
             decrypted
@@ -288,7 +991,7 @@ pub async fn handle_config_post(
     };
 
     // 验证解密后的密码是否与admin_password匹配
-    if decrypted_password != state.app_config.admin_password {
+    if decrypted_password != app_config.admin_password {
         warn!(
             "[{}] /config POST 管理员密码验证失败，访问被禁止",
             client_ip
@@ -298,43 +1001,184 @@ pub async fn handle_config_post(
             .body("<h1>错误</h1><p>身份验证失败</p>");
     }
 
-    // 验证新配置格式
-    let new_config: crate::models::ClashConfig =
-        match serde_yaml_ng::from_str(&form_data.config_content) {
-            Ok(config) => config,
+    // 编辑的文件，被引用的文件需与磁盘上的clash.yml一起校验整体配置
+    let file = form_data
+        .file
+        .clone()
+        .filter(|file| !file.is_empty())
+        .unwrap_or_else(|| MAIN_FILE.to_string());
+    let config_path = Path::new(INCLUDE_DIR).join(&file);
+    let rule_set_name = ruleset::editor_name(&file);
+
+    // 保存期间持有写锁，读取当前配置、校验、渲染、并发检测与写入之间不会被其他保存或重载打断
+    let _write_guard = state.config_write_lock.lock().await;
+
+    let main_content = if file == MAIN_FILE || rule_set_name.is_some() {
+        None
+    } else {
+        match tokio::fs::read_to_string(CLASH_CONFIG_PATH).await {
+            Ok(content) => Some(content),
             Err(e) => {
-                warn!("[{}] 配置格式验证失败: {}", client_ip, e);
-                return HttpResponse::BadRequest()
+                error!("[{}] 读取配置文件失败: {}", client_ip, e);
+                return HttpResponse::InternalServerError()
                     .content_type("text/html; charset=utf-8")
-                    .body(&format!(
-                        r#"<h1>配置格式错误</h1>
-                    <p>YAML解析失败: {}</p>
+                    .body("<h1>保存失败</h1><p>无法读取config/clash.yml</p>");
+            }
+        }
+    };
+
+    // 验证新配置格式和语义，规则集文件只校验规则集本身，配置不变
+    let current = state.snapshot.load_full();
+    let mut hosted_rule_sets = current.hosted_rule_sets.clone();
+    let (new_config, report) = match (rule_set_name, &main_content) {
+        (Some(name), _) => {
+            let (rule_set, report) = validate_rule_set(&form_data.config_content);
+            if let Some(rule_set) = rule_set {
+                hosted_rule_sets.insert(name.to_string(), rule_set);
+            }
+            (Some(current.config.clone()), report)
+        }
+        (None, Some(main_content)) => validate_files(
+            main_content,
+            Some((file.as_str(), form_data.config_content.as_str())),
+        ),
+        (None, None) => validate_content(&form_data.config_content),
+    };
+    let new_config = match new_config {
+        Some(config) if report.valid => config,
+        _ => {
+            let summary = report.error_summary();
+            warn!("[{}] 配置校验失败: {}", client_ip, summary);
+            return HttpResponse::BadRequest()
+                .content_type("text/html; charset=utf-8")
+                .body(format!(
+                    r#"<h1>配置格式错误</h1>
+                    <p>{}</p>
                     <p><a href="javascript:history.back()">返回修改</a></p>"#,
-                        html_escape(&e.to_string())
-                    ));
+                    html_escape(&summary)
+                ));
+        }
+    };
+
+    // 写入文件前先渲染订阅模板，失败时不做任何修改
+    let snapshot = match Snapshot::build(
+        new_config,
+        current.profiles.clone(),
+        current.users.clone(),
+        current.overlays.clone(),
+        current.upstreams.clone(),
+        hosted_rule_sets,
+    ) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            error!("[{}] 保存失败: {}", client_ip, e);
+            return HttpResponse::InternalServerError()
+                .content_type("text/html; charset=utf-8")
+                .body(format!(
+                    "<h1>保存失败</h1><p>无法渲染订阅配置: {}</p>",
+                    html_escape(&e)
+                ));
+        }
+    };
+
+    // 乐观并发控制: 编辑器加载的版本必须仍是当前版本
+    let base_hash = req
+        .headers()
+        .get("If-Match")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| parse_etag(value).to_string())
+        .or_else(|| form_data.base_hash.clone())
+        .filter(|hash| !hash.is_empty() && hash != "*");
+
+    if let Some(base_hash) = base_hash {
+        let current_content = match tokio::fs::read_to_string(&config_path).await {
+            Ok(content) => Some(content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                error!("[{}] 读取配置文件失败: {}", client_ip, e);
+                return HttpResponse::InternalServerError()
+                    .content_type("text/html; charset=utf-8")
+                    .body("<h1>保存失败</h1><p>无法读取当前配置文件</p>");
             }
         };
 
-    // 保存配置到文件
-    if let Err(e) = tokio::fs::write("config/clash.yml", &form_data.config_content).await {
-        error!("[{}] 保存配置文件失败: {}", client_ip, e);
+        if let Some(current_content) = current_content {
+            let current_hash = content_hash(&current_content);
+            if current_hash != base_hash {
+                // 优先展示加载之后其他人做的修改，找不到基线版本时展示与提交内容的差异
+                let diff = match history::find_by_hash(&base_hash).await {
+                    Some(base_content) => history::unified_diff(
+                        &base_content,
+                        &current_content,
+                        "编辑器加载的版本",
+                        "当前配置",
+                    ),
+                    None => history::unified_diff(
+                        &form_data.config_content,
+                        &current_content,
+                        "提交的内容",
+                        "当前配置",
+                    ),
+                };
+                warn!(
+                    "[{}] 配置已被修改，拒绝过期的保存 (基线: {}, 当前: {})",
+                    client_ip, base_hash, current_hash
+                );
+                return HttpResponse::Conflict()
+                    .content_type("text/plain; charset=utf-8")
+                    .header("ETag", format!("\"{}\"", current_hash))
+                    .body(diff);
+            }
+        }
+    }
+
+    // 原子写入配置文件，新建规则集时先创建目录
+    if rule_set_name.is_some()
+        && let Err(e) = tokio::fs::create_dir_all(RULESETS_DIR).await
+    {
+        error!("[{}] 无法创建{}: {}", client_ip, RULESETS_DIR, e);
+        return HttpResponse::InternalServerError()
+            .content_type("text/html; charset=utf-8")
+            .body("<h1>保存失败</h1><p>无法创建规则集目录</p>");
+    }
+    if let Err(e) = write_atomic(&config_path, form_data.config_content.as_bytes()).await {
+        error!("[{}] 保存配置文件{}失败: {}", client_ip, file, e);
         return HttpResponse::InternalServerError()
             .content_type("text/html; charset=utf-8")
             .body("<h1>保存失败</h1><p>无法写入配置文件</p>");
     }
 
-    // 更新内存中的配置
+    // 更新内存中的配置和订阅模板
+    let config_version = publish(&state, snapshot);
+
+    // 记录历史版本，历史版本保存clash.yml及其引用的文件，规则集不在其中
+    if rule_set_name.is_none()
+        && let Err(e) = history::record(
+            &app_config.history,
+            main_content.as_deref().unwrap_or(&form_data.config_content),
+            &client_ip,
+            "save",
+            form_data.message.clone(),
+        )
+        .await
     {
-        let mut config_guard = state.clash_config.write().await;
-        *config_guard = new_config;
+        warn!("[{}] 记录配置历史版本失败: {}", client_ip, e);
     }
 
-    info!("[{}] 配置保存并重载成功", client_ip);
+    info!(
+        "[{}] 配置文件{}保存并重载成功，配置版本: {}",
+        client_ip, file, config_version
+    );
 
     // 返回成功页面
     HttpResponse::Ok()
         .content_type("text/html; charset=utf-8")
-        .body(&format!(
+        .header("X-Config-Version", config_version.to_string())
+        .header(
+            "ETag",
+            format!("\"{}\"", content_hash(&form_data.config_content)),
+        )
+        .body(format!(
             r#"<!DOCTYPE html>
 <html lang="zh-CN">
 <head>
@@ -380,21 +1224,28 @@ pub async fn handle_config_post(
 <body>
     <div class="container">
         <div class="success">✅ 配置保存成功！</div>
-        <p>配置已成功保存到 config/clash.yml 并重载到内存。</p>
+        <p>配置已成功保存到 config/{} 并重载到内存。</p>
         <p>所有后续订阅请求将使用新配置。</p>
         <div>
-            <a href="/config?auth={}" class="btn">🔄 继续编辑</a>
+            <a href="/config?auth={}&file={}" class="btn">🔄 继续编辑</a>
         </div>
     </div>
 </body>
 </html>"#,
-            form_data.auth_token
+            html_escape(&file),
+            form_data.auth_token,
+            html_escape(&file)
         ))
 }
 
 // 热重载处理函数
-pub async fn handle_config_reload(req: HttpRequest, state: State<AppState>) -> impl Responder {
+pub async fn handle_config_reload(
+    req: HttpRequest,
+    query: Query<ReloadQuery>,
+    state: State<AppState>,
+) -> impl Responder {
     let client_ip = get_client_ip(&req);
+    let app_config = state.app_config.load_full();
 
     // 验证Authorization Bearer头
     let auth_header = match req.headers().get("Authorization") {
@@ -417,8 +1268,8 @@ pub async fn handle_config_reload(req: HttpRequest, state: State<AppState>) -> i
     };
 
     // 检查Bearer前缀
-    let token = if auth_str.starts_with("Bearer ") {
-        &auth_str[7..] // 去掉"Bearer "前缀
+    let token = if let Some(token) = auth_str.strip_prefix("Bearer ") {
+        token // 去掉"Bearer "前缀
     } else {
         warn!(
             "[{}] /config/reload Authorization头缺少Bearer前缀",
@@ -428,7 +1279,7 @@ pub async fn handle_config_reload(req: HttpRequest, state: State<AppState>) -> i
     };
 
     // 验证token是否是加密后的值
-    let decrypt_plaintext = match decrypt_secret(token, &state.app_config.encryption_key) {
+    let decrypt_plaintext = match decrypt_secret(token, &app_config.encryption_key) {
         Ok(decrypted) => decrypted,
         Err(e) => {
             warn!("[{}] /config/reload token解密失败: {}", client_ip, e);
@@ -436,7 +1287,7 @@ pub async fn handle_config_reload(req: HttpRequest, state: State<AppState>) -> i
         }
     };
 
-    if decrypt_plaintext != state.app_config.admin_password {
+    if decrypt_plaintext != app_config.admin_password {
         warn!(
             "[{}] /config/reload token验证失败，与管理员密码不符，实际'{}'",
             client_ip, decrypt_plaintext
@@ -444,43 +1295,138 @@ pub async fn handle_config_reload(req: HttpRequest, state: State<AppState>) -> i
         return HttpResponse::NoContent().finish();
     }
 
-    // 重新读取clash.yml配置文件
-    let clash_config_content = match tokio::fs::read_to_string("config/clash.yml").await {
-        Ok(content) => content,
+    // 先重载app.yml，失败时不再继续
+    let app_changes = match reload_app_config(&state).await {
+        Ok(changes) => changes,
         Err(e) => {
-            error!(
-                "[{}] 热重载失败: 无法读取config/clash.yml - {}",
-                client_ip, e
-            );
+            error!("[{}] 应用配置重载失败: {}", client_ip, e);
             return HttpResponse::InternalServerError()
                 .content_type("text/plain; charset=utf-8")
-                .body("Failed to read config file");
+                .body(format!("应用配置重载失败: {}", e));
         }
     };
+    log_app_config_changes(&client_ip, &app_changes);
 
-    // 解析新的配置文件
-    let new_clash_config = match serde_yaml_ng::from_str(&clash_config_content) {
-        Ok(config) => config,
+    let mut lines: Vec<String> = app_changes.applied.clone();
+    lines.extend(
+        app_changes
+            .restart_required
+            .iter()
+            .map(|change| format!("{}（需要重启服务才能生效）", change)),
+    );
+
+    // 重新读取、校验并替换配置
+    match reload_from_disk(&state, &client_ip, query.message.clone()).await {
+        Ok(ReloadOutcome::Reloaded(changes)) => {
+            info!("[{}] 配置文件热重载成功", client_ip);
+            for change in &changes {
+                info!("[{}]   - {}", client_ip, change);
+            }
+            lines.extend(changes);
+            HttpResponse::Ok()
+                .content_type("text/plain; charset=utf-8")
+                .header(
+                    "X-Config-Version",
+                    state.snapshot.load().version.to_string(),
+                )
+                .body(format!("配置重载成功\n{}", lines.join("\n")))
+        }
+        Ok(ReloadOutcome::Unchanged) => {
+            info!("[{}] 配置文件内容未变化，无需重载", client_ip);
+            let body = if lines.is_empty() {
+                "配置重载成功（内容无变化）".to_string()
+            } else {
+                format!("配置重载成功\n{}", lines.join("\n"))
+            };
+            HttpResponse::Ok()
+                .content_type("text/plain; charset=utf-8")
+                .header(
+                    "X-Config-Version",
+                    state.snapshot.load().version.to_string(),
+                )
+                .body(body)
+        }
         Err(e) => {
-            error!(
-                "[{}] 热重载失败: config/clash.yml解析错误 - {}",
+            error!("[{}] 热重载失败: {}", client_ip, e);
+            HttpResponse::InternalServerError()
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("配置重载失败: {}", e))
+        }
+    }
+}
+
+// 配置校验处理函数 (POST)，只校验不保存
+pub async fn handle_config_validate(
+    req: HttpRequest,
+    query: Query<ValidateQuery>,
+    body: Bytes,
+    state: State<AppState>,
+) -> impl Responder {
+    let client_ip = get_client_ip(&req);
+    let app_config = state.app_config.load_full();
+
+    if !authorize_admin(&req, &app_config, &client_ip) {
+        return HttpResponse::NoContent().finish();
+    }
+
+    let content = match std::str::from_utf8(&body) {
+        Ok(content) => content,
+        Err(e) => {
+            warn!(
+                "[{}] /config/validate 内容不是有效的UTF-8: {}",
                 client_ip, e
             );
-            return HttpResponse::InternalServerError()
+            return HttpResponse::BadRequest()
                 .content_type("text/plain; charset=utf-8")
-                .body("Failed to parse config file");
+                .body("配置内容不是有效的UTF-8");
         }
     };
 
-    // 更新配置
-    {
-        let mut config_guard = state.clash_config.write().await;
-        *config_guard = new_clash_config;
-    }
+    // 校验被引用的文件时，与磁盘上的clash.yml一起校验整体配置
+    let (_, report) = match query.file.as_deref().filter(|file| *file != MAIN_FILE) {
+        Some(file) if ruleset::editor_name(file).is_some() => {
+            let (_, report) = validate_rule_set(content);
+            (None, report)
+        }
+        Some(file) if !file.is_empty() => {
+            match tokio::fs::read_to_string(CLASH_CONFIG_PATH).await {
+                Ok(main_content) => validate_live(&main_content, Some((file, content))),
+                Err(e) => {
+                    error!("[{}] 读取配置文件失败: {}", client_ip, e);
+                    return HttpResponse::InternalServerError()
+                        .content_type("text/plain; charset=utf-8")
+                        .body("无法读取config/clash.yml");
+                }
+            }
+        }
+        _ => validate_live(content, None),
+    };
+    debug!(
+        "[{}] 配置校验完成: {} 个错误, {} 个警告",
+        client_ip, report.errors, report.warnings
+    );
 
-    info!("[{}] 配置文件热重载成功", client_ip);
+    json_response(&report)
+}
 
-    HttpResponse::Ok()
-        .content_type("text/plain; charset=utf-8")
-        .body("配置重载成功")
+// 确定编辑器要打开的文件，返回文件名和可以编辑的其他文件: clash.yml引用的文件和托管规则集
+// clash.yml无法解析时只能编辑clash.yml本身和规则集
+async fn editable_file(
+    file: Option<&str>,
+    rule_sets: Vec<String>,
+) -> Result<(String, Vec<String>), String> {
+    let content = tokio::fs::read_to_string(CLASH_CONFIG_PATH)
+        .await
+        .map_err(|e| format!("无法读取{}: {}", CLASH_CONFIG_PATH, e))?;
+    let mut files = include::parse_config(&content)
+        .map(|(_, files)| files)
+        .unwrap_or_default();
+    files.extend(rule_sets);
+
+    match file.filter(|file| !file.is_empty() && *file != MAIN_FILE) {
+        None => Ok((MAIN_FILE.to_string(), files)),
+        Some(file) if files.iter().any(|name| name == file) => Ok((file.to_string(), files)),
+        Some(file) if ruleset::editor_name(file).is_some() => Ok((file.to_string(), files)),
+        Some(file) => Err(format!("{}未被{}引用", file, MAIN_FILE)),
+    }
 }
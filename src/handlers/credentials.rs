@@ -0,0 +1,146 @@
+use crate::credentials::{Credentials, Protocol};
+use crate::handlers::common::{CredentialsQuery, authorize_admin, get_client_ip, json_response};
+use crate::models::AppState;
+
+use ntex::web::types::{Query, State};
+use ntex::web::{HttpRequest, HttpResponse, Responder};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+// 导出结果
+#[derive(Serialize)]
+struct CredentialsExport {
+    protocols: Vec<Protocol>, // 启用的协议
+    // 所有用户的short-id，去重排序，可直接用于Xray的realitySettings.shortIds
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    short_ids: Vec<String>,
+    users: Vec<UserCredentials>,
+}
+
+// 单个用户在服务端配置中使用的密码
+#[derive(Serialize)]
+struct UserCredentials {
+    uuid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trojan: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hysteria2: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tuic: Option<String>,
+    // 以加密方式为键，ss 2022为用户密钥
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    ss: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    short_id: Option<String>,
+}
+
+// 节点密码和short-id导出处理函数 (GET)，用于生成服务端配置
+// 不指定uuid时导出users.yml中的所有用户
+pub async fn handle_credentials_export(
+    req: HttpRequest,
+    query: Query<CredentialsQuery>,
+    state: State<AppState>,
+) -> impl Responder {
+    let client_ip = get_client_ip(&req);
+    let app_config = state.app_config.load_full();
+
+    if !authorize_admin(&req, &app_config, &client_ip) {
+        return HttpResponse::NoContent().finish();
+    }
+
+    let snapshot = state.snapshot.load_full();
+    let uuids: Vec<String> = match &query.uuid {
+        Some(uuid) => match Uuid::parse_str(uuid) {
+            Ok(uuid) => vec![uuid.to_string()],
+            Err(_) => {
+                warn!("[{}] 导出节点密码失败: 无效的UUID {}", client_ip, uuid);
+                return HttpResponse::BadRequest()
+                    .content_type("text/plain; charset=utf-8")
+                    .body(format!("无效的UUID: {}", uuid));
+            }
+        },
+        None => {
+            let mut uuids: Vec<String> = snapshot
+                .users
+                .users
+                .keys()
+                .map(|uuid| uuid.to_ascii_lowercase())
+                .collect();
+            uuids.sort();
+            uuids
+        }
+    };
+
+    // ss密码与加密方式有关，按配置中ss节点使用的加密方式分别导出
+    let ss_ciphers: BTreeSet<&str> = snapshot
+        .config
+        .proxies
+        .iter()
+        .flatten()
+        .filter(|proxy| proxy.get("type").and_then(|v| v.as_str()) == Some("ss"))
+        .filter_map(|proxy| proxy.get("cipher").and_then(|v| v.as_str()))
+        .collect();
+
+    let mut users = Vec::with_capacity(uuids.len());
+    for uuid in uuids {
+        let user = snapshot.users.user(&uuid);
+        let credentials =
+            match Credentials::new(&app_config.credentials, &app_config.encryption_key, user) {
+                Ok(credentials) => credentials,
+                Err(e) => {
+                    error!("[{}] 导出节点密码失败: {}", client_ip, e);
+                    return HttpResponse::InternalServerError()
+                        .content_type("text/plain; charset=utf-8")
+                        .body(e);
+                }
+            };
+        let password = |protocol: Protocol| {
+            credentials
+                .enabled(protocol)
+                .then(|| credentials.user_password(protocol, &uuid, None))
+        };
+        let ss = if credentials.enabled(Protocol::Shadowsocks) {
+            ss_ciphers
+                .iter()
+                .map(|cipher| {
+                    let password =
+                        credentials.user_password(Protocol::Shadowsocks, &uuid, Some(cipher));
+                    (cipher.to_string(), password)
+                })
+                .collect()
+        } else {
+            BTreeMap::new()
+        };
+
+        let short_id = credentials
+            .short_id_enabled()
+            .then(|| credentials.short_id(&uuid));
+
+        users.push(UserCredentials {
+            name: user.and_then(|user| user.name.clone()),
+            trojan: password(Protocol::Trojan),
+            hysteria2: password(Protocol::Hysteria2),
+            tuic: password(Protocol::Tuic),
+            ss,
+            short_id,
+            uuid,
+        });
+    }
+
+    let short_ids: BTreeSet<String> = users
+        .iter()
+        .filter_map(|user| user.short_id.clone())
+        .collect();
+
+    info!("[{}] 导出了{}个用户的节点密码", client_ip, users.len());
+
+    json_response(&CredentialsExport {
+        protocols: app_config.credentials.protocols.clone(),
+        short_ids: short_ids.into_iter().collect(),
+        users,
+    })
+}
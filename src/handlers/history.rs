@@ -0,0 +1,283 @@
+use crate::handlers::common::{
+    HistoryDiffQuery, RollbackQuery, authorize_admin, get_client_ip, json_response,
+};
+use crate::history::{self, VersionMeta};
+use crate::include::{self, INCLUDE_DIR, MAIN_FILE};
+use crate::models::AppState;
+use crate::reload::{CLASH_CONFIG_PATH, publish};
+use crate::snapshot::Snapshot;
+use crate::storage::write_atomic;
+use crate::validation::validate_version;
+
+use ntex::web::types::{Query, State};
+use ntex::web::{HttpRequest, HttpResponse, Responder};
+use serde::Serialize;
+use std::path::Path;
+use tracing::{error, info, warn};
+
+// 当前配置文件在对比时使用的版本名称
+const CURRENT_VERSION: &str = "current";
+
+// 回滚结果
+#[derive(Serialize)]
+struct RollbackResult {
+    restored: String,
+    version: Option<VersionMeta>,
+    config_version: u64, // 回滚后的内存配置版本号
+}
+
+// 读取指定版本的clash.yml及其引用的文件，current表示磁盘上的当前配置
+async fn read_content(id: &str) -> Result<(String, Vec<(String, String)>), String> {
+    if id == CURRENT_VERSION {
+        let content = tokio::fs::read_to_string(CLASH_CONFIG_PATH)
+            .await
+            .map_err(|e| format!("读取{}失败: {}", CLASH_CONFIG_PATH, e))?;
+        let files = include::read_referenced(&content)?;
+        Ok((content, files))
+    } else {
+        Ok((
+            history::read_version(id).await?,
+            history::read_files(id).await?,
+        ))
+    }
+}
+
+// 对比两个版本的所有文件，被引用文件的差异依次追加在clash.yml之后
+fn diff_versions(
+    old: &(String, Vec<(String, String)>),
+    new: &(String, Vec<(String, String)>),
+    old_label: &str,
+    new_label: &str,
+) -> String {
+    let mut diff = history::unified_diff(&old.0, &new.0, old_label, new_label);
+    let mut names: Vec<&str> = Vec::new();
+    for (name, _) in old.1.iter().chain(&new.1) {
+        if !names.contains(&name.as_str()) {
+            names.push(name);
+        }
+    }
+    let content = |files: &[(String, String)], name: &str| {
+        files
+            .iter()
+            .find(|(file, _)| file == name)
+            .map(|(_, content)| content.clone())
+            .unwrap_or_default()
+    };
+    for name in names {
+        diff.push_str(&history::unified_diff(
+            &content(&old.1, name),
+            &content(&new.1, name),
+            &format!("{} {}", old_label, name),
+            &format!("{} {}", new_label, name),
+        ));
+    }
+    diff
+}
+
+// 历史版本列表处理函数 (GET)
+pub async fn handle_history_list(req: HttpRequest, state: State<AppState>) -> impl Responder {
+    let client_ip = get_client_ip(&req);
+    let app_config = state.app_config.load_full();
+
+    if !authorize_admin(&req, &app_config, &client_ip) {
+        return HttpResponse::NoContent().finish();
+    }
+
+    match history::list().await {
+        Ok(versions) => json_response(&versions),
+        Err(e) => {
+            error!("[{}] 读取历史版本列表失败: {}", client_ip, e);
+            HttpResponse::InternalServerError()
+                .content_type("text/plain; charset=utf-8")
+                .body(e)
+        }
+    }
+}
+
+// 历史版本对比处理函数 (GET)，返回统一格式diff
+pub async fn handle_history_diff(
+    req: HttpRequest,
+    query: Query<HistoryDiffQuery>,
+    state: State<AppState>,
+) -> impl Responder {
+    let client_ip = get_client_ip(&req);
+    let app_config = state.app_config.load_full();
+
+    if !authorize_admin(&req, &app_config, &client_ip) {
+        return HttpResponse::NoContent().finish();
+    }
+
+    let to = query.to.as_deref().unwrap_or(CURRENT_VERSION);
+    let (old, new) = match (read_content(&query.from).await, read_content(to).await) {
+        (Ok(old), Ok(new)) => (old, new),
+        (Err(e), _) | (_, Err(e)) => {
+            warn!("[{}] 历史版本对比失败: {}", client_ip, e);
+            return HttpResponse::NotFound()
+                .content_type("text/plain; charset=utf-8")
+                .body(e);
+        }
+    };
+
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .header("Cache-Control", "no-cache")
+        .body(diff_versions(&old, &new, &query.from, to))
+}
+
+// 历史版本回滚处理函数 (POST)
+pub async fn handle_history_rollback(
+    req: HttpRequest,
+    query: Query<RollbackQuery>,
+    state: State<AppState>,
+) -> impl Responder {
+    let client_ip = get_client_ip(&req);
+    let app_config = state.app_config.load_full();
+
+    if !authorize_admin(&req, &app_config, &client_ip) {
+        return HttpResponse::NoContent().finish();
+    }
+
+    let (content, files) = match read_content(&query.id).await {
+        Ok(version) => version,
+        Err(e) => {
+            warn!("[{}] 回滚失败: {}", client_ip, e);
+            return HttpResponse::NotFound()
+                .content_type("text/plain; charset=utf-8")
+                .body(e);
+        }
+    };
+
+    // 引用的文件必须都保存在该版本中，否则无法还原到一致的状态
+    let overrides: Vec<(&str, &str)> = files
+        .iter()
+        .map(|(name, content)| (name.as_str(), content.as_str()))
+        .collect();
+    let referenced = match include::referenced_files(&content, &overrides) {
+        Ok(referenced) => referenced,
+        Err(e) => {
+            warn!("[{}] 回滚失败: 版本 {} - {}", client_ip, query.id, e);
+            return HttpResponse::BadRequest()
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("版本 {} 校验未通过: {}", query.id, e));
+        }
+    };
+    let missing: Vec<&str> = referenced
+        .iter()
+        .filter(|name| !files.iter().any(|(file, _)| file == *name))
+        .map(|name| name.as_str())
+        .collect();
+    if !missing.is_empty() {
+        let message = format!(
+            "版本 {} 没有保存{}引用的文件，无法回滚: {}",
+            query.id,
+            MAIN_FILE,
+            missing.join(", ")
+        );
+        warn!("[{}] 回滚失败: {}", client_ip, message);
+        return HttpResponse::BadRequest()
+            .content_type("text/plain; charset=utf-8")
+            .body(message);
+    }
+
+    // 历史版本同样需要通过校验才能生效
+    let (new_config, report) = validate_version(&content, &files);
+    let new_config = match new_config {
+        Some(config) if report.valid => config,
+        _ => {
+            let summary = report.error_summary();
+            warn!(
+                "[{}] 回滚失败: 版本 {} 校验未通过 - {}",
+                client_ip, query.id, summary
+            );
+            return HttpResponse::BadRequest()
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("版本 {} 校验未通过: {}", query.id, summary));
+        }
+    };
+
+    // 先取得写入锁再读取当前快照，避免覆盖期间完成的重载或上游更新
+    let _write_guard = state.config_write_lock.lock().await;
+
+    let current = state.snapshot.load_full();
+    let snapshot = match Snapshot::build(
+        new_config,
+        current.profiles.clone(),
+        current.users.clone(),
+        current.overlays.clone(),
+        current.upstreams.clone(),
+        current.hosted_rule_sets.clone(),
+    ) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            error!("[{}] 回滚失败: {}", client_ip, e);
+            return HttpResponse::InternalServerError()
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("配置渲染失败: {}", e));
+        }
+    };
+
+    // 先还原被引用的文件，最后写入clash.yml
+    for (name, file_content) in files.iter().filter(|(name, _)| referenced.contains(name)) {
+        let path = Path::new(INCLUDE_DIR).join(name);
+        if let Err(e) = write_atomic(&path, file_content.as_bytes()).await {
+            error!(
+                "[{}] 回滚失败: 无法写入{} - {}",
+                client_ip,
+                path.display(),
+                e
+            );
+            return HttpResponse::InternalServerError()
+                .content_type("text/plain; charset=utf-8")
+                .body("无法写入配置文件");
+        }
+    }
+    if let Err(e) = write_atomic(CLASH_CONFIG_PATH, content.as_bytes()).await {
+        error!("[{}] 回滚失败: 无法写入配置文件 - {}", client_ip, e);
+        return HttpResponse::InternalServerError()
+            .content_type("text/plain; charset=utf-8")
+            .body("无法写入配置文件");
+    }
+
+    let config_version = publish(&state, snapshot);
+
+    let message = query
+        .message
+        .clone()
+        .or_else(|| Some(format!("回滚到版本 {}", query.id)));
+    let version = match history::record(
+        &app_config.history,
+        &content,
+        &client_ip,
+        "rollback",
+        message,
+    )
+    .await
+    {
+        Ok(version) => version,
+        Err(e) => {
+            warn!("[{}] 记录配置历史版本失败: {}", client_ip, e);
+            None
+        }
+    };
+
+    info!(
+        "[{}] 配置已回滚到版本 {}，配置版本: {}",
+        client_ip, query.id, config_version
+    );
+
+    let result = RollbackResult {
+        restored: query.id.clone(),
+        version,
+        config_version,
+    };
+    match serde_json::to_string(&result) {
+        Ok(json) => HttpResponse::Ok()
+            .content_type("application/json; charset=utf-8")
+            .header("Cache-Control", "no-cache")
+            .header("X-Config-Version", config_version.to_string())
+            .body(json),
+        Err(e) => HttpResponse::InternalServerError()
+            .content_type("text/plain; charset=utf-8")
+            .body(format!("JSON序列化失败: {}", e)),
+    }
+}
@@ -0,0 +1,135 @@
+use crate::handlers::common::{ImportRequest, authorize_admin, get_client_ip, json_response};
+use crate::models::AppState;
+use crate::share_link;
+
+use ntex::util::Bytes;
+use ntex::web::types::State;
+use ntex::web::{HttpRequest, HttpResponse, Responder};
+use serde::Serialize;
+use serde_yaml_ng::Value;
+use std::collections::HashSet;
+use tracing::{info, warn};
+
+// 导入结果
+#[derive(Serialize)]
+struct ImportResult {
+    count: usize,
+    proxies: String,         // 解析出的节点，YAML格式，用于预览
+    skipped: Vec<String>,    // 无法解析的链接及原因
+    content: Option<String>, // 追加节点后的编辑器内容，无法自动追加时为空
+    error: Option<String>,   // 无法自动追加的原因
+}
+
+// 节点导入处理函数 (POST)，将分享链接解析为proxies节点
+// 只返回预览和追加节点后的编辑器内容，不修改文件，保存仍通过/config的校验流程
+pub async fn handle_config_import(
+    req: HttpRequest,
+    body: Bytes,
+    state: State<AppState>,
+) -> impl Responder {
+    let client_ip = get_client_ip(&req);
+    let app_config = state.app_config.load_full();
+
+    if !authorize_admin(&req, &app_config, &client_ip) {
+        return HttpResponse::NoContent().finish();
+    }
+
+    let request: ImportRequest = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            warn!("[{}] /config/import 请求格式错误: {}", client_ip, e);
+            return HttpResponse::BadRequest()
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("请求格式错误: {}", e));
+        }
+    };
+
+    let parsed = match share_link::parse_subscription(&request.links) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            warn!("[{}] /config/import 无法解析: {}", client_ip, e);
+            return HttpResponse::BadRequest()
+                .content_type("text/plain; charset=utf-8")
+                .body(e);
+        }
+    };
+
+    // 与当前配置和编辑器中尚未保存的节点重名时在名称后加序号
+    let snapshot = state.snapshot.load_full();
+    let mut names: HashSet<String> = snapshot
+        .config
+        .proxies
+        .iter()
+        .flatten()
+        .filter_map(|proxy| proxy.get("name").and_then(|name| name.as_str()))
+        .map(|name| name.to_string())
+        .collect();
+    if let Some(content) = &request.content {
+        names.extend(editor_proxy_names(content));
+    }
+    let proxies: Vec<_> = parsed
+        .proxies
+        .into_iter()
+        .map(|mut proxy| {
+            let name = proxy
+                .get("name")
+                .and_then(|name| name.as_str())
+                .unwrap_or_default();
+            let name = share_link::unique_name(&mut names, name);
+            proxy.insert("name".to_string(), Value::from(name));
+            proxy
+        })
+        .collect();
+
+    let preview = match share_link::to_yaml(&proxies) {
+        Ok(preview) => preview,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .content_type("text/plain; charset=utf-8")
+                .body(e);
+        }
+    };
+    let (content, error) = match &request.content {
+        Some(_) if proxies.is_empty() => (None, Some("没有可导入的节点".to_string())),
+        Some(content) => match share_link::append_proxies(content, &proxies) {
+            Ok(content) => (Some(content), None),
+            Err(e) => (None, Some(e)),
+        },
+        None => (None, None),
+    };
+
+    info!(
+        "[{}] 解析分享链接: {} 个节点, {} 个无法解析",
+        client_ip,
+        proxies.len(),
+        parsed.skipped.len()
+    );
+
+    json_response(&ImportResult {
+        count: proxies.len(),
+        proxies: preview,
+        skipped: parsed.skipped,
+        content,
+        error,
+    })
+}
+
+// 编辑器内容中已有的节点名称，内容无法解析时忽略
+fn editor_proxy_names(content: &str) -> Vec<String> {
+    let Ok(root) = serde_yaml_ng::from_str::<Value>(content) else {
+        return Vec::new();
+    };
+    let proxies = match &root {
+        Value::Sequence(items) => Some(items),
+        Value::Mapping(_) => root
+            .get("proxies")
+            .and_then(|proxies| proxies.as_sequence()),
+        _ => None,
+    };
+    proxies
+        .into_iter()
+        .flatten()
+        .filter_map(|proxy| proxy.get("name").and_then(|name| name.as_str()))
+        .map(|name| name.to_string())
+        .collect()
+}
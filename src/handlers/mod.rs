@@ -7,10 +7,36 @@ pub mod subscription;
 // 配置管理模块
 pub mod config;
 
+// 历史版本模块
+pub mod history;
+
+// 节点密码模块
+pub mod credentials;
+
+// 覆盖配置模块
+pub mod overlays;
+
+// 节点导入模块
+pub mod import;
+
+// 规则集模块
+pub mod provider;
+
+// 规则模拟模块
+pub mod simulate;
+
 // 其他模块
 pub mod other;
 
 // 重新导出主要的handler函数，保持向后兼容
-pub use config::{handle_config_get, handle_config_post, handle_config_reload};
+pub use config::{
+    handle_config_get, handle_config_post, handle_config_reload, handle_config_validate,
+};
+pub use credentials::handle_credentials_export;
+pub use history::{handle_history_diff, handle_history_list, handle_history_rollback};
+pub use import::handle_config_import;
 pub use other::{handle_favicon, handle_other};
-pub use subscription::handle_subscription;
+pub use overlays::{handle_overlay_list, handle_overlay_merged};
+pub use provider::{handle_hosted_rule_set, handle_rule_provider};
+pub use simulate::handle_simulate;
+pub use subscription::{handle_proxy_provider, handle_subscription};
@@ -0,0 +1,140 @@
+use crate::handlers::common::{MergedQuery, authorize_admin, get_client_ip, json_response};
+use crate::models::AppState;
+use crate::profile::DEFAULT_PROFILE;
+use crate::snapshot::{Snapshot, describe_variant};
+
+use ntex::web::types::{Query, State};
+use ntex::web::{HttpRequest, HttpResponse, Responder};
+use serde::Serialize;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+// 覆盖配置信息
+#[derive(Serialize)]
+struct OverlayInfo {
+    name: String,
+    used_by: Vec<String>, // 引用该覆盖配置的套餐和用户
+}
+
+// 覆盖配置列表处理函数 (GET)
+pub async fn handle_overlay_list(req: HttpRequest, state: State<AppState>) -> impl Responder {
+    let client_ip = get_client_ip(&req);
+    let app_config = state.app_config.load_full();
+
+    if !authorize_admin(&req, &app_config, &client_ip) {
+        return HttpResponse::NoContent().finish();
+    }
+
+    let snapshot = state.snapshot.load_full();
+    let references = snapshot.users.overlay_references();
+    let overlays: Vec<OverlayInfo> = snapshot
+        .overlays
+        .keys()
+        .map(|name| {
+            let mut used_by: Vec<String> = references
+                .iter()
+                .filter(|(_, overlay)| overlay == name)
+                .map(|(owner, _)| owner.clone())
+                .collect();
+            used_by.sort();
+            OverlayInfo {
+                name: name.clone(),
+                used_by,
+            }
+        })
+        .collect();
+
+    json_response(&overlays)
+}
+
+// 合并结果处理函数 (GET)，返回合并覆盖配置并按套餐过滤后的YAML
+// 指定uuid时使用该用户的套餐和覆盖配置，否则使用plan和overlays参数
+pub async fn handle_overlay_merged(
+    req: HttpRequest,
+    query: Query<MergedQuery>,
+    state: State<AppState>,
+) -> impl Responder {
+    let client_ip = get_client_ip(&req);
+    let app_config = state.app_config.load_full();
+
+    if !authorize_admin(&req, &app_config, &client_ip) {
+        return HttpResponse::NoContent().finish();
+    }
+
+    let snapshot = state.snapshot.load_full();
+    let (profile, plan, overlays) = match select_variant(
+        &snapshot,
+        query.uuid.as_deref(),
+        query.profile.as_deref(),
+        query.plan.as_deref(),
+        query.overlays.as_deref(),
+    ) {
+        Ok(variant) => variant,
+        Err(e) => {
+            return HttpResponse::BadRequest()
+                .content_type("text/plain; charset=utf-8")
+                .body(e);
+        }
+    };
+
+    let description = describe_variant(profile, plan, &overlays);
+    let merged = snapshot
+        .merged(profile, plan, &overlays)
+        .and_then(|config| {
+            serde_yaml_ng::to_string(&config).map_err(|e| format!("配置序列化失败: {}", e))
+        });
+    match merged {
+        Ok(yaml) => {
+            info!("[{}] 查看合并结果: {}", client_ip, description);
+            HttpResponse::Ok()
+                .content_type("text/plain; charset=utf-8")
+                .header("Cache-Control", "no-cache")
+                .header("X-Config-Version", snapshot.version.to_string())
+                .body(yaml)
+        }
+        Err(e) => {
+            warn!("[{}] 合并失败: {} - {}", client_ip, description, e);
+            HttpResponse::BadRequest()
+                .content_type("text/plain; charset=utf-8")
+                .body(e)
+        }
+    }
+}
+
+// 合并使用的(命名模板, 套餐, 覆盖配置)
+type Variant<'a> = (Option<&'a str>, Option<&'a str>, Vec<&'a str>);
+
+// 确定要合并的命名模板、套餐和覆盖配置
+// 指定uuid时使用该用户的设置，否则使用请求中的profile、plan和overlays
+pub(crate) fn select_variant<'a>(
+    snapshot: &'a Snapshot,
+    uuid: Option<&str>,
+    profile: Option<&'a str>,
+    plan: Option<&'a str>,
+    overlays: Option<&'a str>,
+) -> Result<Variant<'a>, String> {
+    let requested_profile = profile.filter(|profile| !profile.is_empty());
+    match uuid {
+        Some(uuid) if !uuid.is_empty() => {
+            if Uuid::parse_str(uuid).is_err() {
+                return Err(format!("无效的UUID: {}", uuid));
+            }
+            let profile = snapshot.users.select_profile(uuid, requested_profile)?;
+            Ok((
+                profile,
+                snapshot.users.plan_name(uuid),
+                snapshot.users.overlays_for(uuid),
+            ))
+        }
+        _ => Ok((
+            requested_profile.filter(|profile| *profile != DEFAULT_PROFILE),
+            plan.filter(|plan| !plan.is_empty()),
+            overlays
+                .unwrap_or_default()
+                .split(',')
+                .map(|name| name.trim())
+                .filter(|name| !name.is_empty())
+                .collect(),
+        )),
+    }
+}
@@ -0,0 +1,168 @@
+use crate::compression::{self, Encoding, negotiate};
+use crate::handlers::common::{RuleProviderQuery, RuleSetQuery, get_client_ip, subscriber_uuid};
+use crate::models::{AppConfig, AppState, RuleFormat};
+use crate::provider::{RuleSet, behavior_name, format_name};
+use crate::storage::if_none_match;
+
+use ntex::web::types::{Path, Query, State};
+use ntex::web::{HttpRequest, HttpResponse, Responder};
+use std::sync::Arc;
+use tracing::{debug, error, info, warn};
+
+// 规则集请求处理函数，返回用户所用模板中的inline规则集，用作第三方配置中的rule-providers
+pub async fn handle_rule_provider(
+    req: HttpRequest,
+    name: Path<String>,
+    query: Query<RuleProviderQuery>,
+    state: State<AppState>,
+) -> impl Responder {
+    let client_ip = get_client_ip(&req);
+    let app_config = state.app_config.load_full();
+
+    let Some(uuid) = subscriber_uuid(query.secret.as_deref(), &app_config, &client_ip) else {
+        return HttpResponse::NoContent().finish();
+    };
+    let uuid_str = uuid.to_string();
+    let name = name.into_inner();
+
+    // 与订阅使用同一命名模板，不同模板中同名的规则集内容可能不同
+    let snapshot = state.snapshot.load_full();
+    let profile = match snapshot
+        .users
+        .select_profile(&uuid_str, query.profile.as_deref())
+    {
+        Ok(profile) => profile,
+        Err(e) => {
+            warn!("[{}] {}，访问被禁止", client_ip, e);
+            return HttpResponse::NoContent().finish();
+        }
+    };
+    let Some(rule_set) = snapshot.rule_set_for(&uuid_str, profile, &name) else {
+        warn!("[{}] 规则集不存在: {}", client_ip, name);
+        return HttpResponse::NotFound()
+            .content_type("text/plain; charset=utf-8")
+            .body(format!("规则集不存在: {}", name));
+    };
+
+    info!(
+        "[{}] 用户 {} 获取规则集: {}（{}）",
+        client_ip,
+        uuid,
+        name,
+        behavior_name(&rule_set.behavior)
+    );
+    rule_set_response(
+        &req,
+        rule_set,
+        query.format.as_ref(),
+        &app_config,
+        &client_ip,
+        snapshot.version,
+    )
+}
+
+// 托管规则集请求处理函数，返回config/rulesets/下的规则集，地址固定、不需要secret
+pub async fn handle_hosted_rule_set(
+    req: HttpRequest,
+    name: Path<String>,
+    query: Query<RuleSetQuery>,
+    state: State<AppState>,
+) -> impl Responder {
+    let client_ip = get_client_ip(&req);
+    let app_config = state.app_config.load_full();
+    let snapshot = state.snapshot.load_full();
+    let name = name.into_inner();
+
+    let Some(rule_set) = snapshot.hosted_rule_sets.get(&name) else {
+        debug!("[{}] 托管规则集不存在: {}", client_ip, name);
+        return HttpResponse::NotFound()
+            .content_type("text/plain; charset=utf-8")
+            .body(format!("规则集不存在: {}", name));
+    };
+    debug!("[{}] 获取托管规则集: {}", client_ip, name);
+    rule_set_response(
+        &req,
+        rule_set,
+        query.format.as_ref(),
+        &app_config,
+        &client_ip,
+        snapshot.version,
+    )
+}
+
+// 按格式输出规则集，按Accept-Encoding协商压缩，支持If-None-Match
+fn rule_set_response(
+    req: &HttpRequest,
+    rule_set: &RuleSet,
+    format: Option<&RuleFormat>,
+    app_config: &Arc<AppConfig>,
+    client_ip: &str,
+    version: u64,
+) -> HttpResponse {
+    let format = format.cloned().unwrap_or(RuleFormat::Yaml);
+    let body = match rule_set.body(&format) {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("[{}] {}", client_ip, e);
+            return HttpResponse::BadRequest()
+                .content_type("text/plain; charset=utf-8")
+                .body(e);
+        }
+    };
+
+    // mrs格式本身已经过zstd压缩，不再按Accept-Encoding压缩
+    let encoding = match format {
+        RuleFormat::Mrs => Encoding::Identity,
+        _ => negotiate(
+            req.headers()
+                .get("Accept-Encoding")
+                .and_then(|value| value.to_str().ok()),
+        ),
+    };
+    let etag = rule_set.etag(&format, encoding.as_str());
+    if let Some(if_none_match_header) = req
+        .headers()
+        .get("If-None-Match")
+        .and_then(|value| value.to_str().ok())
+        && if_none_match(if_none_match_header, &etag)
+    {
+        return HttpResponse::NotModified()
+            .header("ETag", etag)
+            .header("Cache-Control", "no-cache")
+            .header("Vary", "Accept-Encoding")
+            .header("X-Config-Version", version.to_string())
+            .finish();
+    }
+    debug!(
+        "[{}] 输出规则集: {}，{} 条",
+        client_ip,
+        format_name(&format),
+        rule_set.payload.len()
+    );
+
+    let content_type = match format {
+        RuleFormat::Yaml => "application/x-yaml; charset=utf-8",
+        RuleFormat::Text => "text/plain; charset=utf-8",
+        RuleFormat::Mrs => "application/octet-stream",
+    };
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type(content_type)
+        .header("Cache-Control", "no-cache")
+        .header("ETag", etag)
+        .header("Vary", "Accept-Encoding")
+        .header("X-Config-Version", version.to_string());
+
+    if encoding == Encoding::Identity {
+        return response.body(body);
+    }
+    match compression::compress(encoding, &body, &app_config.compression) {
+        Ok(data) => response
+            .header("Content-Encoding", encoding.as_str())
+            .body(data),
+        Err(e) => {
+            error!("[{}] {}", client_ip, e);
+            HttpResponse::NoContent().finish()
+        }
+    }
+}
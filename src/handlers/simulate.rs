@@ -0,0 +1,121 @@
+use crate::handlers::common::{SimulateQuery, authorize_admin, get_client_ip, json_response};
+use crate::handlers::overlays::select_variant;
+use crate::models::AppState;
+use crate::simulate::{self, Connection};
+use crate::snapshot::describe_variant;
+
+use ntex::web::types::{Query, State};
+use ntex::web::{HttpRequest, HttpResponse, Responder};
+use std::net::IpAddr;
+use tracing::{info, warn};
+
+// 规则模拟处理函数 (GET)，返回连接命中的规则和代理组链
+// 使用合并覆盖配置后的结果，与merged接口选择配置的方式相同
+pub async fn handle_simulate(
+    req: HttpRequest,
+    query: Query<SimulateQuery>,
+    state: State<AppState>,
+) -> impl Responder {
+    let client_ip = get_client_ip(&req);
+    let app_config = state.app_config.load_full();
+
+    if !authorize_admin(&req, &app_config, &client_ip) {
+        return HttpResponse::NoContent().finish();
+    }
+
+    let connection = match connection(&query) {
+        Ok(connection) => connection,
+        Err(e) => return bad_request(e),
+    };
+
+    let snapshot = state.snapshot.load_full();
+    let (profile, plan, overlays) = match select_variant(
+        &snapshot,
+        query.uuid.as_deref(),
+        query.profile.as_deref(),
+        query.plan.as_deref(),
+        query.overlays.as_deref(),
+    ) {
+        Ok(variant) => variant,
+        Err(e) => return bad_request(e),
+    };
+
+    let description = describe_variant(profile, plan, &overlays);
+    let config = match snapshot.merged(profile, plan, &overlays) {
+        Ok(config) => config,
+        Err(e) => {
+            warn!("[{}] 规则模拟失败: {} - {}", client_ip, description, e);
+            return bad_request(e);
+        }
+    };
+
+    let simulation = simulate::simulate(&config, &snapshot.hosted_rule_sets, &connection);
+    info!(
+        "[{}] 规则模拟: {} {} -> {}",
+        client_ip,
+        description,
+        connection
+            .domain
+            .clone()
+            .or(connection.ip.map(|ip| ip.to_string()))
+            .unwrap_or_default(),
+        simulation.target.as_deref().unwrap_or("未命中")
+    );
+    json_response(&simulation)
+}
+
+// 从查询参数构造模拟的连接，域名填写IP地址时按IP处理
+fn connection(query: &SimulateQuery) -> Result<Connection, String> {
+    let text = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+
+    let mut domain = text(&query.domain).map(|domain| domain.trim_end_matches('.').to_lowercase());
+    let mut ip = match text(&query.ip) {
+        Some(ip) => Some(
+            ip.parse::<IpAddr>()
+                .map_err(|_| format!("无效的IP地址: {}", ip))?,
+        ),
+        None => None,
+    };
+    if let Some(address) = domain.as_deref().and_then(|d| d.parse::<IpAddr>().ok()) {
+        if ip.is_some_and(|ip| ip != address) {
+            return Err("域名填写的IP地址与ip参数不一致".to_string());
+        }
+        ip = Some(address);
+        domain = None;
+    }
+    if domain.is_none() && ip.is_none() {
+        return Err("需要提供domain或ip参数".to_string());
+    }
+
+    let port = match text(&query.port) {
+        Some(port) => Some(
+            port.parse::<u16>()
+                .map_err(|_| format!("无效的端口: {}", port))?,
+        ),
+        None => None,
+    };
+    let udp = match text(&query.network).map(|n| n.to_lowercase()).as_deref() {
+        None | Some("tcp") => false,
+        Some("udp") => true,
+        Some(other) => return Err(format!("network只能是tcp或udp: {}", other)),
+    };
+
+    Ok(Connection {
+        domain,
+        ip,
+        port,
+        udp,
+    })
+}
+
+fn bad_request(message: String) -> HttpResponse {
+    HttpResponse::BadRequest()
+        .content_type("text/plain; charset=utf-8")
+        .body(message)
+}
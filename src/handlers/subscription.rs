@@ -1,10 +1,38 @@
-use crate::handlers::common::{SubscriptionQuery, decrypt_secret, get_client_ip};
+use crate::compression::{Encoding, negotiate};
+use crate::credentials::Credentials;
+use crate::handlers::common::{SubscriptionQuery, get_client_ip, subscriber_uuid};
 use crate::models::AppState;
+use crate::render::{Document, UserContext};
+use crate::snapshot::describe_variant;
+use crate::storage::if_none_match;
 
+use ntex::http::Method;
+use ntex::http::body::{Body, BodySize, MessageBody};
+use ntex::util::Bytes;
 use ntex::web::types::{Query, State};
 use ntex::web::{HttpRequest, HttpResponse, Responder};
+use std::error::Error;
+use std::task::{Context, Poll};
 use tracing::{debug, error, info, warn};
-use uuid::Uuid;
+
+// HEAD响应的响应体: 没有内容，只声明GET时的长度，长度未知时不返回Content-Length
+struct HeadBody(Option<usize>);
+
+impl MessageBody for HeadBody {
+    fn size(&self) -> BodySize {
+        match self.0 {
+            Some(size) => BodySize::Sized(size as u64),
+            None => BodySize::None,
+        }
+    }
+
+    fn poll_next_chunk(
+        &mut self,
+        _: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Box<dyn Error>>>> {
+        Poll::Ready(None)
+    }
+}
 
 // 订阅请求处理函数
 pub async fn handle_subscription(
@@ -12,125 +40,190 @@ pub async fn handle_subscription(
     query: Query<SubscriptionQuery>,
     state: State<AppState>,
 ) -> impl Responder {
-    let client_ip = get_client_ip(&req);
+    serve(&req, &query, &state, Document::Config)
+}
 
-    // 检查是否提供了secret参数
-    let encrypted_secret = match &query.secret {
-        Some(secret) => secret,
-        None => {
-            debug!("[{}] 缺少secret参数，访问被禁止", client_ip);
-            return HttpResponse::NoContent().finish();
-        }
-    };
+// 节点订阅处理函数，只返回proxies，用作第三方配置中的proxy-providers
+pub async fn handle_proxy_provider(
+    req: HttpRequest,
+    query: Query<SubscriptionQuery>,
+    state: State<AppState>,
+) -> impl Responder {
+    serve(&req, &query, &state, Document::Proxies)
+}
 
-    let use_compression = query.zstd;
+fn serve(
+    req: &HttpRequest,
+    query: &SubscriptionQuery,
+    state: &AppState,
+    document: Document,
+) -> HttpResponse {
+    let client_ip = get_client_ip(req);
+    let app_config = state.app_config.load_full();
 
-    // 解密secret获得uuid
-    let uuid_str = match decrypt_secret(&encrypted_secret, &state.app_config.encryption_key) {
-        Ok(decrypted) => {
-            debug!("[{}] 成功解密secret获得uuid: {}", client_ip, decrypted);
-            decrypted
-        }
-        Err(e) => {
-            warn!("[{}] secret解密失败，访问被禁止: {}", client_ip, e);
-            return HttpResponse::NoContent().finish();
-        }
+    // zstd=true参数保留兼容，强制使用zstd；否则按Accept-Encoding协商
+    let encoding = if query.zstd {
+        Encoding::Zstd
+    } else {
+        negotiate(
+            req.headers()
+                .get("Accept-Encoding")
+                .and_then(|value| value.to_str().ok()),
+        )
     };
 
-    // 验证解密后的UUID格式
-    let uuid = match Uuid::parse_str(&uuid_str) {
-        Ok(uuid) => uuid,
-        Err(_) => {
-            warn!(
-                "[{}] 解密后的数据不是有效UUID，访问被禁止: {}",
-                client_ip, uuid_str
-            );
-            return HttpResponse::NoContent().finish();
-        }
+    // 解密secret获得uuid
+    let Some(uuid) = subscriber_uuid(query.secret.as_deref(), &app_config, &client_ip) else {
+        return HttpResponse::NoContent().finish();
     };
 
-    info!("[{}] 收到订阅请求: {}", client_ip, uuid);
+    match document {
+        Document::Config => info!("[{}] 收到订阅请求: {}", client_ip, uuid),
+        Document::Proxies => info!("[{}] 收到节点订阅请求: {}", client_ip, uuid),
+    }
 
     // 处理订阅配置
-    // 1. 获取配置读锁并克隆配置以便修改
-    let clash_config = {
-        let config_guard = state.clash_config.read().await;
-        config_guard.clone()
-    };
-    let mut clash_config = clash_config;
-
-    debug!("[{}] 使用缓存的配置文件", client_ip);
-
-    // 2. 替换proxies中的uuid字段
-    if let Some(ref mut proxies) = clash_config.proxies {
-        for proxy in proxies.iter_mut() {
-            if proxy.contains_key("uuid") {
-                proxy.insert(
-                    "uuid".to_string(),
-                    serde_yaml_ng::Value::String(uuid.to_string()),
-                );
-            }
-        }
-        debug!("[{}] 替换了代理的UUID为: {}", client_ip, uuid);
-    } else {
-        warn!("[{}] 配置中没有找到proxies字段", client_ip);
-    }
+    // 1. 获取当前配置快照，整个请求使用同一版本
+    let snapshot = state.snapshot.load_full();
+    let uuid_str = uuid.to_string();
 
-    // 3. 使用 serde_yaml_ng 将配置序列化为返回给用户的 YAML 字符串
-    let yaml_body = match serde_yaml_ng::to_string(&clash_config) {
-        Ok(yaml) => yaml,
+    // 命名模板: profile参数指定的模板需在允许列表中，未指定时使用用户的默认模板
+    let profile = match snapshot
+        .users
+        .select_profile(&uuid_str, query.profile.as_deref())
+    {
+        Ok(profile) => profile,
         Err(e) => {
-            error!("配置序列化失败: {}", e);
+            warn!("[{}] {}，访问被禁止", client_ip, e);
             return HttpResponse::NoContent().finish();
         }
     };
 
-    let original_size = yaml_body.len();
-
-    // 4. 根据查询参数决定是否压缩
-    if use_compression {
-        // 使用 zstd 压缩 YAML 内容
-        let compressed_data = match zstd::encode_all(yaml_body.as_bytes(), 3) {
-            Ok(data) => {
-                let compressed_size = data.len();
-                let compression_ratio =
-                    (1.0 - (compressed_size as f64 / original_size as f64)) * 100.0;
-                info!(
-                    "[{}] 生成订阅配置(zstd)，大小: {} byte，压缩率: {:.1}%",
-                    client_ip, compressed_size, compression_ratio
-                );
-                data
-            }
+    // 按用户的命名模板、套餐和覆盖配置选择模板，未分配时使用全部节点的基础配置
+    let template = snapshot.template_for(&uuid_str, profile, document);
+
+    debug!(
+        "[{}] 使用预渲染的订阅模板，配置版本: {}，{}",
+        client_ip,
+        snapshot.version,
+        describe_variant(
+            profile,
+            snapshot.users.plan_name(&uuid_str),
+            &snapshot.users.overlays_for(&uuid_str)
+        )
+    );
+
+    // 2. 计算用户在模板中的取值: UUID、按协议生成的节点密码、reality short-id和模板变量
+    let user = snapshot.users.user(&uuid_str);
+    let credentials =
+        match Credentials::new(&app_config.credentials, &app_config.encryption_key, user) {
+            Ok(credentials) => credentials,
             Err(e) => {
-                error!("[{}] 压缩失败: {}", client_ip, e);
+                error!("[{}] 无法生成节点密码: {}", client_ip, e);
                 return HttpResponse::NoContent().finish();
             }
         };
+    let connection_info = req.connection_info();
+    let values = template.values(&UserContext {
+        uuid: &uuid_str,
+        user,
+        host: connection_info.host(),
+        scheme: connection_info.scheme(),
+        credentials: &credentials,
+    });
 
-        // 返回压缩后的响应
-        HttpResponse::Ok()
-            .content_type("application/x-yaml; charset=utf-8")
-            .header("Content-Encoding", "zstd")
+    // 3. 协商缓存: ETag由配置内容、用户取值和编码方式决定，匹配时返回304，无需生成内容
+    let etag = template.etag(&values, encoding.as_str());
+    if let Some(if_none_match_header) = req
+        .headers()
+        .get("If-None-Match")
+        .and_then(|value| value.to_str().ok())
+        && if_none_match(if_none_match_header, &etag)
+    {
+        info!("[{}] 订阅配置未变化，返回304", client_ip);
+        return HttpResponse::NotModified()
+            .header("ETag", etag)
+            .header("Cache-Control", "no-cache")
+            .header("Vary", "Accept-Encoding")
+            .header("X-Config-Version", snapshot.version.to_string())
+            .finish();
+    }
+
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type("application/x-yaml; charset=utf-8")
+        .header("Cache-Control", "no-cache")
+        .header("ETag", etag)
+        .header("Vary", "Accept-Encoding")
+        .header("X-Config-Version", snapshot.version.to_string());
+    // 节点文档由proxy-providers按自己的interval更新，不作为订阅文件下载
+    if document == Document::Config {
+        response
             .header(
                 "Content-Disposition",
                 "attachment; filename=RayGo; filename*=UTF-8''RayGo%E8%AE%A2%E9%98%85",
             )
-            .header("Cache-Control", "no-cache")
+            .header("profile-update-interval", "6");
+    }
+
+    // HEAD请求只用于检查更新，不拼接也不压缩内容
+    // 压缩结果已缓存时返回其长度，否则只在X-Original-Size中给出原始大小
+    if req.method() == Method::HEAD {
+        let original_size = template.size(&values);
+        let size = match encoding {
+            Encoding::Identity => Some(original_size),
+            _ => {
+                response
+                    .header("Content-Encoding", encoding.as_str())
+                    .header("X-Original-Size", original_size.to_string());
+                template.compressed_size(&values, encoding, &app_config.compression)
+            }
+        };
+        info!("[{}] HEAD请求，只返回订阅配置响应头", client_ip);
+        return response.body(Body::from_message(HeadBody(size)));
+    }
+
+    // 4. 按协商结果压缩，压缩结果按用户缓存，配置重载后失效
+    if encoding != Encoding::Identity {
+        let (compressed_data, original_size, cached) =
+            match template.compressed(&values, encoding, &app_config.compression) {
+                Ok(result) => result,
+                Err(e) => {
+                    error!("[{}] {}", client_ip, e);
+                    return HttpResponse::NoContent().finish();
+                }
+            };
+        let compression_ratio =
+            (1.0 - (compressed_data.len() as f64 / original_size as f64)) * 100.0;
+        info!(
+            "[{}] {}订阅配置({})，大小: {} byte，压缩率: {:.1}%",
+            client_ip,
+            if cached { "使用缓存的" } else { "生成" },
+            encoding.as_str(),
+            compressed_data.len(),
+            compression_ratio
+        );
+
+        // 返回压缩后的响应
+        response
+            .header("Content-Encoding", encoding.as_str())
             .header("X-Original-Size", original_size.to_string())
-            .header("profile-update-interval", "6")
             .body(compressed_data)
     } else {
+        // 5. 在模板的取值位置拼接用户UUID和节点密码
+        let yaml_body = template.splice(&values);
+        let original_size = yaml_body.len();
+        debug!(
+            "[{}] 替换了{}处代理的UUID和密码，UUID为: {}，按用户生成的密码和short-id: {}处",
+            client_ip,
+            template.placeholders(),
+            uuid,
+            template.credentials()
+        );
+
         // 返回未压缩的响应
         info!("[{}] 生成订阅配置，大小: {} byte", client_ip, original_size);
 
-        HttpResponse::Ok()
-            .content_type("application/x-yaml; charset=utf-8")
-            .header(
-                "Content-Disposition",
-                "attachment; filename=RayGo; filename*=UTF-8''RayGo%E8%AE%A2%E9%98%85",
-            )
-            .header("Cache-Control", "no-cache")
-            .header("profile-update-interval", "6")
-            .body(yaml_body)
+        response.body(yaml_body)
     }
 }
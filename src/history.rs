@@ -0,0 +1,297 @@
+use crate::include;
+use crate::models::HistoryConfig;
+use crate::storage::{content_hash, write_atomic};
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use std::sync::OnceLock;
+use time::{OffsetDateTime, UtcOffset};
+use tracing::{debug, warn};
+
+// 历史版本存放目录
+pub const HISTORY_DIR: &str = "config/history";
+
+// 历史版本时间戳使用的本地时区偏移量，启动时设置
+static LOCAL_OFFSET: OnceLock<UtcOffset> = OnceLock::new();
+
+// 设置本地时区偏移量（多线程环境下无法再安全获取，需在启动时调用）
+pub fn set_local_offset(offset: UtcOffset) {
+    let _ = LOCAL_OFFSET.set(offset);
+}
+
+fn now_local() -> OffsetDateTime {
+    let offset = LOCAL_OFFSET.get().copied().unwrap_or(UtcOffset::UTC);
+    OffsetDateTime::now_utc().to_offset(offset)
+}
+
+// 历史版本元数据
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VersionMeta {
+    pub id: String,
+    pub timestamp: String,
+    pub unix_time: i64,
+    pub client_ip: String,
+    pub action: String, // startup / save / reload / rollback
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    pub size: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>, // 内容哈希，用于匹配编辑器加载的版本
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>, // 同时保存的被引用文件
+}
+
+// 版本中保存的被引用文件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct IncludedFile {
+    name: String,
+    content: String,
+}
+
+fn content_path(id: &str) -> String {
+    format!("{}/{}.yml", HISTORY_DIR, id)
+}
+
+fn meta_path(id: &str) -> String {
+    format!("{}/{}.meta.yml", HISTORY_DIR, id)
+}
+
+fn files_path(id: &str) -> String {
+    format!("{}/{}.files.yml", HISTORY_DIR, id)
+}
+
+// 校验版本ID，防止路径穿越
+fn check_id(id: &str) -> Result<(), String> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(format!("无效的版本ID: {}", id));
+    }
+    Ok(())
+}
+
+// 记录一个新的历史版本，content为clash.yml的内容，其引用的文件从磁盘读取后一起保存
+// 所有文件都与最新版本相同时跳过
+pub async fn record(
+    config: &HistoryConfig,
+    content: &str,
+    client_ip: &str,
+    action: &str,
+    message: Option<String>,
+) -> Result<Option<VersionMeta>, String> {
+    if !config.enabled {
+        return Ok(None);
+    }
+
+    tokio::fs::create_dir_all(HISTORY_DIR)
+        .await
+        .map_err(|e| format!("创建历史目录失败: {}", e))?;
+
+    let files: Vec<IncludedFile> = include::read_referenced(content)?
+        .into_iter()
+        .map(|(name, content)| IncludedFile { name, content })
+        .collect();
+
+    // 与最新版本内容一致时不重复记录
+    if let Some(latest) = list().await?.first()
+        && read_version(&latest.id).await.ok().as_deref() == Some(content)
+        && read_included(&latest.id).await.ok().as_ref() == Some(&files)
+    {
+        debug!("配置内容与历史版本 {} 相同，跳过记录", latest.id);
+        return Ok(None);
+    }
+
+    let now = now_local();
+    let base_id = format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}",
+        now.year(),
+        now.month() as u8,
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second(),
+        now.millisecond()
+    );
+
+    // 同一毫秒内的多次记录追加序号
+    let mut id = base_id.clone();
+    let mut sequence = 1;
+    while tokio::fs::try_exists(content_path(&id))
+        .await
+        .unwrap_or(false)
+    {
+        id = format!("{}-{}", base_id, sequence);
+        sequence += 1;
+    }
+
+    let meta = VersionMeta {
+        id: id.clone(),
+        timestamp: format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}",
+            now.year(),
+            now.month() as u8,
+            now.day(),
+            now.hour(),
+            now.minute(),
+            now.second(),
+            format_offset(now.offset())
+        ),
+        unix_time: now.unix_timestamp(),
+        client_ip: client_ip.to_string(),
+        action: action.to_string(),
+        message: message.filter(|m| !m.trim().is_empty()),
+        size: content.len(),
+        hash: Some(content_hash(content)),
+        files: files.iter().map(|file| file.name.clone()).collect(),
+    };
+
+    let meta_yaml =
+        serde_yaml_ng::to_string(&meta).map_err(|e| format!("序列化版本信息失败: {}", e))?;
+    write_atomic(content_path(&id), content.as_bytes())
+        .await
+        .map_err(|e| format!("写入历史版本失败: {}", e))?;
+    if !files.is_empty() {
+        let files_yaml = serde_yaml_ng::to_string(&files)
+            .map_err(|e| format!("序列化被引用的文件失败: {}", e))?;
+        write_atomic(files_path(&id), files_yaml.as_bytes())
+            .await
+            .map_err(|e| format!("写入历史版本失败: {}", e))?;
+    }
+    write_atomic(meta_path(&id), meta_yaml.as_bytes())
+        .await
+        .map_err(|e| format!("写入历史版本信息失败: {}", e))?;
+
+    prune(config).await;
+
+    Ok(Some(meta))
+}
+
+fn format_offset(offset: UtcOffset) -> String {
+    if offset.is_utc() {
+        return "Z".to_string();
+    }
+    let (hours, minutes, _) = offset.as_hms();
+    let sign = if offset.is_negative() { '-' } else { '+' };
+    format!("{}{:02}:{:02}", sign, hours.abs(), minutes.abs())
+}
+
+// 列出所有历史版本，按时间从新到旧排序
+pub async fn list() -> Result<Vec<VersionMeta>, String> {
+    let mut entries = match tokio::fs::read_dir(HISTORY_DIR).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("读取历史目录失败: {}", e)),
+    };
+
+    let mut versions = Vec::new();
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .map_err(|e| format!("读取历史目录失败: {}", e))?
+    {
+        let file_name = entry.file_name();
+        let Some(name) = file_name.to_str() else {
+            continue;
+        };
+        if !name.ends_with(".meta.yml") {
+            continue;
+        }
+        match tokio::fs::read_to_string(entry.path()).await {
+            Ok(content) => match serde_yaml_ng::from_str::<VersionMeta>(&content) {
+                Ok(meta) => versions.push(meta),
+                Err(e) => warn!("历史版本信息 {} 解析失败: {}", name, e),
+            },
+            Err(e) => warn!("读取历史版本信息 {} 失败: {}", name, e),
+        }
+    }
+
+    versions.sort_by(|a, b| b.id.cmp(&a.id));
+    Ok(versions)
+}
+
+// 读取历史版本的配置内容
+pub async fn read_version(id: &str) -> Result<String, String> {
+    check_id(id)?;
+    tokio::fs::read_to_string(content_path(id))
+        .await
+        .map_err(|e| format!("读取历史版本 {} 失败: {}", id, e))
+}
+
+// 读取历史版本中保存的被引用文件（文件名，内容），没有引用文件的版本返回空列表
+pub async fn read_files(id: &str) -> Result<Vec<(String, String)>, String> {
+    check_id(id)?;
+    Ok(read_included(id)
+        .await?
+        .into_iter()
+        .map(|file| (file.name, file.content))
+        .collect())
+}
+
+async fn read_included(id: &str) -> Result<Vec<IncludedFile>, String> {
+    match tokio::fs::read_to_string(files_path(id)).await {
+        Ok(content) => serde_yaml_ng::from_str(&content)
+            .map_err(|e| format!("历史版本 {} 的被引用文件解析失败: {}", id, e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("读取历史版本 {} 失败: {}", id, e)),
+    }
+}
+
+// 按内容哈希查找历史版本中clash.yml或被引用文件的内容
+pub async fn find_by_hash(hash: &str) -> Option<String> {
+    let versions = list().await.ok()?;
+    for meta in &versions {
+        if meta.hash.as_deref() == Some(hash) {
+            return read_version(&meta.id).await.ok();
+        }
+        if meta.files.is_empty() {
+            continue;
+        }
+        let files = read_included(&meta.id).await.ok()?;
+        if let Some(file) = files
+            .into_iter()
+            .find(|file| content_hash(&file.content) == hash)
+        {
+            return Some(file.content);
+        }
+    }
+    None
+}
+
+// 按保留策略清理旧版本，最新版本始终保留
+async fn prune(config: &HistoryConfig) {
+    let versions = match list().await {
+        Ok(versions) => versions,
+        Err(e) => {
+            warn!("清理历史版本失败: {}", e);
+            return;
+        }
+    };
+
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let max_age = config.max_age_days.map(|days| days as i64 * 86400);
+
+    for (index, meta) in versions.iter().enumerate().skip(1) {
+        let too_many = config.max_versions > 0 && index >= config.max_versions;
+        let too_old = max_age.is_some_and(|age| now - meta.unix_time > age);
+        if !too_many && !too_old {
+            continue;
+        }
+        debug!("清理历史版本: {}", meta.id);
+        for path in [content_path(&meta.id), meta_path(&meta.id)] {
+            if let Err(e) = tokio::fs::remove_file(&path).await {
+                warn!("删除历史版本文件 {} 失败: {}", path, e);
+            }
+        }
+        if !meta.files.is_empty()
+            && let Err(e) = tokio::fs::remove_file(files_path(&meta.id)).await
+        {
+            warn!("删除历史版本文件 {} 失败: {}", files_path(&meta.id), e);
+        }
+    }
+}
+
+// 生成两个版本之间的统一格式diff
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(old_label, new_label)
+        .to_string()
+}
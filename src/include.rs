@@ -0,0 +1,240 @@
+use crate::models::ClashConfig;
+use crate::servers;
+use serde_yaml_ng::{Mapping, Value};
+use std::path::Path;
+
+// 被引用的文件所在目录，引用时只能写该目录下的文件名
+pub const INCLUDE_DIR: &str = "config";
+// 主配置文件名
+pub const MAIN_FILE: &str = "clash.yml";
+// 顶层字段，列出的文件的顶层字段合并到引用它的文件中
+pub const INCLUDES_KEY: &str = "includes";
+// 引用单个文件作为字段值的YAML标签，如 rules: !include rules.yml
+const INCLUDE_TAG: &str = "include";
+// 有其他用途、不能被引用的文件
+const RESERVED_FILES: [&str; 3] = [MAIN_FILE, "app.yml", "users.yml"];
+
+// 展开引用后的配置
+#[derive(Debug)]
+pub struct Resolved {
+    pub value: Value,
+    // 被引用的文件名，按首次引用的顺序排列
+    pub files: Vec<String>,
+}
+
+// 检查引用的文件名: 只能是config目录下的yml/yaml文件，不能包含路径
+pub fn check_name(name: &str) -> Result<(), String> {
+    let is_yaml = Path::new(name)
+        .extension()
+        .is_some_and(|ext| ext == "yml" || ext == "yaml");
+    if name.contains(['/', '\\']) || name.starts_with('.') || !is_yaml {
+        return Err(format!(
+            "引用的文件只能是{}目录下的yml/yaml文件名: {}",
+            INCLUDE_DIR, name
+        ));
+    }
+    if RESERVED_FILES.contains(&name) {
+        return Err(format!("不能引用{}", name));
+    }
+    Ok(())
+}
+
+// 配置中是否使用了引用，未使用时调用方可以直接解析原文以保留错误位置
+pub fn has_includes(value: &Value) -> bool {
+    let listed = value
+        .as_mapping()
+        .is_some_and(|mapping| mapping.contains_key(INCLUDES_KEY));
+    listed || has_include_tag(value)
+}
+
+fn has_include_tag(value: &Value) -> bool {
+    match value {
+        Value::Tagged(tagged) => tagged.tag == INCLUDE_TAG || has_include_tag(&tagged.value),
+        Value::Sequence(items) => items.iter().any(has_include_tag),
+        Value::Mapping(mapping) => mapping.values().any(has_include_tag),
+        _ => false,
+    }
+}
+
+// 展开配置文件中的引用，name为该文件的名称，用于错误信息和循环检测
+// overrides 为尚未写入磁盘的文件（文件名，内容），如编辑器中的修改或历史版本，解析时代替磁盘上的内容
+pub fn resolve(name: &str, root: Value, overrides: &[(&str, &str)]) -> Result<Resolved, String> {
+    let mut resolver = Resolver {
+        overrides,
+        stack: Vec::new(),
+        files: Vec::new(),
+    };
+    let value = resolver.document(name, root, true)?;
+    Ok(Resolved {
+        value,
+        files: resolver.files,
+    })
+}
+
+// 解析主配置并展开引用，同时返回被引用的文件名
+pub fn parse_config(content: &str) -> Result<(ClashConfig, Vec<String>), String> {
+    parse_file(MAIN_FILE, content)
+}
+
+// 解析完整的配置文件并展开引用，name为该文件的名称
+// 引用了服务端配置的节点同时补全字段
+pub fn parse_file(name: &str, content: &str) -> Result<(ClashConfig, Vec<String>), String> {
+    let root = serde_yaml_ng::from_str::<Value>(content).map_err(|e| e.to_string())?;
+    let (mut config, files) = if !has_includes(&root) {
+        let config = serde_yaml_ng::from_str(content).map_err(|e| e.to_string())?;
+        (config, Vec::new())
+    } else {
+        let resolved = resolve(name, root, &[])?;
+        let config = serde_yaml_ng::from_value(resolved.value).map_err(|e| e.to_string())?;
+        (config, resolved.files)
+    };
+    servers::expand(&mut config).map_err(|errors| {
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        errors.join("; ")
+    })?;
+    Ok((config, files))
+}
+
+// 主配置引用的文件名，按首次引用的顺序排列，未使用引用时为空
+pub fn referenced_files(content: &str, overrides: &[(&str, &str)]) -> Result<Vec<String>, String> {
+    let root = serde_yaml_ng::from_str::<Value>(content)
+        .map_err(|e| format!("{}: YAML解析失败: {}", MAIN_FILE, e))?;
+    if !has_includes(&root) {
+        return Ok(Vec::new());
+    }
+    Ok(resolve(MAIN_FILE, root, overrides)?.files)
+}
+
+// 主配置引用的文件及其在磁盘上的内容，用于记录历史版本
+pub fn read_referenced(content: &str) -> Result<Vec<(String, String)>, String> {
+    referenced_files(content, &[])?
+        .into_iter()
+        .map(|name| {
+            let path = Path::new(INCLUDE_DIR).join(&name);
+            std::fs::read_to_string(&path)
+                .map(|content| (name, content))
+                .map_err(|e| format!("无法读取{}: {}", path.display(), e))
+        })
+        .collect()
+}
+
+struct Resolver<'a> {
+    overrides: &'a [(&'a str, &'a str)],
+    // 当前引用链，用于检测循环引用
+    stack: Vec<String>,
+    files: Vec<String>,
+}
+
+impl Resolver<'_> {
+    // 读取并解析被引用的文件
+    fn read(&mut self, name: &str) -> Result<Value, String> {
+        check_name(name)?;
+        if self.stack.iter().any(|file| file == name) {
+            return Err(format!("循环引用: {} -> {}", self.stack.join(" -> "), name));
+        }
+
+        let content = match self.overrides.iter().find(|(file, _)| *file == name) {
+            Some((_, content)) => content.to_string(),
+            None => {
+                let path = Path::new(INCLUDE_DIR).join(name);
+                std::fs::read_to_string(&path)
+                    .map_err(|e| format!("无法读取{}: {}", path.display(), e))?
+            }
+        };
+        let value = serde_yaml_ng::from_str::<Value>(&content)
+            .map_err(|e| format!("{}: YAML解析失败: {}", name, e))?;
+
+        if !self.files.iter().any(|file| file == name) {
+            self.files.push(name.to_string());
+        }
+        Ok(value)
+    }
+
+    // 展开一个文件中的引用，root表示该文件的顶层字段属于配置顶层
+    fn document(&mut self, name: &str, value: Value, root: bool) -> Result<Value, String> {
+        self.stack.push(name.to_string());
+        let result = match value {
+            Value::Mapping(mapping) if root => self.root(name, mapping).map(Value::Mapping),
+            value => self.tags(value),
+        };
+        self.stack.pop();
+        result
+    }
+
+    // 展开顶层映射: 先展开各字段中的!include，再合并includes列出的文件
+    fn root(&mut self, name: &str, mut mapping: Mapping) -> Result<Mapping, String> {
+        let includes = match mapping.remove(INCLUDES_KEY) {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::String(file)) => vec![file],
+            Some(Value::Sequence(items)) => items
+                .into_iter()
+                .map(|item| match item {
+                    Value::String(file) => Ok(file),
+                    _ => Err(format!("{}: {}中只能填写文件名", name, INCLUDES_KEY)),
+                })
+                .collect::<Result<_, _>>()?,
+            Some(_) => return Err(format!("{}: {}必须是文件名列表", name, INCLUDES_KEY)),
+        };
+
+        let mut resolved = Mapping::new();
+        for (key, value) in mapping {
+            resolved.insert(key, self.tags(value)?);
+        }
+
+        for file in includes {
+            let value = self.read(&file)?;
+            let fields = match self.document(&file, value, true)? {
+                Value::Null => Mapping::new(),
+                Value::Mapping(fields) => fields,
+                _ => {
+                    return Err(format!(
+                        "{}: 通过{}引用的文件顶层必须是映射",
+                        file, INCLUDES_KEY
+                    ));
+                }
+            };
+            for (key, value) in fields {
+                if resolved.contains_key(&key) {
+                    return Err(format!(
+                        "{}: 字段{}已在{}或其他引用的文件中定义",
+                        file,
+                        key.as_str().unwrap_or_default(),
+                        name
+                    ));
+                }
+                resolved.insert(key, value);
+            }
+        }
+        Ok(resolved)
+    }
+
+    // 递归替换!include标签为被引用文件的内容
+    fn tags(&mut self, value: Value) -> Result<Value, String> {
+        match value {
+            Value::Tagged(tagged) if tagged.tag == INCLUDE_TAG => {
+                let Value::String(file) = tagged.value else {
+                    return Err("!include的值必须是文件名".to_string());
+                };
+                let value = self.read(&file)?;
+                self.document(&file, value, false)
+            }
+            Value::Tagged(mut tagged) => {
+                tagged.value = self.tags(tagged.value)?;
+                Ok(Value::Tagged(tagged))
+            }
+            Value::Sequence(items) => items
+                .into_iter()
+                .map(|item| self.tags(item))
+                .collect::<Result<_, _>>()
+                .map(Value::Sequence),
+            Value::Mapping(mapping) => {
+                let mut resolved = Mapping::new();
+                for (key, value) in mapping {
+                    resolved.insert(key, self.tags(value)?);
+                }
+                Ok(Value::Mapping(resolved))
+            }
+            value => Ok(value),
+        }
+    }
+}
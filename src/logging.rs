@@ -0,0 +1,53 @@
+use std::sync::OnceLock;
+use time::UtcOffset;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::time::OffsetTime;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{Registry, reload};
+
+// 日志级别热更新句柄，初始化后才可用
+static LEVEL_HANDLE: OnceLock<reload::Handle<LevelFilter, Registry>> = OnceLock::new();
+
+// 解析日志级别字符串
+pub fn parse_level(level: &str) -> Option<LevelFilter> {
+    match level.to_lowercase().as_str() {
+        "error" => Some(LevelFilter::ERROR),
+        "warn" => Some(LevelFilter::WARN),
+        "info" => Some(LevelFilter::INFO),
+        "debug" => Some(LevelFilter::DEBUG),
+        "trace" => Some(LevelFilter::TRACE),
+        _ => None,
+    }
+}
+
+// 初始化tracing subscriber - 使用RFC 3339本地时间戳格式，日志级别可在运行时修改
+pub fn init(level: LevelFilter, offset: UtcOffset) {
+    let time_format = time::format_description::parse(
+        "[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond digits:3]",
+    )
+    .expect("时间格式字符串无效");
+    let timer = OffsetTime::new(offset, time_format);
+
+    let (level_layer, handle) = reload::Layer::new(level);
+    tracing_subscriber::registry()
+        .with(level_layer)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_timer(timer)
+                .with_ansi(true),
+        )
+        .init();
+
+    let _ = LEVEL_HANDLE.set(handle);
+}
+
+// 运行时修改日志级别
+pub fn set_level(level: &str) -> Result<(), String> {
+    let filter = parse_level(level).ok_or_else(|| format!("无效的日志级别: {}", level))?;
+    let handle = LEVEL_HANDLE
+        .get()
+        .ok_or_else(|| "日志系统尚未初始化".to_string())?;
+    handle
+        .reload(filter)
+        .map_err(|e| format!("修改日志级别失败: {}", e))
+}
@@ -1,11 +1,34 @@
+mod compression;
+mod credentials;
+mod geo;
 mod handlers;
+mod history;
+mod include;
+mod logging;
 mod models;
+mod mrs;
+mod overlay;
+mod profile;
+mod provider;
+mod reload;
+mod render;
+mod rules;
+mod ruleset;
+mod servers;
+mod share_link;
+mod simulate;
+mod snapshot;
+mod storage;
+mod template;
+mod upstream;
+mod users;
+mod validation;
 
+use arc_swap::ArcSwap;
 use ntex::web::{self, App, HttpServer};
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::info;
-use tracing_subscriber::fmt::time::OffsetTime;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
 
 use crate::models::AppState;
 
@@ -31,6 +54,14 @@ async fn main() -> std::io::Result<()> {
             ));
         }
     };
+    if let Err(e) = app_config
+        .credentials
+        .check()
+        .and_then(|_| upstream::check(&app_config.upstreams))
+    {
+        eprintln!("配置文件格式错误: {}", e);
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e));
+    }
 
     // 读取Clash配置文件
     let clash_config_content = match tokio::fs::read_to_string("config/clash.yml").await {
@@ -41,9 +72,9 @@ async fn main() -> std::io::Result<()> {
         }
     };
 
-    // 解析Clash配置文件
-    let clash_config: models::ClashConfig = match serde_yaml_ng::from_str(&clash_config_content) {
-        Ok(config) => config,
+    // 解析Clash配置文件，展开其中引用的文件
+    let (clash_config, included_files) = match include::parse_config(&clash_config_content) {
+        Ok(parsed) => parsed,
         Err(e) => {
             eprintln!("Clash配置文件格式错误: {}", e);
             return Err(std::io::Error::new(
@@ -53,60 +84,169 @@ async fn main() -> std::io::Result<()> {
         }
     };
 
+    // 读取用户列表
+    let users = match users::UsersStore::load().await {
+        Ok(users) => users,
+        Err(e) => {
+            eprintln!("用户列表加载失败: {}", e);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e));
+        }
+    };
+
+    // 读取覆盖配置
+    let overlays = match overlay::load().await {
+        Ok(overlays) => overlays,
+        Err(e) => {
+            eprintln!("覆盖配置加载失败: {}", e);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e));
+        }
+    };
+
+    // 读取命名模板
+    let profiles = match profile::load().await {
+        Ok(profiles) => profiles,
+        Err(e) => {
+            eprintln!("命名模板加载失败: {}", e);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e));
+        }
+    };
+
+    // 读取托管规则集
+    let hosted_rule_sets = match ruleset::load().await {
+        Ok(rule_sets) => rule_sets,
+        Err(e) => {
+            eprintln!("规则集加载失败: {}", e);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e));
+        }
+    };
+
+    // 读取上游订阅上次成功拉取的节点
+    let upstreams = upstream::load_cached(&app_config.upstreams).await;
+    let has_upstreams = !upstreams.is_empty();
+
+    // 预渲染订阅模板，上游节点与当前配置冲突时先不使用，等待下次拉取
+    let built = snapshot::Snapshot::build(
+        clash_config.clone(),
+        profiles.clone(),
+        users.clone(),
+        overlays.clone(),
+        upstreams,
+        hosted_rule_sets.clone(),
+    )
+    .or_else(|e| {
+        if !has_upstreams {
+            return Err(e);
+        }
+        eprintln!("上游订阅的缓存节点无法使用，暂不加载: {}", e);
+        snapshot::Snapshot::build(
+            clash_config,
+            profiles,
+            users,
+            overlays,
+            Default::default(),
+            hosted_rule_sets,
+        )
+    });
+    let mut snapshot = match built {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            eprintln!("订阅模板渲染失败: {}", e);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e));
+        }
+    };
+    snapshot.version = 1;
+
     // 创建应用状态
     let app_state = AppState {
-        app_config: app_config.clone(),
-        clash_config: Arc::new(RwLock::new(clash_config)),
+        app_config: Arc::new(ArcSwap::from_pointee(app_config.clone())),
+        snapshot: Arc::new(ArcSwap::from_pointee(snapshot)),
+        config_write_lock: Arc::new(Mutex::new(())),
     };
 
     // 根据配置文件初始化日志系统
-    let log_level = match app_config.log_level.to_lowercase().as_str() {
-        "error" => tracing::Level::ERROR,
-        "warn" => tracing::Level::WARN,
-        "info" => tracing::Level::INFO,
-        "debug" => tracing::Level::DEBUG,
-        "trace" => tracing::Level::TRACE,
-        _ => {
-            eprintln!(
-                "无效的日志级别: {}，使用默认级别 info",
-                app_config.log_level
-            );
-            tracing::Level::INFO
-        }
-    };
+    let log_level = logging::parse_level(&app_config.log_level).unwrap_or_else(|| {
+        eprintln!(
+            "无效的日志级别: {}，使用默认级别 info",
+            app_config.log_level
+        );
+        tracing::level_filters::LevelFilter::INFO
+    });
 
     // 先初始化tracing subscriber - 使用RFC 3339本地时间戳格式
     let offset = time::UtcOffset::current_local_offset().expect("无法获取本地时区偏移量");
-    let time_format = time::format_description::parse(
-        "[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond digits:3]",
-    )
-    .expect("时间格式字符串无效");
-    let timer = OffsetTime::new(offset, time_format);
-    tracing_subscriber::fmt()
-        .with_max_level(log_level)
-        .with_timer(timer)
-        .with_ansi(true)
-        .init();
+    history::set_local_offset(offset);
+    logging::init(log_level, offset);
 
     // 初始化log桥接以捕获第三方库日志
     tracing_log::LogTracer::init_with_filter(tracing_log::log::LevelFilter::Warn)
         .expect("设置logger失败");
 
     info!("📝 日志级别设置为: {}", app_config.log_level);
+
+    // 记录启动时的配置版本，内容未变化时不会重复记录
+    if let Err(e) = history::record(
+        &app_config.history,
+        &clash_config_content,
+        "本地",
+        "startup",
+        None,
+    )
+    .await
+    {
+        warn!("记录配置历史版本失败: {}", e);
+    }
+
+    // 启动配置文件监听和SIGHUP重载（按配置开关）
+    reload::spawn(app_state.clone(), &app_config.reload);
+    // 启动上游订阅定时拉取
+    upstream::spawn(app_state.clone(), &app_config.upstreams);
+
     info!("🚀 RayGo-sub 服务器已启动");
     info!("🗃️  配置文件已加载到内存缓存");
+    if !included_files.is_empty() {
+        info!("📎 引用的配置文件: {}", included_files.join(", "));
+    }
     info!(
         "📍 服务地址: http://{}:{}",
         app_config.addr, app_config.port
     );
-    info!("   - GET /?secret=XXXX - 获取对应的clash订阅文件");
+    info!("   - GET /?secret=XXXX - 获取对应的clash订阅文件（支持HEAD和If-None-Match）");
+    info!("   - GET /providers/proxies?secret=XXXX - 只包含节点的proxy-providers订阅");
+    info!("   - GET /providers/rules/<名称>?secret=XXXX - 模板中inline规则集的rule-providers订阅");
+    info!("   - GET /rulesets/<名称> - config/rulesets/下的托管规则集");
     info!("   - GET /config?auth=XXXX - 配置编辑页面");
     info!("   - POST /config/reload - 热重载配置文件");
+    info!("   - POST /config/validate - 校验配置内容");
+    info!("   - GET /config/history - 配置历史版本列表");
+    info!("   - GET /config/history/diff - 对比配置历史版本");
+    info!("   - POST /config/history/rollback - 回滚配置历史版本");
+    info!("   - GET /config/credentials - 导出按用户生成的节点密码");
+    info!("   - GET /config/overlays - 覆盖配置列表");
+    info!("   - GET /config/merged - 查看合并覆盖配置后的结果");
+    info!("   - GET /config/simulate - 模拟域名或IP命中的规则和代理组");
+    info!("   - POST /config/import - 从分享链接导入节点");
 
     HttpServer::new(move || {
         App::new()
             .state(app_state.clone())
             .route("/", web::get().to(handlers::handle_subscription))
+            .route("/", web::head().to(handlers::handle_subscription))
+            .route(
+                "/providers/proxies",
+                web::get().to(handlers::handle_proxy_provider),
+            )
+            .route(
+                "/providers/proxies",
+                web::head().to(handlers::handle_proxy_provider),
+            )
+            .route(
+                "/providers/rules/{name}",
+                web::get().to(handlers::handle_rule_provider),
+            )
+            .route(
+                "/rulesets/{name}",
+                web::get().to(handlers::handle_hosted_rule_set),
+            )
             .route("/favicon.svg", web::get().to(handlers::handle_favicon))
             .route("/config", web::get().to(handlers::handle_config_get))
             .route("/config", web::post().to(handlers::handle_config_post))
@@ -114,6 +254,39 @@ async fn main() -> std::io::Result<()> {
                 "/config/reload",
                 web::post().to(handlers::handle_config_reload),
             )
+            .route(
+                "/config/validate",
+                web::post().to(handlers::handle_config_validate),
+            )
+            .route(
+                "/config/history",
+                web::get().to(handlers::handle_history_list),
+            )
+            .route(
+                "/config/history/diff",
+                web::get().to(handlers::handle_history_diff),
+            )
+            .route(
+                "/config/history/rollback",
+                web::post().to(handlers::handle_history_rollback),
+            )
+            .route(
+                "/config/credentials",
+                web::get().to(handlers::handle_credentials_export),
+            )
+            .route(
+                "/config/overlays",
+                web::get().to(handlers::handle_overlay_list),
+            )
+            .route(
+                "/config/merged",
+                web::get().to(handlers::handle_overlay_merged),
+            )
+            .route("/config/simulate", web::get().to(handlers::handle_simulate))
+            .route(
+                "/config/import",
+                web::post().to(handlers::handle_config_import),
+            )
             .default_service(web::route().to(handlers::handle_other))
     })
     .bind((app_config.addr.as_str(), app_config.port))?
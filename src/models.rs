@@ -1,7 +1,10 @@
+use crate::credentials::Protocol;
+use crate::snapshot::Snapshot;
+use arc_swap::ArcSwap;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::Mutex;
 
 // DNS配置结构体
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -217,6 +220,17 @@ pub struct ProxyGroup {
     pub strategy: Option<String>,
 }
 
+impl ProxyGroup {
+    // 代理组是否没有任何节点来源
+    pub fn has_no_members(&self) -> bool {
+        self.proxies.as_ref().is_none_or(|p| p.is_empty())
+            && self.use_providers.as_ref().is_none_or(|p| p.is_empty())
+            && self.include_all != Some(true)
+            && self.include_all_proxies != Some(true)
+            && self.include_all_providers != Some(true)
+    }
+}
+
 // 代理提供者类型枚举
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "lowercase")]
@@ -274,7 +288,7 @@ pub struct ProxyProvider {
 }
 
 // 规则类型枚举
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "SCREAMING-KEBAB-CASE")]
 pub enum RuleType {
     Domain,
@@ -332,7 +346,7 @@ pub enum RuleProviderType {
 }
 
 // 规则行为枚举
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum RuleBehavior {
     Domain,
@@ -615,6 +629,16 @@ pub struct AppConfig {
     pub log_level: String,
     pub encryption_key: String, // Base64编码的32字节密钥
     pub admin_password: String, // 管理员密码
+    #[serde(default)]
+    pub history: HistoryConfig, // 配置历史版本
+    #[serde(default)]
+    pub reload: ReloadConfig, // 自动重载
+    #[serde(default)]
+    pub compression: CompressionConfig, // 订阅响应压缩级别
+    #[serde(default)]
+    pub credentials: CredentialsConfig, // 按用户生成的节点密码
+    #[serde(default)]
+    pub upstreams: Vec<UpstreamConfig>, // 定时拉取并追加到proxies的上游订阅
 }
 
 // 默认日志级别
@@ -622,10 +646,196 @@ fn default_log_level() -> String {
     "info".to_string()
 }
 
+// 配置历史版本保留策略
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    // 最多保留的版本数量，0表示不限制
+    #[serde(default = "default_history_max_versions")]
+    pub max_versions: usize,
+    // 版本最长保留天数，不设置表示不限制
+    #[serde(default)]
+    pub max_age_days: Option<u64>,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_versions: default_history_max_versions(),
+            max_age_days: None,
+        }
+    }
+}
+
+// 订阅响应压缩级别
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompressionConfig {
+    // zstd压缩级别 1-22
+    #[serde(default = "default_zstd_level")]
+    pub zstd_level: i32,
+    // gzip压缩级别 0-9
+    #[serde(default = "default_gzip_level")]
+    pub gzip_level: u32,
+    // brotli压缩级别 0-11
+    #[serde(default = "default_brotli_level")]
+    pub brotli_level: u32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            zstd_level: default_zstd_level(),
+            gzip_level: default_gzip_level(),
+            brotli_level: default_brotli_level(),
+        }
+    }
+}
+
+// 按用户生成节点密码和reality short-id
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CredentialsConfig {
+    // 启用的协议: trojan、ss、hysteria2、tuic，默认不启用
+    #[serde(default)]
+    pub protocols: Vec<Protocol>,
+    // 按用户生成reality-opts.short-id
+    #[serde(default)]
+    pub short_id: bool,
+    // 生成的short-id长度（十六进制字符数），2-16之间的偶数
+    #[serde(default = "default_short_id_length")]
+    pub short_id_length: usize,
+    // HMAC密钥，不设置时使用encryption_key
+    #[serde(default)]
+    pub key: Option<String>,
+}
+
+impl Default for CredentialsConfig {
+    fn default() -> Self {
+        Self {
+            protocols: Vec::new(),
+            short_id: false,
+            short_id_length: default_short_id_length(),
+            key: None,
+        }
+    }
+}
+
+impl CredentialsConfig {
+    // 检查设置是否有效
+    pub fn check(&self) -> Result<(), String> {
+        if !(2..=16).contains(&self.short_id_length) || !self.short_id_length.is_multiple_of(2) {
+            return Err(format!(
+                "short_id_length应为2-16之间的偶数: {}",
+                self.short_id_length
+            ));
+        }
+        Ok(())
+    }
+}
+
+// 上游订阅: 定时拉取后重命名节点并追加到proxies
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UpstreamConfig {
+    // 名称，同时用作缓存文件名
+    pub name: String,
+    // 订阅地址，支持http和https
+    pub url: String,
+    // 节点名称前缀，不设置时为"[名称] "
+    #[serde(default)]
+    pub prefix: Option<String>,
+    // 为节点添加的标签，用于套餐过滤
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // 拉取间隔（秒）
+    #[serde(default = "default_upstream_interval")]
+    pub interval: u64,
+    // 请求超时（秒）
+    #[serde(default = "default_upstream_timeout")]
+    pub timeout: u64,
+    // 请求使用的User-Agent，部分订阅服务按此返回Clash格式
+    #[serde(default = "default_upstream_user_agent")]
+    pub user_agent: String,
+}
+
+impl UpstreamConfig {
+    // 节点名称前缀
+    pub fn prefix(&self) -> String {
+        self.prefix
+            .clone()
+            .unwrap_or_else(|| format!("[{}] ", self.name))
+    }
+}
+
+fn default_upstream_interval() -> u64 {
+    3600
+}
+
+fn default_upstream_timeout() -> u64 {
+    15
+}
+
+fn default_upstream_user_agent() -> String {
+    "clash.meta".to_string()
+}
+
+fn default_short_id_length() -> usize {
+    8
+}
+
+fn default_zstd_level() -> i32 {
+    3
+}
+
+fn default_gzip_level() -> u32 {
+    6
+}
+
+fn default_brotli_level() -> u32 {
+    5
+}
+
+// 自动重载配置，默认全部关闭
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReloadConfig {
+    // 监听config/clash.yml文件变化并自动重载
+    #[serde(default)]
+    pub watch: bool,
+    // 文件变化的防抖时间（毫秒）
+    #[serde(default = "default_reload_debounce_ms")]
+    pub debounce_ms: u64,
+    // 收到SIGHUP信号时重载
+    #[serde(default)]
+    pub sighup: bool,
+}
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        Self {
+            watch: false,
+            debounce_ms: default_reload_debounce_ms(),
+            sighup: false,
+        }
+    }
+}
+
+fn default_reload_debounce_ms() -> u64 {
+    500
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_history_max_versions() -> usize {
+    50
+}
+
 // 应用状态结构体，用于缓存配置文件内容
 #[allow(dead_code)]
 #[derive(Clone)]
 pub struct AppState {
-    pub app_config: AppConfig,
-    pub clash_config: Arc<RwLock<ClashConfig>>,
+    pub app_config: Arc<ArcSwap<AppConfig>>, // 应用配置，app.yml重载时替换
+    pub snapshot: Arc<ArcSwap<Snapshot>>,    // 当前配置快照，重载时整体替换
+    pub config_write_lock: Arc<Mutex<()>>,   // 串行化配置文件写入
 }
@@ -0,0 +1,512 @@
+use crate::models::RuleBehavior;
+use std::collections::BTreeSet;
+use std::net::IpAddr;
+
+// mihomo的MRS二进制规则集格式，整个文件经过zstd压缩:
+// 魔数"MRS\x01"、behavior(1字节)、规则数(i64)、扩展数据长度(i64)和扩展数据，之后是规则内容
+// domain规则为按字节反转的域名构成的简洁前缀树，ipcidr规则为合并后的IP范围，整数均为大端序
+const MAGIC: [u8; 4] = *b"MRS\x01";
+// 规则内容的格式版本
+const SET_VERSION: u8 = 1;
+// 接近mihomo转换时使用的SpeedBestCompression
+const ZSTD_LEVEL: i32 = 11;
+
+// domain前缀树中的通配符
+const COMPLEX_WILDCARD: char = '+';
+
+// 将domain或ipcidr规则集编译为MRS格式
+// 编译后重新解码并与规则内容比对，保证输出能被还原
+pub fn compile(behavior: &RuleBehavior, payload: &[String]) -> Result<Vec<u8>, String> {
+    if payload.is_empty() {
+        return Err("规则集为空，无法生成mrs格式".to_string());
+    }
+
+    let (behavior_byte, expected, body) = match behavior {
+        RuleBehavior::Domain => {
+            let keys = domain_keys(payload);
+            let body = DomainSet::build(&keys).to_bytes();
+            (0u8, Decoded::Domain(keys), body)
+        }
+        RuleBehavior::Ipcidr => {
+            let ranges = ip_ranges(payload)?;
+            let body = ranges_to_bytes(&ranges);
+            (1u8, Decoded::Ipcidr(ranges), body)
+        }
+        RuleBehavior::Classical => {
+            return Err("mrs格式只支持domain和ipcidr规则集".to_string());
+        }
+    };
+
+    let mut data = Vec::with_capacity(body.len() + 21);
+    data.extend_from_slice(&MAGIC);
+    data.push(behavior_byte);
+    data.extend_from_slice(&(payload.len() as i64).to_be_bytes());
+    // 扩展数据，目前为空
+    data.extend_from_slice(&0i64.to_be_bytes());
+    data.extend_from_slice(&body);
+    let output = zstd::encode_all(data.as_slice(), ZSTD_LEVEL)
+        .map_err(|e| format!("zstd压缩失败: {}", e))?;
+
+    if decode(&output)? != expected {
+        return Err("mrs规则集编译结果校验失败".to_string());
+    }
+    Ok(output)
+}
+
+// MRS文件解码后的规则内容
+#[derive(Debug, PartialEq, Eq)]
+enum Decoded {
+    // 前缀树中的域名
+    Domain(BTreeSet<String>),
+    // 合并后的IP范围
+    Ipcidr(Vec<IpRange>),
+}
+
+// 解码MRS文件
+fn decode(data: &[u8]) -> Result<Decoded, String> {
+    let data = zstd::decode_all(data).map_err(|e| format!("zstd解压失败: {}", e))?;
+    let mut reader = Reader { data: &data };
+    if reader.take(4)? != MAGIC {
+        return Err("不是mrs格式".to_string());
+    }
+    let behavior = reader.byte()?;
+    reader.int()?;
+    let extra = reader.size()?;
+    reader.take(extra)?;
+    if reader.byte()? != SET_VERSION {
+        return Err("不支持的mrs规则内容版本".to_string());
+    }
+
+    let decoded = match behavior {
+        0 => {
+            let leaves = reader.words()?;
+            let label_bitmap = reader.words()?;
+            let labels_len = reader.size()?;
+            let labels = reader.take(labels_len)?.to_vec();
+            let set = DomainSet {
+                leaves,
+                label_bitmap,
+                labels,
+            };
+            Decoded::Domain(set.keys()?)
+        }
+        1 => {
+            let count = reader.size()?;
+            let mut ranges = Vec::with_capacity(count.min(reader.data.len() / 32));
+            for _ in 0..count {
+                let from = u128::from_be_bytes(reader.take(16)?.try_into().unwrap_or_default());
+                let to = u128::from_be_bytes(reader.take(16)?.try_into().unwrap_or_default());
+                ranges.push(IpRange::from_mapped(from, to));
+            }
+            Decoded::Ipcidr(ranges)
+        }
+        other => return Err(format!("不支持的mrs behavior: {}", other)),
+    };
+    if !reader.data.is_empty() {
+        return Err("mrs文件末尾有多余数据".to_string());
+    }
+    Ok(decoded)
+}
+
+// 按大端序读取MRS数据
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.data.len() < len {
+            return Err("mrs文件不完整".to_string());
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn int(&mut self) -> Result<i64, String> {
+        let bytes = self.take(8)?;
+        Ok(i64::from_be_bytes(bytes.try_into().unwrap_or_default()))
+    }
+
+    fn size(&mut self) -> Result<usize, String> {
+        usize::try_from(self.int()?).map_err(|_| "mrs文件中的长度无效".to_string())
+    }
+
+    fn words(&mut self) -> Result<Vec<u64>, String> {
+        let count = self.size()?;
+        let bytes = self.take(count.checked_mul(8).ok_or("mrs文件中的长度无效")?)?;
+        Ok(bytes
+            .chunks_exact(8)
+            .map(|word| u64::from_be_bytes(word.try_into().unwrap_or_default()))
+            .collect())
+    }
+}
+
+// 规则集中的域名按mihomo的域名树规则展开后的集合:
+// +.example.com 展开为 example.com 和 +.example.com 两项；.example.com 只匹配子域名，在树中记为 +.example.com
+fn domain_keys(payload: &[String]) -> BTreeSet<String> {
+    let mut keys = BTreeSet::new();
+    for entry in payload {
+        let domain = entry.trim().to_lowercase();
+        if let Some(rest) = domain.strip_prefix("+.") {
+            keys.insert(rest.to_string());
+            keys.insert(domain);
+        } else if domain.starts_with('.') {
+            keys.insert(format!("{}{}", COMPLEX_WILDCARD, domain));
+        } else {
+            keys.insert(domain);
+        }
+    }
+    keys
+}
+
+// 按字符反转域名，前缀树中相同后缀的域名共享节点
+fn reverse(domain: &str) -> String {
+    domain.chars().rev().collect()
+}
+
+// 简洁前缀树（succinct trie），与mihomo的DomainSet布局一致:
+// 按层序遍历节点，每个节点的子节点标签依次对应label_bitmap中的0，节点结束处为1；leaves标记以该节点结束的域名
+struct DomainSet {
+    leaves: Vec<u64>,
+    label_bitmap: Vec<u64>,
+    labels: Vec<u8>,
+}
+
+impl DomainSet {
+    fn build(domains: &BTreeSet<String>) -> Self {
+        // 反转后按字节排序，相同前缀的键连续，且短的在前
+        let mut keys: Vec<Vec<u8>> = domains.iter().map(|d| reverse(d).into_bytes()).collect();
+        keys.sort();
+        keys.dedup();
+
+        let mut set = DomainSet {
+            leaves: Vec::new(),
+            label_bitmap: Vec::new(),
+            labels: Vec::new(),
+        };
+        // 队列元素: (起始下标, 结束下标, 列)
+        let mut queue = vec![(0usize, keys.len(), 0usize)];
+        let mut label_index = 0;
+        let mut i = 0;
+        while i < queue.len() {
+            let (mut start, end, col) = queue[i];
+            if keys[start].len() == col {
+                start += 1;
+                set_bit(&mut set.leaves, i, true);
+            }
+            let mut j = start;
+            while j < end {
+                let from = j;
+                let label = keys[from][col];
+                while j < end && keys[j][col] == label {
+                    j += 1;
+                }
+                queue.push((from, j, col + 1));
+                set.labels.push(label);
+                set_bit(&mut set.label_bitmap, label_index, false);
+                label_index += 1;
+            }
+            set_bit(&mut set.label_bitmap, label_index, true);
+            label_index += 1;
+            i += 1;
+        }
+        set
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![SET_VERSION];
+        for words in [&self.leaves, &self.label_bitmap] {
+            data.extend_from_slice(&(words.len() as i64).to_be_bytes());
+            for word in words.iter() {
+                data.extend_from_slice(&word.to_be_bytes());
+            }
+        }
+        data.extend_from_slice(&(self.labels.len() as i64).to_be_bytes());
+        data.extend_from_slice(&self.labels);
+        data
+    }
+
+    // 按层序遍历还原树中的所有域名
+    fn keys(&self) -> Result<BTreeSet<String>, String> {
+        let invalid = || "mrs文件中的域名树无效".to_string();
+        let mut prefixes: Vec<Vec<u8>> = vec![Vec::new()];
+        let mut node = 0;
+        let mut label = 0;
+        let total = self.label_bitmap.len() * 64;
+        for index in 0..total {
+            if node >= prefixes.len() {
+                break;
+            }
+            if get_bit(&self.label_bitmap, index) {
+                node += 1;
+                continue;
+            }
+            let byte = *self.labels.get(label).ok_or_else(invalid)?;
+            let mut prefix = prefixes[node].clone();
+            prefix.push(byte);
+            prefixes.push(prefix);
+            label += 1;
+        }
+        if label != self.labels.len() {
+            return Err(invalid());
+        }
+
+        let mut keys = BTreeSet::new();
+        for (index, prefix) in prefixes.into_iter().enumerate() {
+            if get_bit(&self.leaves, index) {
+                let key = String::from_utf8(prefix).map_err(|_| invalid())?;
+                keys.insert(reverse(&key));
+            }
+        }
+        Ok(keys)
+    }
+}
+
+fn set_bit(words: &mut Vec<u64>, index: usize, value: bool) {
+    while index >> 6 >= words.len() {
+        words.push(0);
+    }
+    words[index >> 6] |= (value as u64) << (index & 63);
+}
+
+fn get_bit(words: &[u64], index: usize) -> bool {
+    words
+        .get(index >> 6)
+        .is_some_and(|word| word & (1 << (index & 63)) != 0)
+}
+
+// IP范围，IPv4和IPv6分开合并，IPv4在前
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct IpRange {
+    v6: bool,
+    from: u128,
+    to: u128,
+}
+
+// IPv4地址在MRS中写为IPv4映射的IPv6地址 ::ffff:a.b.c.d
+const V4_MAPPED: u128 = 0xffff << 32;
+
+impl IpRange {
+    fn from_mapped(from: u128, to: u128) -> Self {
+        let v4 = from >> 32 == 0xffff && to >> 32 == 0xffff;
+        match v4 {
+            true => IpRange {
+                v6: false,
+                from: from & 0xffff_ffff,
+                to: to & 0xffff_ffff,
+            },
+            false => IpRange { v6: true, from, to },
+        }
+    }
+
+    fn to_mapped(self) -> (u128, u128) {
+        match self.v6 {
+            true => (self.from, self.to),
+            false => (V4_MAPPED | self.from, V4_MAPPED | self.to),
+        }
+    }
+}
+
+// 将CIDR列表合并为有序、互不重叠且不相邻的IP范围
+fn ip_ranges(payload: &[String]) -> Result<Vec<IpRange>, String> {
+    let mut ranges = Vec::with_capacity(payload.len());
+    for entry in payload {
+        let invalid = || format!("{}: 不是有效的CIDR", entry);
+        let (address, prefix) = entry.trim().split_once('/').ok_or_else(invalid)?;
+        let address: IpAddr = address.parse().map_err(|_| invalid())?;
+        let prefix: u32 = prefix.parse().map_err(|_| invalid())?;
+        let (v6, bits, value) = match address {
+            IpAddr::V4(v4) => (false, 32, u32::from(v4) as u128),
+            IpAddr::V6(v6) => (true, 128, u128::from(v6)),
+        };
+        if prefix > bits {
+            return Err(invalid());
+        }
+        let host_mask = match prefix {
+            0 if bits == 128 => u128::MAX,
+            _ => (1u128 << (bits - prefix)) - 1,
+        };
+        let from = value & !host_mask;
+        ranges.push(IpRange {
+            v6,
+            from,
+            to: from | host_mask,
+        });
+    }
+    ranges.sort();
+
+    let mut merged: Vec<IpRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if last.v6 == range.v6 && range.from <= last.to.saturating_add(1) => {
+                last.to = last.to.max(range.to);
+            }
+            _ => merged.push(range),
+        }
+    }
+    Ok(merged)
+}
+
+fn ranges_to_bytes(ranges: &[IpRange]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(9 + ranges.len() * 32);
+    data.push(SET_VERSION);
+    data.extend_from_slice(&(ranges.len() as i64).to_be_bytes());
+    for range in ranges {
+        let (from, to) = range.to_mapped();
+        data.extend_from_slice(&from.to_be_bytes());
+        data.extend_from_slice(&to.to_be_bytes());
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(entries: &[&str]) -> Vec<String> {
+        entries.iter().map(|entry| entry.to_string()).collect()
+    }
+
+    fn compile_decode(behavior: RuleBehavior, entries: &[&str]) -> Decoded {
+        let output = compile(&behavior, &payload(entries)).unwrap();
+        decode(&output).unwrap()
+    }
+
+    fn v4(from: &str, to: &str) -> IpRange {
+        let parse = |ip: &str| u32::from(ip.parse::<std::net::Ipv4Addr>().unwrap()) as u128;
+        IpRange {
+            v6: false,
+            from: parse(from),
+            to: parse(to),
+        }
+    }
+
+    fn v6(from: &str, to: &str) -> IpRange {
+        let parse = |ip: &str| u128::from(ip.parse::<std::net::Ipv6Addr>().unwrap());
+        IpRange {
+            v6: true,
+            from: parse(from),
+            to: parse(to),
+        }
+    }
+
+    #[test]
+    fn domain_keys_follow_mihomo_wildcards() {
+        let decoded = compile_decode(
+            RuleBehavior::Domain,
+            &[
+                "+.example.com",
+                ".sub.test",
+                "*.wild.org",
+                "Plain.NET",
+                " spaced.io ",
+                "example.com",
+            ],
+        );
+        let expected = [
+            "+.example.com",
+            "+.sub.test",
+            "*.wild.org",
+            "example.com",
+            "plain.net",
+            "spaced.io",
+        ];
+        let expected = expected.iter().map(|key| key.to_string()).collect();
+        assert_eq!(decoded, Decoded::Domain(expected));
+    }
+
+    #[test]
+    fn domain_set_layout() {
+        // 反转后为"ba"和"ca": 根节点有b、c两个子节点，各自再有一个a子节点
+        let keys = ["ab", "ac"].iter().map(|key| key.to_string()).collect();
+        let set = DomainSet::build(&keys);
+        assert_eq!(set.labels, b"bcaa");
+        assert_eq!(set.label_bitmap, [0b1_1101_0100]);
+        assert_eq!(set.leaves, [0b11000]);
+        assert_eq!(set.keys().unwrap(), keys);
+    }
+
+    #[test]
+    fn header_records_behavior_and_count() {
+        let output = compile(
+            &RuleBehavior::Ipcidr,
+            &payload(&["10.0.0.0/8", "10.0.0.0/9"]),
+        )
+        .unwrap();
+        let data = zstd::decode_all(output.as_slice()).unwrap();
+        assert_eq!(data[..4], MAGIC);
+        assert_eq!(data[4], 1);
+        // 规则数为原始条目数，不是合并后的范围数
+        assert_eq!(data[5..13], 2i64.to_be_bytes());
+        assert_eq!(data[13..21], 0i64.to_be_bytes());
+        assert_eq!(data[21], SET_VERSION);
+        assert_eq!(data[22..30], 1i64.to_be_bytes());
+        // IPv4写为IPv4映射的IPv6地址
+        let from = u128::from_be_bytes(data[30..46].try_into().unwrap());
+        assert_eq!(
+            from,
+            u128::from(std::net::Ipv4Addr::new(10, 0, 0, 0).to_ipv6_mapped())
+        );
+    }
+
+    #[test]
+    fn ipcidr_ranges_are_merged() {
+        let decoded = compile_decode(
+            RuleBehavior::Ipcidr,
+            &[
+                "192.168.1.0/24",
+                "192.168.1.128/25",
+                "10.0.0.0/9",
+                "10.128.0.0/9",
+                "10.1.0.0/16",
+                "172.16.0.1/12",
+                "11.0.0.0/8",
+                "2001:db8::/33",
+                "2001:db8:8000::/33",
+                "2001:db8::1/128",
+                "fe80::/10",
+            ],
+        );
+        let expected = vec![
+            // 相邻的10.0.0.0/9、10.128.0.0/9和11.0.0.0/8合并为一个范围
+            v4("10.0.0.0", "11.255.255.255"),
+            // 主机位被清除
+            v4("172.16.0.0", "172.31.255.255"),
+            v4("192.168.1.0", "192.168.1.255"),
+            v6("2001:db8::", "2001:db8:ffff:ffff:ffff:ffff:ffff:ffff"),
+            v6("fe80::", "febf:ffff:ffff:ffff:ffff:ffff:ffff:ffff"),
+        ];
+        assert_eq!(decoded, Decoded::Ipcidr(expected));
+    }
+
+    #[test]
+    fn ipcidr_zero_prefix_covers_everything() {
+        let decoded = compile_decode(
+            RuleBehavior::Ipcidr,
+            &["1.2.3.0/24", "0.0.0.0/0", "2001:db8::/32", "::/0"],
+        );
+        let expected = vec![
+            v4("0.0.0.0", "255.255.255.255"),
+            v6("::", "ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff"),
+        ];
+        assert_eq!(decoded, Decoded::Ipcidr(expected));
+    }
+
+    #[test]
+    fn rejects_unsupported_payloads() {
+        assert!(compile(&RuleBehavior::Domain, &[]).is_err());
+        assert!(compile(&RuleBehavior::Classical, &payload(&["DOMAIN,example.com"])).is_err());
+        for entry in ["10.0.0.0", "10.0.0.0/33", "::/129", "example.com/8"] {
+            assert!(
+                compile(&RuleBehavior::Ipcidr, &payload(&[entry])).is_err(),
+                "{}",
+                entry
+            );
+        }
+    }
+}
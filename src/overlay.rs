@@ -0,0 +1,133 @@
+use crate::models::ClashConfig;
+use serde_yaml_ng::{Mapping, Value};
+use std::collections::BTreeMap;
+
+// 覆盖配置目录，每个文件是一个覆盖配置，文件名（不含扩展名）为其名称
+pub const OVERLAYS_DIR: &str = "config/overlays";
+
+// 支持 prepend-<字段> / append-<字段> 的列表字段
+const LIST_KEYS: [&str; 3] = ["rules", "proxies", "proxy-groups"];
+
+// 所有覆盖配置，按名称排序
+pub type Overlays = BTreeMap<String, Mapping>;
+
+// 读取覆盖配置目录，目录不存在时返回空列表
+pub async fn load() -> Result<Overlays, String> {
+    let mut overlays = Overlays::new();
+    let mut entries = match tokio::fs::read_dir(OVERLAYS_DIR).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(overlays),
+        Err(e) => return Err(format!("无法读取{}: {}", OVERLAYS_DIR, e)),
+    };
+
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .map_err(|e| format!("无法读取{}: {}", OVERLAYS_DIR, e))?
+    {
+        let path = entry.path();
+        let is_yaml = path
+            .extension()
+            .is_some_and(|ext| ext == "yml" || ext == "yaml");
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        if !is_yaml || name.starts_with('.') {
+            continue;
+        }
+
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|e| format!("无法读取{}: {}", path.display(), e))?;
+        let overlay = parse(&content).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if overlays.insert(name.to_string(), overlay).is_some() {
+            return Err(format!("覆盖配置名称重复: {}", name));
+        }
+    }
+
+    Ok(overlays)
+}
+
+// 解析单个覆盖配置，空文件视为空的覆盖配置
+pub fn parse(content: &str) -> Result<Mapping, String> {
+    match serde_yaml_ng::from_str::<Value>(content).map_err(|e| format!("解析失败: {}", e))? {
+        Value::Null => Ok(Mapping::new()),
+        Value::Mapping(mapping) => Ok(mapping),
+        _ => Err("覆盖配置的顶层必须是映射".to_string()),
+    }
+}
+
+// 按顺序将覆盖配置合并到基础配置上
+// prepend-<字段> / append-<字段> 在列表前后插入，映射逐层合并，其他值直接替换
+pub fn merge(base: &ClashConfig, overlays: &[(&str, &Mapping)]) -> Result<ClashConfig, String> {
+    let Value::Mapping(mut merged) =
+        serde_yaml_ng::to_value(base).map_err(|e| format!("配置序列化失败: {}", e))?
+    else {
+        return Err("配置序列化结果不是映射".to_string());
+    };
+
+    for (name, overlay) in overlays {
+        apply(&mut merged, overlay).map_err(|e| format!("覆盖配置 {}: {}", name, e))?;
+    }
+
+    serde_yaml_ng::from_value(Value::Mapping(merged))
+        .map_err(|e| format!("合并后的配置格式错误: {}", e))
+}
+
+fn apply(target: &mut Mapping, overlay: &Mapping) -> Result<(), String> {
+    for (key, value) in overlay {
+        let name = key.as_str().unwrap_or_default();
+        let list_op = [("prepend-", true), ("append-", false)]
+            .into_iter()
+            .find_map(|(prefix, prepend)| {
+                let field = name.strip_prefix(prefix)?;
+                LIST_KEYS.contains(&field).then_some((field, prepend))
+            });
+
+        match list_op {
+            Some((field, prepend)) => {
+                let Value::Sequence(items) = value else {
+                    return Err(format!("{}的值必须是列表", name));
+                };
+                let entry = target
+                    .entry(Value::String(field.to_string()))
+                    .or_insert_with(|| Value::Sequence(Vec::new()));
+                if entry.is_null() {
+                    *entry = Value::Sequence(Vec::new());
+                }
+                let Value::Sequence(existing) = entry else {
+                    return Err(format!("基础配置中的{}不是列表", field));
+                };
+                if prepend {
+                    existing.splice(0..0, items.iter().cloned());
+                } else {
+                    existing.extend(items.iter().cloned());
+                }
+            }
+            None => match target.get_mut(key) {
+                Some(existing) => deep_merge(existing, value),
+                None => {
+                    target.insert(key.clone(), value.clone());
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+// 映射逐层合并，其他类型（包括列表）直接替换
+fn deep_merge(target: &mut Value, overlay: &Value) {
+    match (target, overlay) {
+        (Value::Mapping(target), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match target.get_mut(key) {
+                    Some(existing) => deep_merge(existing, value),
+                    None => {
+                        target.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (target, overlay) => *target = overlay.clone(),
+    }
+}
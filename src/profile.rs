@@ -0,0 +1,71 @@
+use crate::include;
+use crate::models::ClashConfig;
+use crate::validation::validate_config;
+use std::collections::BTreeMap;
+
+// 命名模板目录，每个文件是一份完整的Clash配置，文件名（不含扩展名）为其名称
+pub const PROFILES_DIR: &str = "config/profiles";
+
+// 表示config/clash.yml的模板名称
+pub const DEFAULT_PROFILE: &str = "default";
+
+// 所有命名模板，按名称排序
+pub type Profiles = BTreeMap<String, ClashConfig>;
+
+// 读取并校验命名模板目录，目录不存在时返回空列表
+// 模板与clash.yml一样可以通过!include和includes引用config目录下的文件
+pub async fn load() -> Result<Profiles, String> {
+    let mut profiles = Profiles::new();
+    let mut entries = match tokio::fs::read_dir(PROFILES_DIR).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(profiles),
+        Err(e) => return Err(format!("无法读取{}: {}", PROFILES_DIR, e)),
+    };
+
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .map_err(|e| format!("无法读取{}: {}", PROFILES_DIR, e))?
+    {
+        let path = entry.path();
+        let is_yaml = path
+            .extension()
+            .is_some_and(|ext| ext == "yml" || ext == "yaml");
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        if !is_yaml || name.starts_with('.') {
+            continue;
+        }
+        if name == DEFAULT_PROFILE {
+            return Err(format!(
+                "{}: 模板名称{}保留给clash.yml",
+                path.display(),
+                DEFAULT_PROFILE
+            ));
+        }
+
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|e| format!("无法读取{}: {}", path.display(), e))?;
+        let file_name = path
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .unwrap_or(name);
+        let (config, _) = include::parse_file(&format!("profiles/{}", file_name), &content)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let report = validate_config(&config, &content);
+        if !report.valid {
+            return Err(format!(
+                "{}校验失败: {}",
+                path.display(),
+                report.error_summary()
+            ));
+        }
+        if profiles.insert(name.to_string(), config).is_some() {
+            return Err(format!("模板名称重复: {}", name));
+        }
+    }
+
+    Ok(profiles)
+}
@@ -0,0 +1,115 @@
+use crate::models::{ClashConfig, RuleBehavior, RuleFormat, RuleProviderType};
+use crate::mrs;
+use crate::storage::content_hash;
+use ntex::util::Bytes;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, OnceLock};
+
+// 可以作为rule-providers下发的规则集，按名称排序
+pub type RuleSets = BTreeMap<String, RuleSet>;
+
+// 规则集: 第三方配置通过type: http的rule-providers引用
+#[derive(Debug, Clone)]
+pub struct RuleSet {
+    pub behavior: RuleBehavior,
+    pub payload: Vec<String>,
+    // 规则内容哈希，用于ETag
+    content_hash: String,
+    // 编译后的mrs格式，首次请求时生成，随快照在重载时丢弃
+    mrs: Arc<OnceLock<Result<Bytes, String>>>,
+}
+
+// 只比较规则内容，不比较缓存
+impl PartialEq for RuleSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.behavior == other.behavior && self.payload == other.payload
+    }
+}
+
+impl Eq for RuleSet {}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    payload: &'a [String],
+}
+
+impl RuleSet {
+    pub fn new(behavior: RuleBehavior, payload: Vec<String>) -> Self {
+        Self {
+            content_hash: content_hash(&payload.join("\n")),
+            behavior,
+            payload,
+            mrs: Arc::default(),
+        }
+    }
+
+    // 是否可以编译为mrs格式，mihomo只支持非空的domain和ipcidr规则集
+    pub fn supports_mrs(&self) -> bool {
+        !matches!(self.behavior, RuleBehavior::Classical) && !self.payload.is_empty()
+    }
+
+    // 按rule-providers的format输出规则集内容
+    pub fn body(&self, format: &RuleFormat) -> Result<Bytes, String> {
+        match format {
+            RuleFormat::Yaml => serde_yaml_ng::to_string(&Payload {
+                payload: &self.payload,
+            })
+            .map(Bytes::from)
+            .map_err(|e| format!("规则集序列化失败: {}", e)),
+            RuleFormat::Text => {
+                let mut body = self.payload.join("\n");
+                body.push('\n');
+                Ok(Bytes::from(body))
+            }
+            RuleFormat::Mrs => self
+                .mrs
+                .get_or_init(|| mrs::compile(&self.behavior, &self.payload).map(Bytes::from))
+                .clone(),
+        }
+    }
+
+    // 由规则内容、格式和编码决定的强ETag
+    pub fn etag(&self, format: &RuleFormat, encoding: &str) -> String {
+        let digest = content_hash(&format!(
+            "{}\0{}\0{}",
+            self.content_hash,
+            format_name(format),
+            encoding
+        ));
+        format!("\"{}\"", &digest[..32])
+    }
+}
+
+pub fn behavior_name(behavior: &RuleBehavior) -> &'static str {
+    match behavior {
+        RuleBehavior::Domain => "domain",
+        RuleBehavior::Ipcidr => "ipcidr",
+        RuleBehavior::Classical => "classical",
+    }
+}
+
+pub fn format_name(format: &RuleFormat) -> &'static str {
+    match format {
+        RuleFormat::Yaml => "yaml",
+        RuleFormat::Text => "text",
+        RuleFormat::Mrs => "mrs",
+    }
+}
+
+// 配置中的inline规则集，与rule-providers中的名称相同
+pub fn inline_rule_sets(config: &ClashConfig) -> RuleSets {
+    config
+        .rule_providers
+        .iter()
+        .flatten()
+        .filter(|(_, provider)| matches!(provider.provider_type, RuleProviderType::Inline))
+        .map(|(name, provider)| {
+            let payload = provider.payload.clone().unwrap_or_default();
+            (
+                name.clone(),
+                RuleSet::new(provider.behavior.clone(), payload),
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,585 @@
+use crate::geo::GEO_DIR;
+use crate::handlers::common::decode_key;
+use crate::history;
+use crate::logging;
+use crate::models::{AppConfig, AppState, ClashConfig, ReloadConfig};
+use crate::overlay::{self, OVERLAYS_DIR};
+use crate::profile::{self, PROFILES_DIR, Profiles};
+use crate::ruleset::{self, RULESETS_DIR};
+use crate::servers::SERVERS_DIR;
+use crate::snapshot::Snapshot;
+use crate::upstream;
+use crate::users::{USERS_PATH, UsersStore};
+use crate::validation::{Severity, validate_content};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use serde_yaml_ng::{Mapping, Value};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+// 应用配置文件路径
+pub const APP_CONFIG_PATH: &str = "config/app.yml";
+// Clash配置文件路径
+pub const CLASH_CONFIG_PATH: &str = "config/clash.yml";
+
+// 重载结果
+pub enum ReloadOutcome {
+    // 文件内容与内存中的配置一致，未做替换
+    Unchanged,
+    // 已替换为新配置，附带变更摘要
+    Reloaded(Vec<String>),
+}
+
+// app.yml重载产生的变更
+#[derive(Debug, Default)]
+pub struct AppConfigChanges {
+    // 已生效的变更
+    pub applied: Vec<String>,
+    // 需要重启服务才能生效的变更，运行中仍使用旧值
+    pub restart_required: Vec<String>,
+}
+
+impl AppConfigChanges {
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty() && self.restart_required.is_empty()
+    }
+}
+
+// 从磁盘重新读取clash.yml及其引用的文件、命名模板、users.yml和覆盖配置，
+// 校验通过后替换内存中的配置并记录历史版本
+// source 用于日志和历史记录，如客户端IP、"文件监听"、"SIGHUP"
+pub async fn reload_from_disk(
+    state: &AppState,
+    source: &str,
+    message: Option<String>,
+) -> Result<ReloadOutcome, String> {
+    // 与保存、回滚串行执行，避免读到旧文件后覆盖较新的内存配置
+    let _write_guard = state.config_write_lock.lock().await;
+
+    let content = tokio::fs::read_to_string(CLASH_CONFIG_PATH)
+        .await
+        .map_err(|e| format!("无法读取{}: {}", CLASH_CONFIG_PATH, e))?;
+
+    // 外部工具非原子写入时可能读到截断的空文件，不能当作合法配置
+    if content.trim().is_empty() {
+        return Err(format!("{}内容为空", CLASH_CONFIG_PATH));
+    }
+
+    let (parsed, report) = validate_content(&content);
+    let new_config = match parsed {
+        Some(config) if report.valid => config,
+        _ => return Err(format!("配置校验失败: {}", report.error_summary())),
+    };
+    if report.warnings > 0 {
+        warn!("[{}] 重载的配置存在 {} 个警告", source, report.warnings);
+        for message in report.messages(Severity::Warning) {
+            warn!("[{}]   - {}", source, message);
+        }
+    }
+
+    let profiles = profile::load().await?;
+    let users = UsersStore::load().await?;
+    let overlays = overlay::load().await?;
+    let hosted_rule_sets = ruleset::load().await?;
+
+    // 已持有写入锁，读取后再替换不会被其他写入打断
+    let current = state.snapshot.load_full();
+    let mut changes = summarize_changes(&current.config, &new_config);
+    changes.extend(summarize_profile_changes(&current.profiles, &profiles));
+    if users != current.users {
+        changes.push(format!(
+            "{}已更新: {} 个套餐, {} 个用户",
+            USERS_PATH,
+            users.plans.len(),
+            users.users.len()
+        ));
+    }
+    if overlays != current.overlays {
+        changes.push(format!(
+            "{}已更新: {} 个覆盖配置",
+            OVERLAYS_DIR,
+            overlays.len()
+        ));
+    }
+    if hosted_rule_sets != current.hosted_rule_sets {
+        changes.push(format!(
+            "{}已更新: {} 个规则集",
+            RULESETS_DIR,
+            hosted_rule_sets.len()
+        ));
+    }
+    if changes.is_empty() {
+        return Ok(ReloadOutcome::Unchanged);
+    }
+    let version = publish(
+        state,
+        Snapshot::build(
+            new_config,
+            profiles,
+            users,
+            overlays,
+            current.upstreams.clone(),
+            hosted_rule_sets,
+        )?,
+    );
+    debug!("[{}] 已发布配置版本 {}", source, version);
+
+    let history_config = state.app_config.load().history.clone();
+    if let Err(e) = history::record(&history_config, &content, source, "reload", message).await {
+        warn!("[{}] 记录配置历史版本失败: {}", source, e);
+    }
+
+    Ok(ReloadOutcome::Reloaded(changes))
+}
+
+// 发布新的配置快照，返回新的版本号
+// 调用方需持有config_write_lock，保证版本号递增不被并发发布打断
+// 快照需在写入文件前构建好，避免文件已写入而内存中的配置无法更新
+pub fn publish(state: &AppState, mut snapshot: Snapshot) -> u64 {
+    let version = state.snapshot.load().version + 1;
+    snapshot.version = version;
+    state.snapshot.store(Arc::new(snapshot));
+    version
+}
+
+// 对比新旧配置，生成可读的变更摘要，无变化时返回空列表
+pub fn summarize_changes(old: &ClashConfig, new: &ClashConfig) -> Vec<String> {
+    let old = to_mapping(old);
+    let new = to_mapping(new);
+    let mut changes = Vec::new();
+
+    diff_named_list(&old, &new, "proxies", "代理节点", &mut changes);
+    diff_named_list(&old, &new, "proxy-groups", "代理组", &mut changes);
+
+    let old_rules = string_list(&old, "rules");
+    let new_rules = string_list(&new, "rules");
+    if old_rules != new_rules {
+        let old_set: HashSet<&String> = old_rules.iter().collect();
+        let new_set: HashSet<&String> = new_rules.iter().collect();
+        let added = new_set.difference(&old_set).count();
+        let removed = old_set.difference(&new_set).count();
+        changes.push(format!(
+            "规则: {} -> {} 条（新增 {} 条，删除 {} 条）",
+            old_rules.len(),
+            new_rules.len(),
+            added,
+            removed
+        ));
+    }
+
+    // 其余顶层字段只报告字段名
+    let mut keys: Vec<&str> = old
+        .keys()
+        .chain(new.keys())
+        .filter_map(|key| key.as_str())
+        .filter(|key| !matches!(*key, "proxies" | "proxy-groups" | "rules"))
+        .collect();
+    keys.sort_unstable();
+    keys.dedup();
+    let changed_keys: Vec<&str> = keys
+        .into_iter()
+        .filter(|key| old.get(*key) != new.get(*key))
+        .collect();
+    if !changed_keys.is_empty() {
+        changes.push(format!("其他字段变更: {}", changed_keys.join(", ")));
+    }
+
+    changes
+}
+
+// 对比新旧命名模板，每条变更前加上模板名称
+fn summarize_profile_changes(old: &Profiles, new: &Profiles) -> Vec<String> {
+    let mut changes = Vec::new();
+    for (name, config) in new {
+        match old.get(name) {
+            Some(old_config) => changes.extend(
+                summarize_changes(old_config, config)
+                    .into_iter()
+                    .map(|change| format!("模板 {}: {}", name, change)),
+            ),
+            None => changes.push(format!("新增模板 {}", name)),
+        }
+    }
+    for name in old.keys().filter(|name| !new.contains_key(*name)) {
+        changes.push(format!("删除模板 {}", name));
+    }
+    changes
+}
+
+fn to_mapping(config: &ClashConfig) -> Mapping {
+    match serde_yaml_ng::to_value(config) {
+        Ok(Value::Mapping(mapping)) => mapping,
+        _ => Mapping::new(),
+    }
+}
+
+fn string_list(mapping: &Mapping, key: &str) -> Vec<String> {
+    mapping
+        .get(key)
+        .and_then(|v| v.as_sequence())
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| item.as_str().map(|s| s.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// 按name字段对比列表项（代理节点、代理组）
+fn diff_named_list(
+    old: &Mapping,
+    new: &Mapping,
+    key: &str,
+    label: &str,
+    changes: &mut Vec<String>,
+) {
+    let named = |mapping: &Mapping| -> Vec<(String, Value)> {
+        mapping
+            .get(key)
+            .and_then(|v| v.as_sequence())
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|item| {
+                        let name = item.get("name")?.as_str()?.to_string();
+                        Some((name, item.clone()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let old_items = named(old);
+    let new_items = named(new);
+
+    let find = |items: &[(String, Value)], name: &str| {
+        items
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.clone())
+    };
+
+    let added: Vec<&str> = new_items
+        .iter()
+        .filter(|(name, _)| find(&old_items, name).is_none())
+        .map(|(name, _)| name.as_str())
+        .collect();
+    let removed: Vec<&str> = old_items
+        .iter()
+        .filter(|(name, _)| find(&new_items, name).is_none())
+        .map(|(name, _)| name.as_str())
+        .collect();
+    let modified: Vec<&str> = new_items
+        .iter()
+        .filter(|(name, value)| find(&old_items, name).is_some_and(|old| &old != value))
+        .map(|(name, _)| name.as_str())
+        .collect();
+
+    if !added.is_empty() {
+        changes.push(format!("新增{}: {}", label, added.join(", ")));
+    }
+    if !removed.is_empty() {
+        changes.push(format!("删除{}: {}", label, removed.join(", ")));
+    }
+    if !modified.is_empty() {
+        changes.push(format!("修改{}: {}", label, modified.join(", ")));
+    }
+
+    // 名称集合不变但顺序调整
+    if added.is_empty() && removed.is_empty() && modified.is_empty() {
+        let old_order: Vec<&String> = old_items.iter().map(|(n, _)| n).collect();
+        let new_order: Vec<&String> = new_items.iter().map(|(n, _)| n).collect();
+        if old_order != new_order {
+            changes.push(format!("{}顺序调整", label));
+        }
+    }
+}
+
+// 从磁盘重新读取app.yml并替换应用配置
+// 监听地址等只在启动时生效的设置保留运行中的值，并标记为需要重启
+pub async fn reload_app_config(state: &AppState) -> Result<AppConfigChanges, String> {
+    let content = tokio::fs::read_to_string(APP_CONFIG_PATH)
+        .await
+        .map_err(|e| format!("无法读取{}: {}", APP_CONFIG_PATH, e))?;
+    if content.trim().is_empty() {
+        return Err(format!("{}内容为空", APP_CONFIG_PATH));
+    }
+
+    let mut new_config: AppConfig = serde_yaml_ng::from_str(&content)
+        .map_err(|e| format!("{}解析失败: {}", APP_CONFIG_PATH, e))?;
+    decode_key(&new_config.encryption_key).map_err(|e| format!("encryption_key无效: {}", e))?;
+    if logging::parse_level(&new_config.log_level).is_none() {
+        return Err(format!("无效的日志级别: {}", new_config.log_level));
+    }
+    new_config.credentials.check()?;
+    upstream::check(&new_config.upstreams)?;
+
+    // 与其他配置写入串行执行
+    let _write_guard = state.config_write_lock.lock().await;
+    let old_config = state.app_config.load_full();
+    let mut changes = AppConfigChanges::default();
+
+    if new_config.addr != old_config.addr || new_config.port != old_config.port {
+        changes.restart_required.push(format!(
+            "监听地址 {}:{} -> {}:{}",
+            old_config.addr, old_config.port, new_config.addr, new_config.port
+        ));
+        new_config.addr = old_config.addr.clone();
+        new_config.port = old_config.port;
+    }
+    // 文件监听和SIGHUP处理在启动时创建
+    if new_config.reload != old_config.reload {
+        changes
+            .restart_required
+            .push("reload（自动重载开关）".to_string());
+        new_config.reload = old_config.reload.clone();
+    }
+    // 上游订阅的拉取任务在启动时创建
+    if new_config.upstreams != old_config.upstreams {
+        changes
+            .restart_required
+            .push("upstreams（上游订阅）".to_string());
+        new_config.upstreams = old_config.upstreams.clone();
+    }
+
+    if !new_config
+        .log_level
+        .eq_ignore_ascii_case(&old_config.log_level)
+    {
+        logging::set_level(&new_config.log_level)?;
+        changes.applied.push(format!(
+            "日志级别: {} -> {}",
+            old_config.log_level, new_config.log_level
+        ));
+    }
+    if new_config.encryption_key != old_config.encryption_key {
+        changes
+            .applied
+            .push("encryption_key已更新，旧密钥加密的订阅链接和token将失效".to_string());
+    }
+    if new_config.admin_password != old_config.admin_password {
+        changes.applied.push("admin_password已更新".to_string());
+    }
+    if new_config.history != old_config.history {
+        changes.applied.push("history保留策略已更新".to_string());
+    }
+    if new_config.compression != old_config.compression {
+        changes
+            .applied
+            .push("compression压缩级别已更新".to_string());
+    }
+    if new_config.credentials != old_config.credentials {
+        changes
+            .applied
+            .push("credentials设置已更新，按用户生成的节点密码和short-id随之变化".to_string());
+    }
+
+    state.app_config.store(Arc::new(new_config));
+    Ok(changes)
+}
+
+// 记录app.yml重载结果
+pub fn log_app_config_changes(source: &str, changes: &AppConfigChanges) {
+    if changes.is_empty() {
+        debug!("[{}] {} 内容未变化", source, APP_CONFIG_PATH);
+        return;
+    }
+    info!("[{}] 应用配置已重载", source);
+    for change in &changes.applied {
+        info!("[{}]   - {}", source, change);
+    }
+    for change in &changes.restart_required {
+        warn!("[{}]   - {}（需要重启服务才能生效）", source, change);
+    }
+}
+
+// 执行一次clash.yml重载并记录日志
+async fn reload_clash_and_log(state: &AppState, source: &str) {
+    match reload_from_disk(state, source, None).await {
+        Ok(ReloadOutcome::Reloaded(changes)) => {
+            info!("[{}] 配置文件已自动重载", source);
+            for change in changes {
+                info!("[{}]   - {}", source, change);
+            }
+        }
+        Ok(ReloadOutcome::Unchanged) => {
+            debug!("[{}] 配置文件内容未变化，跳过重载", source);
+        }
+        Err(e) => {
+            error!("[{}] 自动重载失败，继续使用当前配置: {}", source, e);
+        }
+    }
+}
+
+// 执行一次app.yml重载并记录日志
+async fn reload_app_and_log(state: &AppState, source: &str) {
+    match reload_app_config(state).await {
+        Ok(changes) => log_app_config_changes(source, &changes),
+        Err(e) => error!("[{}] 应用配置重载失败，继续使用当前配置: {}", source, e),
+    }
+}
+
+// 启动配置文件监听和SIGHUP处理，按app.yml中的开关决定是否启用
+pub fn spawn(state: AppState, config: &ReloadConfig) {
+    if config.watch {
+        spawn_watcher(state.clone(), config.debounce_ms);
+    }
+    if config.sighup {
+        spawn_sighup_handler(state);
+    }
+}
+
+// 被监听的配置文件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WatchedFile {
+    App,
+    Clash,
+}
+
+fn spawn_watcher(state: AppState, debounce_ms: u64) {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let app_file = Path::new(APP_CONFIG_PATH).file_name();
+    let config_dir = Path::new(CLASH_CONFIG_PATH)
+        .parent()
+        .and_then(|dir| dir.file_name());
+    let overlays_dir = Path::new(OVERLAYS_DIR).file_name();
+    let profiles_dir = Path::new(PROFILES_DIR).file_name();
+    let servers_dir = Path::new(SERVERS_DIR).file_name();
+    let rulesets_dir = Path::new(RULESETS_DIR).file_name();
+    let geo_dir = Path::new(GEO_DIR).file_name();
+
+    let mut watcher =
+        match notify::recommended_watcher(move |result: notify::Result<Event>| match result {
+            Ok(event) => {
+                // 只关心写入、创建、删除和rename，忽略读取产生的访问事件
+                let relevant = matches!(
+                    event.kind,
+                    EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                );
+                if !relevant {
+                    return;
+                }
+                // 保存时通过rename原子替换，只能监听目录，这里过滤出配置文件本身
+                for path in &event.paths {
+                    if path.file_name() == app_file {
+                        let _ = tx.send(WatchedFile::App);
+                        continue;
+                    }
+                    // 被引用的文件、命名模板、用户列表、覆盖配置和规则集与clash.yml一起重载，生成新的配置快照
+                    // 这些文件都是config目录、命名模板目录、覆盖配置目录或规则集目录下的yml文件，临时文件和隐藏文件除外
+                    let is_yaml = path
+                        .extension()
+                        .is_some_and(|ext| ext == "yml" || ext == "yaml");
+                    let hidden = path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| name.starts_with('.'));
+                    let parent = path.parent().and_then(|dir| dir.file_name());
+                    let watched_dir = parent == config_dir
+                        || parent == profiles_dir
+                        || parent == overlays_dir
+                        || parent == rulesets_dir;
+                    // 节点引用的服务端配置是服务端配置目录下的json文件，规则集引用的geo数据是geo目录下的dat文件
+                    let is_server_config =
+                        parent == servers_dir && path.extension().is_some_and(|ext| ext == "json");
+                    let is_geo_data =
+                        parent == geo_dir && path.extension().is_some_and(|ext| ext == "dat");
+                    if !hidden && ((is_yaml && watched_dir) || is_server_config || is_geo_data) {
+                        let _ = tx.send(WatchedFile::Clash);
+                    }
+                }
+            }
+            Err(e) => error!("配置文件监听出错: {}", e),
+        }) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                error!("创建配置文件监听失败: {}", e);
+                return;
+            }
+        };
+
+    let watch_dir = Path::new(CLASH_CONFIG_PATH)
+        .parent()
+        .unwrap_or(Path::new("."));
+    if let Err(e) = watcher.watch(watch_dir, RecursiveMode::NonRecursive) {
+        error!("监听目录 {} 失败: {}", watch_dir.display(), e);
+        return;
+    }
+    // 命名模板、覆盖配置、服务端配置、规则集和geo数据目录在启动时存在才会被监听
+    for dir in [
+        PROFILES_DIR,
+        OVERLAYS_DIR,
+        SERVERS_DIR,
+        RULESETS_DIR,
+        GEO_DIR,
+    ] {
+        if Path::new(dir).is_dir()
+            && let Err(e) = watcher.watch(Path::new(dir), RecursiveMode::NonRecursive)
+        {
+            error!("监听目录 {} 失败: {}", dir, e);
+        }
+    }
+    info!(
+        "👀 已启用配置文件监听: {}, {}及其引用的文件, {}/, {}, {}/, {}/, {}/, {}/（防抖 {}ms）",
+        APP_CONFIG_PATH,
+        CLASH_CONFIG_PATH,
+        PROFILES_DIR,
+        USERS_PATH,
+        OVERLAYS_DIR,
+        SERVERS_DIR,
+        RULESETS_DIR,
+        GEO_DIR,
+        debounce_ms
+    );
+
+    ntex::rt::spawn(async move {
+        // 持有watcher，任务结束前监听保持有效
+        let _watcher = watcher;
+        let debounce = Duration::from_millis(debounce_ms);
+        while let Some(first) = rx.recv().await {
+            // 防抖: 直到一段时间内没有新事件才重载，避免读到写了一半的文件
+            let mut changed = vec![first];
+            while let Ok(Some(file)) = tokio::time::timeout(debounce, rx.recv()).await {
+                if !changed.contains(&file) {
+                    changed.push(file);
+                }
+            }
+            // 先重载app.yml，历史保留策略等设置会影响clash.yml的重载
+            if changed.contains(&WatchedFile::App) {
+                reload_app_and_log(&state, "文件监听").await;
+            }
+            if changed.contains(&WatchedFile::Clash) {
+                reload_clash_and_log(&state, "文件监听").await;
+            }
+        }
+    });
+}
+
+#[cfg(unix)]
+fn spawn_sighup_handler(state: AppState) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            error!("注册SIGHUP处理失败: {}", e);
+            return;
+        }
+    };
+    info!("📡 已启用SIGHUP重载");
+
+    ntex::rt::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("收到SIGHUP信号，重新加载配置");
+            reload_app_and_log(&state, "SIGHUP").await;
+            reload_clash_and_log(&state, "SIGHUP").await;
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn spawn_sighup_handler(_state: AppState) {
+    warn!("当前平台不支持SIGHUP，已忽略reload.sighup配置");
+}
@@ -0,0 +1,383 @@
+use crate::compression::{self, Encoding};
+use crate::credentials::{Credentials, PasswordSlot};
+use crate::models::{ClashConfig, CompressionConfig};
+use crate::storage::content_hash;
+use crate::template::{self, Part};
+use crate::upstream::UPSTREAM_KEY;
+use crate::users::{self, UserEntry};
+use ntex::util::Bytes;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing::warn;
+use uuid::Uuid;
+
+// 每个配置版本最多缓存的压缩结果数量，超出后清空重新缓存
+const MAX_COMPRESSED_ENTRIES: usize = 4096;
+
+// 压缩缓存的键: (编码, 压缩级别, 用户取值摘要)
+type CacheKey = (Encoding, i32, String);
+
+// 渲染的文档类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Document {
+    // 完整的订阅配置
+    Config,
+    // 只包含proxies的节点文档，用作proxy-providers
+    Proxies,
+}
+
+// 模板中按用户取值的位置
+enum Slot {
+    // 用户UUID
+    Uuid,
+    // 按协议生成的节点密码
+    Password(PasswordSlot),
+    // reality-opts.short-id，值为模板中的原始值
+    ShortId(String),
+    // 包含请求时变量的字符串
+    Text(Vec<Part>),
+}
+
+// 模板片段
+enum Segment {
+    // 原样输出的YAML文本
+    Literal(String),
+    // 按用户取值的位置，值为slots中的下标
+    Slot(usize),
+}
+
+// 计算取值所需的用户和请求信息
+pub struct UserContext<'a> {
+    pub uuid: &'a str,
+    // users.yml中的用户信息
+    pub user: Option<&'a UserEntry>,
+    // 订阅请求的Host和协议
+    pub host: &'a str,
+    pub scheme: &'a str,
+    pub credentials: &'a Credentials<'a>,
+}
+
+// 单个用户在模板各个取值位置的YAML标量
+pub struct UserValues {
+    scalars: Vec<String>,
+    // 所有取值的摘要，用于ETag和压缩缓存
+    digest: String,
+}
+
+// 预渲染的订阅模板
+// 每个配置版本只序列化一次，请求时只需在取值位置拼接用户的UUID、密码、short-id和模板变量
+pub struct RenderedTemplate {
+    segments: Vec<Segment>,
+    slots: Vec<Slot>,
+    literal_size: usize,
+    placeholders: usize,
+    // 模板内容哈希，不含随机占位符，相同内容的配置得到相同哈希
+    content_hash: String,
+    // 按编码、压缩级别和用户取值缓存的压缩结果: (压缩数据, 原始大小)，随模板一起在重载时丢弃
+    compressed: Mutex<HashMap<CacheKey, (Bytes, usize)>>,
+}
+
+impl RenderedTemplate {
+    // 将配置渲染为模板: 用随机占位符替换proxies中的uuid、可按用户生成的密码和short-id，
+    // 以及包含请求时变量的字符串，序列化后再按占位符切分；环境变量在此时直接替换
+    // 占位符格式为 前缀 + s<下标>z，下标0固定为UUID
+    pub fn render(config: &ClashConfig, document: Document) -> Result<Self, String> {
+        let prefix = format!("raygoplaceholder{}", Uuid::new_v4().simple());
+        let placeholder = |index: usize| format!("{}s{}z", prefix, index);
+
+        if config.proxies.is_none() && document == Document::Config {
+            warn!("配置中没有找到proxies字段");
+        }
+
+        let mut config = config.clone();
+        users::strip_tags(&mut config);
+
+        // 上游订阅的节点使用上游的UUID和密码，也不替换模板变量
+        // 先取出这些节点，替换完成后再放回原来的位置
+        let mut upstream_proxies = Vec::new();
+        if let Some(proxies) = config.proxies.take() {
+            let mut local = Vec::with_capacity(proxies.len());
+            for (index, mut proxy) in proxies.into_iter().enumerate() {
+                match proxy.remove(UPSTREAM_KEY) {
+                    Some(_) => upstream_proxies.push((index, proxy)),
+                    None => local.push(proxy),
+                }
+            }
+            config.proxies = Some(local);
+        }
+
+        let mut slots = vec![Slot::Uuid];
+        for proxy in config.proxies.iter_mut().flatten() {
+            if proxy.contains_key("uuid") {
+                proxy.insert(
+                    "uuid".to_string(),
+                    serde_yaml_ng::Value::String(placeholder(0)),
+                );
+            }
+            // 模板中直接写了变量的密码和short-id按模板变量处理
+            if let Some(slot) = PasswordSlot::from_proxy(proxy)
+                && !template::has_variables(&slot.original)
+            {
+                proxy.insert(
+                    "password".to_string(),
+                    serde_yaml_ng::Value::String(placeholder(slots.len())),
+                );
+                slots.push(Slot::Password(slot));
+            }
+            if let Some(serde_yaml_ng::Value::Mapping(opts)) = proxy.get_mut("reality-opts")
+                && let Some(short_id) = opts.get_mut("short-id")
+            {
+                // short-id全为数字时YAML会解析为整数
+                let original = match short_id {
+                    serde_yaml_ng::Value::String(value) => Some(value.clone()),
+                    serde_yaml_ng::Value::Number(value) => Some(value.to_string()),
+                    _ => None,
+                };
+                if let Some(original) = original
+                    && !template::has_variables(&original)
+                {
+                    *short_id = serde_yaml_ng::Value::String(placeholder(slots.len()));
+                    slots.push(Slot::ShortId(original));
+                }
+            }
+        }
+
+        let mut value =
+            serde_yaml_ng::to_value(&config).map_err(|e| format!("配置序列化失败: {}", e))?;
+        if document == Document::Proxies {
+            let proxies = value
+                .get("proxies")
+                .cloned()
+                .unwrap_or(serde_yaml_ng::Value::Sequence(Vec::new()));
+            let mut mapping = serde_yaml_ng::Mapping::new();
+            mapping.insert("proxies".into(), proxies);
+            value = serde_yaml_ng::Value::Mapping(mapping);
+        }
+        substitute_variables(&mut value, &mut slots, &placeholder)?;
+        if let Some(serde_yaml_ng::Value::Sequence(proxies)) = value.get_mut("proxies") {
+            for (index, proxy) in upstream_proxies {
+                let proxy =
+                    serde_yaml_ng::to_value(proxy).map_err(|e| format!("配置序列化失败: {}", e))?;
+                proxies.insert(index, proxy);
+            }
+        }
+
+        let yaml =
+            serde_yaml_ng::to_string(&value).map_err(|e| format!("配置序列化失败: {}", e))?;
+
+        let mut segments = Vec::new();
+        let mut placeholders = 0;
+        let mut placeholder_size = 0;
+        for (index, piece) in yaml.split(prefix.as_str()).enumerate() {
+            let literal = if index == 0 {
+                piece
+            } else {
+                let (slot, rest) =
+                    parse_slot(piece).ok_or_else(|| "订阅模板中的占位符格式错误".to_string())?;
+                if slot >= slots.len() {
+                    return Err("订阅模板中的占位符格式错误".to_string());
+                }
+                segments.push(Segment::Slot(slot));
+                placeholders += 1;
+                placeholder_size += piece.len() - rest.len() + prefix.len();
+                rest
+            };
+            if !literal.is_empty() {
+                segments.push(Segment::Literal(literal.to_string()));
+            }
+        }
+
+        // 占位符每次渲染都不同，哈希时用固定标记代替
+        let hash_source: String = segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => text.clone(),
+                Segment::Slot(slot) => format!("\0slot{}\0", slot),
+            })
+            .collect();
+
+        Ok(Self {
+            content_hash: content_hash(&hash_source),
+            literal_size: yaml.len() - placeholder_size,
+            segments,
+            slots,
+            placeholders,
+            compressed: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // 模板中取值位置的数量
+    pub fn placeholders(&self) -> usize {
+        self.placeholders
+    }
+
+    // 模板中按用户生成的密码、short-id和包含模板变量的字符串数量
+    pub fn credentials(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|slot| !matches!(slot, Slot::Uuid))
+            .count()
+    }
+
+    // 计算指定用户在各个取值位置的值
+    pub fn values(&self, context: &UserContext) -> UserValues {
+        let uuid = context.uuid;
+        let user = context.user;
+        let variables = template::Values {
+            user_name: user.and_then(|user| user.name.as_deref()).unwrap_or(uuid),
+            user_uuid: uuid,
+            user_expire: user.and_then(|user| user.expire.as_deref()).unwrap_or(""),
+            request_host: context.host,
+            request_scheme: context.scheme,
+        };
+        let scalars: Vec<String> = self
+            .slots
+            .iter()
+            .map(|slot| match slot {
+                Slot::Uuid => yaml_scalar(uuid),
+                Slot::Password(slot) => {
+                    yaml_scalar(&context.credentials.client_password(slot, uuid))
+                }
+                Slot::ShortId(original) => {
+                    yaml_scalar(&context.credentials.client_short_id(original, uuid))
+                }
+                Slot::Text(parts) => yaml_scalar(&template::expand(parts, &variables)),
+            })
+            .collect();
+        let digest = content_hash(&scalars.join("\0"));
+        UserValues { scalars, digest }
+    }
+
+    // 生成指定用户、指定编码的强ETag
+    // 由模板内容、用户取值和编码方式共同决定，任一变化都会得到新的ETag
+    pub fn etag(&self, values: &UserValues, encoding: &str) -> String {
+        let digest = content_hash(&format!(
+            "{}\0{}\0{}",
+            self.content_hash, values.digest, encoding
+        ));
+        format!("\"{}\"", &digest[..32])
+    }
+
+    // 指定用户订阅内容的长度，无需拼接
+    pub fn size(&self, values: &UserValues) -> usize {
+        let values_size: usize = self
+            .segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Slot(slot) => values.scalars[*slot].len(),
+                Segment::Literal(_) => 0,
+            })
+            .sum();
+        self.literal_size + values_size
+    }
+
+    // 拼接出指定用户的订阅内容
+    pub fn splice(&self, values: &UserValues) -> String {
+        let mut output = String::with_capacity(self.size(values));
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => output.push_str(text),
+                Segment::Slot(slot) => output.push_str(&values.scalars[*slot]),
+            }
+        }
+        output
+    }
+
+    // 获取指定用户按指定编码压缩的订阅内容，返回 (压缩数据, 原始大小, 是否命中缓存)
+    pub fn compressed(
+        &self,
+        values: &UserValues,
+        encoding: Encoding,
+        config: &CompressionConfig,
+    ) -> Result<(Bytes, usize, bool), String> {
+        let key = (encoding, encoding.level(config), values.digest.clone());
+        if let Some((data, original_size)) = self.compressed_cache().get(&key) {
+            return Ok((data.clone(), *original_size, true));
+        }
+
+        let body = self.splice(values);
+        let data = Bytes::from(compression::compress(encoding, body.as_bytes(), config)?);
+
+        let mut cache = self.compressed_cache();
+        if cache.len() >= MAX_COMPRESSED_ENTRIES {
+            cache.clear();
+        }
+        cache.insert(key, (data.clone(), body.len()));
+
+        Ok((data, body.len(), false))
+    }
+
+    // 已缓存的压缩结果的长度，未缓存时返回None
+    pub fn compressed_size(
+        &self,
+        values: &UserValues,
+        encoding: Encoding,
+        config: &CompressionConfig,
+    ) -> Option<usize> {
+        let key = (encoding, encoding.level(config), values.digest.clone());
+        self.compressed_cache()
+            .get(&key)
+            .map(|(data, _)| data.len())
+    }
+
+    fn compressed_cache(&self) -> std::sync::MutexGuard<'_, HashMap<CacheKey, (Bytes, usize)>> {
+        // 缓存内容始终完整，锁中毒时直接继续使用
+        self.compressed
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// 替换配置中所有字符串（包括映射的键）里的模板变量
+// 只包含环境变量的字符串直接替换为结果，包含请求时变量的字符串替换为占位符
+fn substitute_variables(
+    value: &mut serde_yaml_ng::Value,
+    slots: &mut Vec<Slot>,
+    placeholder: &dyn Fn(usize) -> String,
+) -> Result<(), String> {
+    match value {
+        serde_yaml_ng::Value::String(text) if template::has_variables(text) => {
+            let parts = template::parse(text)?;
+            *text = if template::is_dynamic(&parts) {
+                slots.push(Slot::Text(parts));
+                placeholder(slots.len() - 1)
+            } else {
+                template::expand(&parts, &template::Values::default())
+            };
+        }
+        serde_yaml_ng::Value::Sequence(items) => {
+            for item in items {
+                substitute_variables(item, slots, placeholder)?;
+            }
+        }
+        serde_yaml_ng::Value::Mapping(mapping) => {
+            // 键也可能包含变量，重建映射保持原有顺序
+            for (mut key, mut item) in std::mem::take(mapping) {
+                substitute_variables(&mut key, slots, placeholder)?;
+                substitute_variables(&mut item, slots, placeholder)?;
+                mapping.insert(key, item);
+            }
+        }
+        serde_yaml_ng::Value::Tagged(tagged) => {
+            substitute_variables(&mut tagged.value, slots, placeholder)?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// 解析占位符前缀之后的 s<下标>z，返回下标和剩余的文本
+fn parse_slot(piece: &str) -> Option<(usize, &str)> {
+    let rest = piece.strip_prefix('s')?;
+    let end = rest.find('z')?;
+    let slot = rest[..end].parse().ok()?;
+    Some((slot, &rest[end + 1..]))
+}
+
+// 按serde_yaml_ng的规则输出字符串标量，与直接序列化配置的结果保持一致
+fn yaml_scalar(value: &str) -> String {
+    match serde_yaml_ng::to_string(value) {
+        Ok(yaml) => yaml.trim_end_matches('\n').to_string(),
+        Err(_) => format!("'{}'", value.replace('\'', "''")),
+    }
+}
@@ -0,0 +1,113 @@
+use crate::models::{Rule, RuleType};
+use serde::Deserialize;
+use serde::de::IntoDeserializer;
+use std::fmt;
+
+// 内置策略名称，无需在proxies或proxy-groups中定义
+pub const BUILTIN_TARGETS: &[&str] = &[
+    "DIRECT",
+    "REJECT",
+    "REJECT-DROP",
+    "PASS",
+    "COMPATIBLE",
+    "GLOBAL",
+];
+
+// 规则解析错误
+#[derive(Debug)]
+pub enum RuleError {
+    // 规则类型无法识别（可能是较新内核支持的类型）
+    UnknownType(String),
+    // 规则结构不完整或格式错误
+    Malformed(String),
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::UnknownType(keyword) => write!(f, "未知的规则类型: {}", keyword),
+            RuleError::Malformed(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+// 判断策略名称是否为内置策略
+pub fn is_builtin_target(name: &str) -> bool {
+    BUILTIN_TARGETS.contains(&name)
+}
+
+// 按逗号切分规则，括号内的逗号不切分（AND/OR/NOT/SUB-RULE的条件部分）
+pub fn split_rule(line: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut start = 0;
+
+    for (index, ch) in line.char_indices() {
+        match ch {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(line[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(line[start..].trim());
+    parts
+}
+
+// 解析规则类型关键字，如 DOMAIN-SUFFIX
+pub fn parse_rule_type(keyword: &str) -> Option<RuleType> {
+    let keyword = keyword.trim().to_uppercase();
+    let result: Result<RuleType, serde::de::value::Error> =
+        RuleType::deserialize(keyword.as_str().into_deserializer());
+    result.ok()
+}
+
+// 解析一条字符串格式的规则，如 "DOMAIN-SUFFIX,google.com,代理,no-resolve"
+pub fn parse_rule(line: &str) -> Result<Rule, RuleError> {
+    let parts = split_rule(line);
+    let keyword = parts[0];
+
+    if keyword.is_empty() {
+        return Err(RuleError::Malformed("规则为空".to_string()));
+    }
+
+    let rule_type =
+        parse_rule_type(keyword).ok_or_else(|| RuleError::UnknownType(keyword.to_string()))?;
+
+    // MATCH规则只有目标策略，没有匹配内容
+    if rule_type == RuleType::Match {
+        let target = parts
+            .get(1)
+            .filter(|target| !target.is_empty())
+            .ok_or_else(|| RuleError::Malformed("MATCH规则缺少目标策略".to_string()))?;
+        return Ok(Rule {
+            rule_type,
+            payload: String::new(),
+            target: target.to_string(),
+            params: None,
+        });
+    }
+
+    if parts.len() < 3 || parts[1].is_empty() || parts[2].is_empty() {
+        return Err(RuleError::Malformed(format!(
+            "规则字段不足，需要 类型,内容,策略 格式: {}",
+            line
+        )));
+    }
+
+    let params: Vec<String> = parts[3..].iter().map(|p| p.to_string()).collect();
+
+    Ok(Rule {
+        rule_type,
+        payload: parts[1].to_string(),
+        target: parts[2].to_string(),
+        params: if params.is_empty() {
+            None
+        } else {
+            Some(params)
+        },
+    })
+}
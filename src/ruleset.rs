@@ -0,0 +1,343 @@
+use crate::geo::{DomainKind, GeoFiles};
+use crate::models::{
+    ClashConfig, RuleBehavior, RuleFormat, RuleProvider, RuleProviderType, RuleType,
+};
+use crate::provider::{RuleSet, RuleSets, format_name};
+use crate::rules;
+use serde::Deserialize;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+// 托管规则集目录，每个文件是一个规则集，文件名（不含扩展名）为其名称
+pub const RULESETS_DIR: &str = "config/rulesets";
+// 编辑器中规则集文件名的前缀，如 rulesets/intranet.yml
+pub const EDITOR_PREFIX: &str = "rulesets/";
+// 自动生成的rule-providers的更新间隔（秒）
+const PROVIDER_INTERVAL: u32 = 86400;
+// 新建规则集时编辑器中的初始内容
+pub const NEW_RULE_SET: &str =
+    "# behavior: domain、ipcidr或classical\nbehavior: domain\npayload:\n  - '+.example.com'\n";
+
+// 规则集文件内容
+#[derive(Deserialize)]
+struct RuleSetFile {
+    behavior: RuleBehavior,
+    #[serde(default)]
+    payload: Vec<String>,
+    // 从config/geo/下的geosite.dat和geoip.dat中提取的分类，追加到payload之后
+    #[serde(default)]
+    geosite: Vec<String>,
+    #[serde(default)]
+    geoip: Vec<String>,
+}
+
+// 解析后的规则集，以及不影响使用的问题（如geosite中无法转换的条目）
+pub struct Parsed {
+    pub rule_set: RuleSet,
+    pub warnings: Vec<EntryError>,
+}
+
+// 规则集中有问题的条目
+#[derive(Debug)]
+pub struct EntryError {
+    // 出错的条目，文件整体格式错误时为空
+    pub entry: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for EntryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.entry {
+            Some(entry) => write!(f, "{}: {}", entry, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+// 规则集名称只能包含字母、数字、-和_，用于URL和文件名
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn path(name: &str) -> PathBuf {
+    Path::new(RULESETS_DIR).join(format!("{}.yml", name))
+}
+
+// 托管规则集文件是否存在，用于校验RULE-SET规则引用的名称
+pub fn exists(name: &str) -> bool {
+    valid_name(name) && path(name).is_file()
+}
+
+// 编辑器中的文件名对应的规则集名称，如 rulesets/intranet.yml -> intranet
+pub fn editor_name(file: &str) -> Option<&str> {
+    file.strip_prefix(EDITOR_PREFIX)?
+        .strip_suffix(".yml")
+        .filter(|name| valid_name(name))
+}
+
+// 读取托管规则集目录，目录不存在时返回空列表
+pub async fn load() -> Result<RuleSets, String> {
+    let mut rule_sets = RuleSets::new();
+    let mut geo = GeoFiles::default();
+    let mut entries = match tokio::fs::read_dir(RULESETS_DIR).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(rule_sets),
+        Err(e) => return Err(format!("无法读取{}: {}", RULESETS_DIR, e)),
+    };
+
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .map_err(|e| format!("无法读取{}: {}", RULESETS_DIR, e))?
+    {
+        let path = entry.path();
+        let is_yaml = path.extension().is_some_and(|ext| ext == "yml");
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        if !is_yaml || name.starts_with('.') {
+            continue;
+        }
+        if !valid_name(name) {
+            return Err(format!(
+                "{}: 规则集名称只能包含字母、数字、-和_",
+                path.display()
+            ));
+        }
+
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|e| format!("无法读取{}: {}", path.display(), e))?;
+        let parsed = parse(&content, &mut geo).map_err(|errors| {
+            let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            format!("{}: {}", path.display(), errors.join("; "))
+        })?;
+        for warning in &parsed.warnings {
+            warn!("{}: {}", path.display(), warning);
+        }
+        rule_sets.insert(name.to_string(), parsed.rule_set);
+    }
+
+    Ok(rule_sets)
+}
+
+// 解析并检查规则集文件，返回所有有问题的条目
+pub fn parse(content: &str, geo: &mut GeoFiles) -> Result<Parsed, Vec<EntryError>> {
+    let file: RuleSetFile = serde_yaml_ng::from_str(content).map_err(|e| {
+        vec![EntryError {
+            entry: None,
+            message: format!("规则集格式错误: {}", e),
+        }]
+    })?;
+
+    let mut errors: Vec<EntryError> = file
+        .payload
+        .iter()
+        .filter_map(|entry| {
+            check_entry(&file.behavior, entry)
+                .err()
+                .map(|message| EntryError {
+                    entry: Some(entry.clone()),
+                    message,
+                })
+        })
+        .collect();
+
+    let mut payload = file.payload;
+    let mut warnings = Vec::new();
+    for category in &file.geosite {
+        match geosite_entries(&file.behavior, category, geo) {
+            Ok((entries, skipped)) => {
+                payload.extend(entries);
+                if skipped > 0 {
+                    warnings.push(EntryError {
+                        entry: Some(category.clone()),
+                        message: format!(
+                            "{} 条关键字或正则规则无法用于domain规则集，已忽略",
+                            skipped
+                        ),
+                    });
+                }
+            }
+            Err(message) => errors.push(EntryError {
+                entry: Some(category.clone()),
+                message,
+            }),
+        }
+    }
+    for category in &file.geoip {
+        match geoip_entries(&file.behavior, category, geo) {
+            Ok(entries) => payload.extend(entries),
+            Err(message) => errors.push(EntryError {
+                entry: Some(category.clone()),
+                message,
+            }),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(Parsed {
+        rule_set: RuleSet::new(file.behavior, payload),
+        warnings,
+    })
+}
+
+// geosite分类按behavior转换为规则集条目，返回 (条目, 忽略的条目数)
+// domain规则集只能表示完整域名和子域名，关键字和正则规则被忽略；classical规则集转换为对应的规则
+fn geosite_entries(
+    behavior: &RuleBehavior,
+    category: &str,
+    geo: &mut GeoFiles,
+) -> Result<(Vec<String>, usize), String> {
+    if matches!(behavior, RuleBehavior::Ipcidr) {
+        return Err("ipcidr规则集不能包含geosite".to_string());
+    }
+    let classical = matches!(behavior, RuleBehavior::Classical);
+    let mut entries = Vec::new();
+    let mut skipped = 0;
+    for domain in geo.site(category)? {
+        let entry = match (domain.kind, classical) {
+            (DomainKind::Full, false) => domain.value,
+            (DomainKind::Suffix, false) => format!("+.{}", domain.value),
+            (DomainKind::Full, true) => format!("DOMAIN,{}", domain.value),
+            (DomainKind::Suffix, true) => format!("DOMAIN-SUFFIX,{}", domain.value),
+            (DomainKind::Keyword, true) => format!("DOMAIN-KEYWORD,{}", domain.value),
+            (DomainKind::Regex, true) => format!("DOMAIN-REGEX,{}", domain.value),
+            (DomainKind::Keyword | DomainKind::Regex, false) => {
+                skipped += 1;
+                continue;
+            }
+        };
+        entries.push(entry);
+    }
+    Ok((entries, skipped))
+}
+
+// geoip分类按behavior转换为规则集条目
+fn geoip_entries(
+    behavior: &RuleBehavior,
+    category: &str,
+    geo: &mut GeoFiles,
+) -> Result<Vec<String>, String> {
+    let cidrs = geo.ip(category)?;
+    match behavior {
+        RuleBehavior::Domain => Err("domain规则集不能包含geoip".to_string()),
+        RuleBehavior::Ipcidr => Ok(cidrs),
+        RuleBehavior::Classical => Ok(cidrs
+            .into_iter()
+            .map(|cidr| match cidr.contains(':') {
+                true => format!("IP-CIDR6,{}", cidr),
+                false => format!("IP-CIDR,{}", cidr),
+            })
+            .collect()),
+    }
+}
+
+// 按behavior检查单个条目
+fn check_entry(behavior: &RuleBehavior, entry: &str) -> Result<(), String> {
+    if entry.trim().is_empty() {
+        return Err("条目为空".to_string());
+    }
+    match behavior {
+        RuleBehavior::Domain => {
+            // 支持 example.com、+.example.com、*.example.com 和 .example.com
+            // 除开头外不能有空的标签，与mihomo域名树的要求一致
+            let valid = entry
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '-' | '.' | '*' | '+' | '_'))
+                && entry.split('.').skip(1).all(|label| !label.is_empty());
+            match valid {
+                true => Ok(()),
+                false => Err("不是有效的域名".to_string()),
+            }
+        }
+        RuleBehavior::Ipcidr => {
+            let (address, prefix) = entry
+                .split_once('/')
+                .ok_or_else(|| "需要CIDR格式，如 10.0.0.0/8".to_string())?;
+            let address: IpAddr = address
+                .parse()
+                .map_err(|_| "不是有效的IP地址".to_string())?;
+            let max = if address.is_ipv4() { 32 } else { 128 };
+            match prefix.parse::<u8>() {
+                Ok(prefix) if prefix <= max => Ok(()),
+                _ => Err("前缀长度无效".to_string()),
+            }
+        }
+        RuleBehavior::Classical => {
+            let parts = rules::split_rule(entry);
+            let rule_type = rules::parse_rule_type(parts[0])
+                .ok_or_else(|| format!("未知的规则类型: {}", parts[0]))?;
+            if matches!(rule_type, RuleType::Match | RuleType::SubRule) {
+                return Err(format!("规则集中不能使用{}规则", parts[0]));
+            }
+            if parts.len() < 2 || parts[1].is_empty() {
+                return Err("需要 类型,内容 格式".to_string());
+            }
+            Ok(())
+        }
+    }
+}
+
+// 配置中RULE-SET规则引用的规则集名称，包括子规则
+fn referenced(config: &ClashConfig) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let rules = config
+        .rules
+        .iter()
+        .flatten()
+        .filter_map(|line| rules::parse_rule(line).ok());
+    let sub_rules = config
+        .sub_rules
+        .iter()
+        .flatten()
+        .flat_map(|(_, sub_rule)| sub_rule.rules.iter().cloned());
+    for rule in rules.chain(sub_rules) {
+        if rule.rule_type == RuleType::RuleSet && !names.contains(&rule.payload) {
+            names.push(rule.payload);
+        }
+    }
+    names
+}
+
+// 为RULE-SET引用了托管规则集、但rule-providers中没有定义的名称生成http类型的rule-provider
+// 地址使用订阅请求的协议和Host，渲染时按请求填入；domain和ipcidr规则集使用体积更小的mrs格式
+pub fn attach(config: &mut ClashConfig, rule_sets: &RuleSets) -> Result<(), String> {
+    for name in referenced(config) {
+        let providers = config.rule_providers.get_or_insert_with(Default::default);
+        if providers.contains_key(&name) {
+            continue;
+        }
+        let rule_set = rule_sets
+            .get(&name)
+            .ok_or_else(|| format!("引用了不存在的规则集: {}", name))?;
+        let format = match rule_set.supports_mrs() {
+            true => RuleFormat::Mrs,
+            false => RuleFormat::Text,
+        };
+        let provider = RuleProvider {
+            provider_type: RuleProviderType::Http,
+            url: Some(format!(
+                "{{{{request.scheme}}}}://{{{{request.host}}}}/rulesets/{}?format={}",
+                name,
+                format_name(&format)
+            )),
+            path: None,
+            interval: Some(PROVIDER_INTERVAL),
+            proxy: None,
+            behavior: rule_set.behavior.clone(),
+            format: Some(format),
+            size_limit: None,
+            payload: None,
+        };
+        providers.insert(name, provider);
+    }
+    Ok(())
+}
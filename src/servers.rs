@@ -0,0 +1,721 @@
+use crate::models::ClashConfig;
+use crate::share_link::{Proxy, apply_transport};
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use serde_json::Value as Json;
+use serde_yaml_ng::{Mapping, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+// 服务端配置目录，存放各服务器的Xray或sing-box配置文件
+pub const SERVERS_DIR: &str = "config/servers";
+// 节点引用的服务端配置文件名
+pub const SERVER_CONFIG_KEY: &str = "server-config";
+// 服务端配置中有多个入站时，按tag选择其中一个
+pub const INBOUND_KEY: &str = "inbound";
+
+// 补全节点字段失败
+#[derive(Debug)]
+pub struct ExpandError {
+    // 节点名称，用于标注错误位置
+    pub proxy: String,
+    pub message: String,
+}
+
+impl fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "节点 {}: {}", self.proxy, self.message)
+    }
+}
+
+// 为引用了服务端配置的节点补全字段: 端口、传输层、TLS/Reality、short-id、flow和SNI
+// 节点中已有的字段优先，server未填写时使用配置文件名（不含.json）
+// 返回用到的服务端配置文件名
+pub fn expand(config: &mut ClashConfig) -> Result<Vec<String>, Vec<ExpandError>> {
+    expand_from(config, Path::new(SERVERS_DIR))
+}
+
+fn expand_from(config: &mut ClashConfig, dir: &Path) -> Result<Vec<String>, Vec<ExpandError>> {
+    let mut files: HashMap<String, Result<Vec<Inbound>, String>> = HashMap::new();
+    let mut used = Vec::new();
+    let mut errors = Vec::new();
+
+    for proxy in config.proxies.iter_mut().flatten() {
+        let Some(file) = proxy.remove(SERVER_CONFIG_KEY) else {
+            continue;
+        };
+        let inbound = proxy.remove(INBOUND_KEY);
+        let name = proxy
+            .get("name")
+            .and_then(|name| name.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let error = |message: String| ExpandError {
+            proxy: name.clone(),
+            message,
+        };
+
+        let Some(file) = file.as_str() else {
+            errors.push(error(format!("{}必须是文件名", SERVER_CONFIG_KEY)));
+            continue;
+        };
+        let inbounds = files
+            .entry(file.to_string())
+            .or_insert_with(|| load(dir, file));
+        let inbounds = match inbounds {
+            Ok(inbounds) => inbounds,
+            Err(e) => {
+                errors.push(error(e.clone()));
+                continue;
+            }
+        };
+        let selected = select(inbounds, inbound.as_ref().and_then(|tag| tag.as_str()));
+        let derived = match selected.and_then(|inbound| inbound.proxy.clone()) {
+            Ok(proxy) => proxy,
+            Err(e) => {
+                errors.push(error(format!("{}: {}", file, e)));
+                continue;
+            }
+        };
+
+        if !proxy.contains_key("server") {
+            let host = file.strip_suffix(".json").unwrap_or(file);
+            proxy.insert("server".to_string(), Value::from(host));
+        }
+        fill(proxy, derived);
+        if !used.iter().any(|used| used == file) {
+            used.push(file.to_string());
+        }
+    }
+
+    match errors.is_empty() {
+        true => Ok(used),
+        false => Err(errors),
+    }
+}
+
+// 节点中没有的字段使用服务端配置生成的值，映射字段逐个补全
+fn fill(proxy: &mut Proxy, derived: Proxy) {
+    for (key, value) in derived {
+        match (proxy.get_mut(&key), value) {
+            (None, value) => {
+                proxy.insert(key, value);
+            }
+            (Some(Value::Mapping(existing)), Value::Mapping(value)) => {
+                for (key, value) in value {
+                    if !existing.contains_key(&key) {
+                        existing.insert(key, value);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// 服务端配置中可以生成节点的入站，无法生成节点的入站在被选中时才报错
+struct Inbound {
+    tag: Option<String>,
+    proxy: Result<Proxy, String>,
+}
+
+// 选择入站: 指定tag时按tag查找，否则配置中只能有一个可用的入站
+fn select<'a>(inbounds: &'a [Inbound], tag: Option<&str>) -> Result<&'a Inbound, String> {
+    if let Some(tag) = tag {
+        return inbounds
+            .iter()
+            .find(|inbound| inbound.tag.as_deref() == Some(tag))
+            .ok_or_else(|| format!("找不到tag为{}的可用入站", tag));
+    }
+    match inbounds {
+        [] => Err("没有可以生成节点的入站".to_string()),
+        [inbound] => Ok(inbound),
+        _ => {
+            let tags: Vec<&str> = inbounds
+                .iter()
+                .map(|inbound| inbound.tag.as_deref().unwrap_or("(无tag)"))
+                .collect();
+            Err(format!(
+                "有多个入站，需要用{}指定其中一个: {}",
+                INBOUND_KEY,
+                tags.join(", ")
+            ))
+        }
+    }
+}
+
+// 读取服务端配置，解析其中可以生成节点的入站
+fn load(dir: &Path, file: &str) -> Result<Vec<Inbound>, String> {
+    let is_json = Path::new(file).extension().is_some_and(|ext| ext == "json");
+    if file.contains(['/', '\\']) || file.starts_with('.') || !is_json {
+        return Err(format!(
+            "{}只能是{}目录下的json文件名: {}",
+            SERVER_CONFIG_KEY, SERVERS_DIR, file
+        ));
+    }
+    let path = dir.join(file);
+    let content =
+        std::fs::read_to_string(&path).map_err(|e| format!("无法读取{}: {}", path.display(), e))?;
+    let root: Json =
+        serde_json::from_str(&content).map_err(|e| format!("{}: JSON解析失败: {}", file, e))?;
+
+    let inbounds = root
+        .get("inbounds")
+        .and_then(|inbounds| inbounds.as_array())
+        .ok_or_else(|| format!("{}: 没有inbounds列表", file))?;
+    let mut parsed = Vec::new();
+    for inbound in inbounds {
+        // Xray的入站使用protocol字段，sing-box使用type字段
+        let proxy = match (inbound.get("protocol"), inbound.get("type")) {
+            (Some(_), _) => xray_inbound(inbound),
+            (None, Some(_)) => sing_box_inbound(inbound),
+            _ => continue,
+        };
+        let tag = text(inbound, "tag");
+        let proxy = match proxy {
+            Ok(Some(proxy)) => Ok(proxy),
+            Ok(None) => continue,
+            Err(e) => Err(format!(
+                "入站 {}: {}",
+                tag.as_deref().unwrap_or("(无tag)"),
+                e
+            )),
+        };
+        parsed.push(Inbound { tag, proxy });
+    }
+    Ok(parsed)
+}
+
+// 传输方式转换为mihomo的network，Xray的raw即tcp
+// mihomo不支持的传输方式（如xhttp、kcp、quic）返回错误，不生成无法使用的节点
+fn network(name: &str) -> Result<&str, String> {
+    match name {
+        "raw" | "tcp" => Ok("tcp"),
+        "ws" | "grpc" | "httpupgrade" | "h2" | "http" => Ok(name),
+        other => Err(format!("mihomo不支持传输方式{}", other)),
+    }
+}
+
+// Shadowsocks 2022多用户时客户端密码为 服务端密钥:用户密钥
+fn ss_password(method: &str, server: Option<String>, user: Option<String>) -> Option<String> {
+    match (server, user) {
+        (Some(server), Some(user)) if method.starts_with("2022-") => {
+            Some(format!("{}:{}", server, user))
+        }
+        (server, user) => server.or(user),
+    }
+}
+
+// 读取字符串字段，空字符串视为没有
+fn text(value: &Json, key: &str) -> Option<String> {
+    value
+        .get(key)
+        .and_then(|value| value.as_str())
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_string())
+}
+
+// 读取端口，Xray允许写成字符串
+fn port(value: Option<&Json>) -> Result<u16, String> {
+    let port = match value {
+        Some(Json::Number(port)) => port.as_u64(),
+        Some(Json::String(port)) => port.parse().ok(),
+        _ => None,
+    };
+    port.and_then(|port| u16::try_from(port).ok())
+        .filter(|port| *port > 0)
+        .ok_or_else(|| "缺少有效的端口（不支持端口范围）".to_string())
+}
+
+// 列表中第一个元素的字符串字段，如clients[0].id
+fn first(list: Option<&Json>, key: &str) -> Option<String> {
+    list.and_then(|list| list.as_array())
+        .and_then(|list| list.first())
+        .and_then(|item| text(item, key))
+}
+
+// 字符串列表中的第一个非空元素，如httpSettings.host[0]
+fn first_text(list: Option<&Json>) -> Option<String> {
+    list?
+        .as_array()?
+        .first()?
+        .as_str()
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_string())
+}
+
+fn strings(value: Option<&Json>) -> Vec<Value> {
+    value
+        .and_then(|value| value.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|item| item.as_str())
+        .map(Value::from)
+        .collect()
+}
+
+fn base_proxy(proxy_type: &str, port: u16) -> Proxy {
+    let mut proxy = Proxy::new();
+    proxy.insert("type".to_string(), Value::from(proxy_type));
+    proxy.insert("port".to_string(), Value::from(port));
+    proxy
+}
+
+// 由Reality私钥计算客户端使用的公钥
+pub fn reality_public_key(private_key: &str) -> Result<String, String> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(private_key.trim_end_matches('='))
+        .or_else(|_| STANDARD.decode(private_key))
+        .map_err(|_| "Reality私钥不是有效的Base64".to_string())?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| "Reality私钥长度应为32字节".to_string())?;
+    let secret = x25519_dalek::StaticSecret::from(bytes);
+    let public = x25519_dalek::PublicKey::from(&secret);
+    Ok(URL_SAFE_NO_PAD.encode(public.as_bytes()))
+}
+
+// Reality参数，short-id优先使用第一个非空值
+fn reality_opts(private_key: Option<&str>, short_ids: &[Value]) -> Result<Mapping, String> {
+    let private_key = private_key.ok_or("Reality缺少私钥")?;
+    let mut opts = Mapping::new();
+    opts.insert("public-key".into(), reality_public_key(private_key)?.into());
+    let short_id = short_ids
+        .iter()
+        .find(|id| id.as_str().is_some_and(|id| !id.is_empty()))
+        .or(short_ids.first());
+    if let Some(short_id) = short_id {
+        opts.insert("short-id".into(), short_id.clone());
+    }
+    Ok(opts)
+}
+
+// Xray入站: vless、vmess、trojan、shadowsocks，其他协议忽略
+fn xray_inbound(inbound: &Json) -> Result<Option<Proxy>, String> {
+    let protocol = inbound
+        .get("protocol")
+        .and_then(|protocol| protocol.as_str())
+        .unwrap_or_default();
+    if !matches!(protocol, "vless" | "vmess" | "trojan" | "shadowsocks") {
+        return Ok(None);
+    }
+    let port = port(inbound.get("port"))?;
+    let settings = inbound.get("settings").unwrap_or(&Json::Null);
+    let clients = settings.get("clients");
+
+    let mut proxy = match protocol {
+        "vless" => {
+            let mut proxy = base_proxy("vless", port);
+            let uuid = first(clients, "id").ok_or("clients中没有用户")?;
+            proxy.insert("uuid".to_string(), Value::from(uuid));
+            if let Some(flow) = first(clients, "flow") {
+                proxy.insert("flow".to_string(), Value::from(flow));
+            }
+            proxy
+        }
+        "vmess" => {
+            let mut proxy = base_proxy("vmess", port);
+            let uuid = first(clients, "id").ok_or("clients中没有用户")?;
+            proxy.insert("uuid".to_string(), Value::from(uuid));
+            proxy.insert("alterId".to_string(), Value::from(0));
+            proxy.insert("cipher".to_string(), Value::from("auto"));
+            proxy
+        }
+        "trojan" => {
+            let mut proxy = base_proxy("trojan", port);
+            let password = first(clients, "password").ok_or("clients中没有用户")?;
+            proxy.insert("password".to_string(), Value::from(password));
+            proxy
+        }
+        _ => {
+            let mut proxy = base_proxy("ss", port);
+            let method = text(settings, "method")
+                .or_else(|| first(clients, "method"))
+                .ok_or("缺少加密方式")?;
+            let password = ss_password(
+                &method,
+                text(settings, "password"),
+                first(clients, "password"),
+            )
+            .ok_or("缺少密码")?;
+            proxy.insert("cipher".to_string(), Value::from(method));
+            proxy.insert("password".to_string(), Value::from(password));
+            proxy
+        }
+    };
+    proxy.insert("udp".to_string(), Value::from(true));
+
+    let stream = inbound.get("streamSettings").unwrap_or(&Json::Null);
+    let network = text(stream, "network").unwrap_or_else(|| "tcp".to_string());
+    if protocol != "shadowsocks" {
+        let network = self::network(&network)?;
+        let (path, host) = match network {
+            "ws" => {
+                let ws = stream.get("wsSettings").unwrap_or(&Json::Null);
+                let host = text(ws, "host")
+                    .or_else(|| ws.get("headers").and_then(|headers| text(headers, "Host")));
+                (text(ws, "path"), host)
+            }
+            "grpc" => {
+                let grpc = stream.get("grpcSettings").unwrap_or(&Json::Null);
+                (text(grpc, "serviceName"), None)
+            }
+            "httpupgrade" => {
+                let upgrade = stream.get("httpupgradeSettings").unwrap_or(&Json::Null);
+                (text(upgrade, "path"), text(upgrade, "host"))
+            }
+            "h2" | "http" => {
+                let http = stream.get("httpSettings").unwrap_or(&Json::Null);
+                (text(http, "path"), first_text(http.get("host")))
+            }
+            _ => (None, None),
+        };
+        apply_transport(&mut proxy, Some(network), path.as_deref(), host.as_deref());
+    }
+
+    match text(stream, "security").as_deref() {
+        Some("tls") => {
+            let tls = stream.get("tlsSettings").unwrap_or(&Json::Null);
+            proxy.insert("tls".to_string(), Value::from(true));
+            if let Some(server_name) = text(tls, "serverName") {
+                proxy.insert(sni_key(protocol).to_string(), Value::from(server_name));
+            }
+            let alpn = strings(tls.get("alpn"));
+            if !alpn.is_empty() {
+                proxy.insert("alpn".to_string(), Value::Sequence(alpn));
+            }
+        }
+        Some("reality") => {
+            let reality = stream.get("realitySettings").unwrap_or(&Json::Null);
+            proxy.insert("tls".to_string(), Value::from(true));
+            if let Some(Value::String(server_name)) = strings(reality.get("serverNames")).first() {
+                proxy.insert(
+                    sni_key(protocol).to_string(),
+                    Value::from(server_name.as_str()),
+                );
+            }
+            let opts = reality_opts(
+                reality.get("privateKey").and_then(|key| key.as_str()),
+                &strings(reality.get("shortIds")),
+            )?;
+            proxy.insert("reality-opts".to_string(), Value::Mapping(opts));
+            proxy.insert("client-fingerprint".to_string(), Value::from("chrome"));
+        }
+        _ => {}
+    }
+    Ok(Some(proxy))
+}
+
+// trojan节点的SNI字段为sni，其他为servername
+fn sni_key(protocol: &str) -> &'static str {
+    match protocol {
+        "trojan" => "sni",
+        _ => "servername",
+    }
+}
+
+// sing-box入站: vless、vmess、trojan、shadowsocks、hysteria2、tuic，其他类型忽略
+fn sing_box_inbound(inbound: &Json) -> Result<Option<Proxy>, String> {
+    let inbound_type = inbound
+        .get("type")
+        .and_then(|inbound_type| inbound_type.as_str())
+        .unwrap_or_default();
+    let proxy_type = match inbound_type {
+        "vless" | "vmess" | "trojan" | "hysteria2" | "tuic" => inbound_type,
+        "shadowsocks" => "ss",
+        _ => return Ok(None),
+    };
+    let port = port(inbound.get("listen_port"))?;
+    let users = inbound.get("users");
+    let mut proxy = base_proxy(proxy_type, port);
+
+    match inbound_type {
+        "vless" | "vmess" | "tuic" => {
+            let uuid = first(users, "uuid").ok_or("users中没有用户")?;
+            proxy.insert("uuid".to_string(), Value::from(uuid));
+        }
+        _ => {}
+    }
+    match inbound_type {
+        "trojan" | "hysteria2" | "tuic" => {
+            let password = first(users, "password").ok_or("users中没有用户")?;
+            proxy.insert("password".to_string(), Value::from(password));
+        }
+        "shadowsocks" => {
+            let method = text(inbound, "method").ok_or("缺少加密方式")?;
+            let password =
+                ss_password(&method, text(inbound, "password"), first(users, "password"))
+                    .ok_or("缺少密码")?;
+            proxy.insert("cipher".to_string(), Value::from(method));
+            proxy.insert("password".to_string(), Value::from(password));
+        }
+        _ => {}
+    }
+    match inbound_type {
+        "vless" => {
+            if let Some(flow) = first(users, "flow") {
+                proxy.insert("flow".to_string(), Value::from(flow));
+            }
+        }
+        "vmess" => {
+            proxy.insert("alterId".to_string(), Value::from(0));
+            proxy.insert("cipher".to_string(), Value::from("auto"));
+        }
+        "hysteria2" => {
+            if let Some(obfs) = inbound.get("obfs") {
+                if let Some(obfs_type) = text(obfs, "type") {
+                    proxy.insert("obfs".to_string(), Value::from(obfs_type));
+                }
+                if let Some(password) = text(obfs, "password") {
+                    proxy.insert("obfs-password".to_string(), Value::from(password));
+                }
+            }
+        }
+        "tuic" => {
+            if let Some(congestion) = text(inbound, "congestion_control") {
+                proxy.insert("congestion-controller".to_string(), Value::from(congestion));
+            }
+        }
+        _ => {}
+    }
+    proxy.insert("udp".to_string(), Value::from(true));
+
+    if let Some(transport_settings) = inbound.get("transport") {
+        let network = text(transport_settings, "type").unwrap_or_else(|| "tcp".to_string());
+        let network = self::network(&network)?;
+        let path = match network {
+            "grpc" => text(transport_settings, "service_name"),
+            _ => text(transport_settings, "path"),
+        };
+        let host = transport_settings
+            .get("headers")
+            .and_then(|headers| text(headers, "Host"))
+            .or_else(|| text(transport_settings, "host"))
+            .or_else(|| first_text(transport_settings.get("host")));
+        apply_transport(&mut proxy, Some(network), path.as_deref(), host.as_deref());
+    } else if matches!(inbound_type, "vless" | "vmess" | "trojan") {
+        proxy.insert("network".to_string(), Value::from("tcp"));
+    }
+
+    let tls = inbound.get("tls").unwrap_or(&Json::Null);
+    if tls.get("enabled").and_then(|enabled| enabled.as_bool()) == Some(true) {
+        // hysteria2和tuic始终使用TLS，节点中不需要tls字段
+        let sni_key = match inbound_type {
+            "vless" | "vmess" => {
+                proxy.insert("tls".to_string(), Value::from(true));
+                "servername"
+            }
+            _ => "sni",
+        };
+        if let Some(server_name) = text(tls, "server_name") {
+            proxy.insert(sni_key.to_string(), Value::from(server_name));
+        }
+        let alpn = strings(tls.get("alpn"));
+        if !alpn.is_empty() {
+            proxy.insert("alpn".to_string(), Value::Sequence(alpn));
+        }
+        let reality = tls.get("reality").unwrap_or(&Json::Null);
+        if reality.get("enabled").and_then(|enabled| enabled.as_bool()) == Some(true) {
+            let opts = reality_opts(
+                reality.get("private_key").and_then(|key| key.as_str()),
+                &strings(reality.get("short_id")),
+            )?;
+            proxy.insert("reality-opts".to_string(), Value::Mapping(opts));
+            proxy.insert("client-fingerprint".to_string(), Value::from("chrome"));
+        }
+    }
+    Ok(Some(proxy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIVATE_KEY: &str = "yBaw532IIUNuQWDTncozoBaLJmcd1JZzvsHUgVPxMk8";
+    const PUBLIC_KEY: &str = "7xhH4b_VkliBxGulljcyPOH-bYUA2dl-XAdZAsfhk04";
+
+    fn json(text: &str) -> Json {
+        serde_json::from_str(text).unwrap()
+    }
+
+    fn proxy(yaml: &str) -> Proxy {
+        serde_yaml_ng::from_str(yaml).unwrap()
+    }
+
+    fn value(yaml: &str) -> Value {
+        serde_yaml_ng::from_str(yaml).unwrap()
+    }
+
+    fn xray(text: &str) -> Proxy {
+        xray_inbound(&json(text)).unwrap().unwrap()
+    }
+
+    fn sing_box(text: &str) -> Proxy {
+        sing_box_inbound(&json(text)).unwrap().unwrap()
+    }
+
+    #[test]
+    fn xray_vless_reality() {
+        let inbound = format!(
+            r#"{{"protocol": "vless", "port": 443,
+                "settings": {{"clients": [{{"id": "uuid-1", "flow": "xtls-rprx-vision"}}]}},
+                "streamSettings": {{"network": "raw", "security": "reality",
+                    "realitySettings": {{"serverNames": ["www.apple.com"],
+                        "privateKey": "{}", "shortIds": ["", "ab"]}}}}}}"#,
+            PRIVATE_KEY
+        );
+        let expected = format!(
+            "{{type: vless, port: 443, uuid: uuid-1, flow: xtls-rprx-vision, udp: true,
+              network: tcp, tls: true, servername: www.apple.com, client-fingerprint: chrome,
+              reality-opts: {{public-key: {}, short-id: ab}}}}",
+            PUBLIC_KEY
+        );
+        assert_eq!(xray(&inbound), proxy(&expected));
+    }
+
+    #[test]
+    fn xray_vmess_ws() {
+        let inbound = r#"{"protocol": "vmess", "port": "8443",
+            "settings": {"clients": [{"id": "uuid-2"}]},
+            "streamSettings": {"network": "ws", "security": "tls",
+                "wsSettings": {"path": "/ws", "headers": {"Host": "cdn.example.com"}},
+                "tlsSettings": {"serverName": "cdn.example.com", "alpn": ["h2", "http/1.1"]}}}"#;
+        let expected = "{type: vmess, port: 8443, uuid: uuid-2, alterId: 0, cipher: auto,
+            udp: true, network: ws, ws-opts: {path: /ws, headers: {Host: cdn.example.com}},
+            tls: true, servername: cdn.example.com, alpn: [h2, http/1.1]}";
+        assert_eq!(xray(inbound), proxy(expected));
+    }
+
+    #[test]
+    fn xray_transports() {
+        let inbound = |stream: &str| {
+            format!(
+                r#"{{"protocol": "vless", "port": 443,
+                    "settings": {{"clients": [{{"id": "uuid-3"}}]}}, "streamSettings": {}}}"#,
+                stream
+            )
+        };
+        let h2 = xray(&inbound(
+            r#"{"network": "h2", "httpSettings": {"path": "/h2", "host": ["", "b.com"]}}"#,
+        ));
+        assert_eq!(h2["network"], Value::from("h2"));
+        // 第一个host为空时不设置host
+        assert_eq!(h2["h2-opts"], value("{path: /h2}"));
+        let h2 = xray(&inbound(
+            r#"{"network": "http", "httpSettings": {"host": ["a.com", "b.com"]}}"#,
+        ));
+        assert_eq!(h2["network"], Value::from("h2"));
+        assert_eq!(h2["h2-opts"], value("{host: [a.com]}"));
+
+        let upgrade = xray(&inbound(
+            r#"{"network": "httpupgrade", "httpupgradeSettings": {"path": "/up", "host": "a.com"}}"#,
+        ));
+        assert_eq!(upgrade["network"], Value::from("ws"));
+        assert_eq!(
+            upgrade["ws-opts"],
+            value("{path: /up, headers: {Host: a.com}, v2ray-http-upgrade: true}")
+        );
+
+        for network in ["xhttp", "kcp"] {
+            let stream = format!(r#"{{"network": "{}"}}"#, network);
+            let error = xray_inbound(&json(&inbound(&stream))).unwrap_err();
+            assert!(error.contains(network), "{}", error);
+        }
+    }
+
+    #[test]
+    fn shadowsocks_passwords() {
+        let single = r#"{"protocol": "shadowsocks", "port": 8388,
+            "settings": {"method": "aes-128-gcm", "password": "secret"}}"#;
+        let expected = "{type: ss, port: 8388, cipher: aes-128-gcm, password: secret, udp: true}";
+        assert_eq!(xray(single), proxy(expected));
+
+        // 2022多用户: 服务端密钥:用户密钥
+        let multi = r#"{"protocol": "shadowsocks", "port": 8388,
+            "settings": {"method": "2022-blake3-aes-128-gcm", "password": "c2VydmVy",
+                "clients": [{"password": "dXNlcg=="}]}}"#;
+        let expected = "{type: ss, port: 8388, cipher: 2022-blake3-aes-128-gcm,
+            password: 'c2VydmVy:dXNlcg==', udp: true}";
+        assert_eq!(xray(multi), proxy(expected));
+
+        let multi = r#"{"type": "shadowsocks", "listen_port": 8388,
+            "method": "2022-blake3-aes-128-gcm", "password": "c2VydmVy",
+            "users": [{"name": "a", "password": "dXNlcg=="}]}"#;
+        assert_eq!(sing_box(multi), proxy(expected));
+    }
+
+    #[test]
+    fn sing_box_trojan_grpc() {
+        let inbound = r#"{"type": "trojan", "listen_port": 443,
+            "users": [{"password": "pw"}],
+            "transport": {"type": "grpc", "service_name": "svc"},
+            "tls": {"enabled": true, "server_name": "tj.example.com"}}"#;
+        let expected = "{type: trojan, port: 443, password: pw, udp: true, network: grpc,
+            grpc-opts: {grpc-service-name: svc}, sni: tj.example.com}";
+        assert_eq!(sing_box(inbound), proxy(expected));
+    }
+
+    #[test]
+    fn sing_box_hysteria2() {
+        let inbound = r#"{"type": "hysteria2", "listen_port": 8443,
+            "users": [{"password": "hy"}],
+            "obfs": {"type": "salamander", "password": "ob"},
+            "tls": {"enabled": true, "server_name": "hy.example.com", "alpn": ["h3"]}}"#;
+        let expected = "{type: hysteria2, port: 8443, password: hy, obfs: salamander,
+            obfs-password: ob, udp: true, sni: hy.example.com, alpn: [h3]}";
+        assert_eq!(sing_box(inbound), proxy(expected));
+    }
+
+    #[test]
+    fn expand_reports_errors() {
+        let dir = std::env::temp_dir().join(format!("raygo-servers-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("hk.example.com.json"),
+            r#"{"inbounds": [
+                {"tag": "ws", "protocol": "vless", "port": 443,
+                    "settings": {"clients": [{"id": "uuid-4"}]},
+                    "streamSettings": {"network": "ws", "wsSettings": {"path": "/ws"}}},
+                {"tag": "xhttp", "protocol": "vless", "port": 8443,
+                    "settings": {"clients": [{"id": "uuid-4"}]},
+                    "streamSettings": {"network": "xhttp"}}
+            ]}"#,
+        )
+        .unwrap();
+
+        let mut config: ClashConfig = serde_yaml_ng::from_str(
+            "proxies:
+              - {name: ok, type: vless, server-config: hk.example.com.json, inbound: ws}
+              - {name: missing, type: vless, server-config: missing.json}
+              - {name: tag, type: vless, server-config: hk.example.com.json, inbound: nope}
+              - {name: path, type: vless, server-config: ../hk.example.com.json}
+              - {name: xhttp, type: vless, server-config: hk.example.com.json, inbound: xhttp}
+              - {name: many, type: vless, server-config: hk.example.com.json}",
+        )
+        .unwrap();
+        let errors = expand_from(&mut config, &dir).unwrap_err();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let message = |name: &str| {
+            errors
+                .iter()
+                .find(|error| error.proxy == name)
+                .map(|error| error.message.clone())
+                .unwrap_or_default()
+        };
+        assert_eq!(errors.len(), 5);
+        assert!(message("missing").contains("无法读取"));
+        assert!(message("tag").contains("找不到tag为nope"));
+        assert!(message("path").contains("只能是"));
+        assert!(message("xhttp").contains("xhttp"));
+        assert!(message("many").contains("有多个入站"));
+
+        // 成功的节点仍然补全，server默认为文件名
+        let ok = &config.proxies.as_ref().unwrap()[0];
+        assert_eq!(ok["server"], Value::from("hk.example.com"));
+        assert_eq!(ok["uuid"], Value::from("uuid-4"));
+        assert_eq!(ok["network"], Value::from("ws"));
+        assert!(!ok.contains_key(SERVER_CONFIG_KEY) && !ok.contains_key(INBOUND_KEY));
+    }
+}
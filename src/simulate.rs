@@ -0,0 +1,694 @@
+use crate::geo::{DomainKind, GeoFiles};
+use crate::models::{ClashConfig, ProxyGroupType, Rule, RuleBehavior, RuleProviderType, RuleType};
+use crate::provider::RuleSets;
+use crate::rules;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+
+// 子规则最多嵌套的层数
+const MAX_DEPTH: usize = 8;
+
+// 模拟的连接
+pub struct Connection {
+    // 目标域名，小写
+    pub domain: Option<String>,
+    // 目标IP，提供域名时视为域名解析的结果
+    pub ip: Option<IpAddr>,
+    pub port: Option<u16>,
+    pub udp: bool,
+}
+
+// 规则所在的位置
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleRef {
+    // rules，或子规则的名称
+    pub source: String,
+    // 在列表中的序号，从1开始
+    pub index: usize,
+    pub rule: String,
+    // 命中的规则集条目，或无法判断的原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+// 策略链中的一步
+#[derive(Debug, Serialize)]
+pub struct ChainStep {
+    pub name: String,
+    // 代理组类型，或 proxy、builtin、missing
+    pub kind: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub members: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+// 模拟结果
+#[derive(Debug, Default, Serialize)]
+pub struct Simulation {
+    // 命中的规则，经过SUB-RULE时依次包含子规则中命中的规则
+    pub matched: Vec<RuleRef>,
+    pub target: Option<String>,
+    // 从目标策略开始的代理组链
+    pub chain: Vec<ChainStep>,
+    // 命中之前无法在服务端判断的规则，实际可能先于命中的规则生效
+    pub undetermined: Vec<RuleRef>,
+    // 命中之后同样会命中、被遮蔽的规则（不含MATCH）
+    pub shadowed: Vec<RuleRef>,
+}
+
+// 条件的判断结果
+enum Check {
+    // 命中，附带命中的规则集或geo条目
+    Match(Option<String>),
+    Miss,
+    // 依赖客户端信息或下载的内容，无法判断
+    Unknown(String),
+}
+
+// 规则的判断结果
+enum Outcome {
+    // (命中的条目, 子规则中命中的规则, 目标策略)
+    Hit(Option<String>, Vec<RuleRef>, String),
+    Miss,
+    Unknown(String),
+}
+
+// 按规则顺序匹配连接，返回第一条命中的规则和对应的代理组链
+pub fn simulate(config: &ClashConfig, hosted: &RuleSets, connection: &Connection) -> Simulation {
+    let rules: Vec<(usize, String, Rule)> = config
+        .rules
+        .iter()
+        .flatten()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            rules::parse_rule(line)
+                .ok()
+                .map(|rule| (index + 1, line.clone(), rule))
+        })
+        .collect();
+
+    let mut simulator = Simulator {
+        config,
+        hosted,
+        connection,
+        geo: GeoFiles::default(),
+        regexes: HashMap::new(),
+    };
+    let mut result = Simulation::default();
+    let mut shadowed = Vec::new();
+    if let Some((matched, target)) = simulator.walk(
+        "rules",
+        &rules,
+        0,
+        &mut result.undetermined,
+        Some(&mut shadowed),
+    ) {
+        result.chain = chain(config, &target);
+        result.matched = matched;
+        result.target = Some(target);
+    }
+    result.shadowed = shadowed;
+    result
+}
+
+struct Simulator<'a> {
+    config: &'a ClashConfig,
+    hosted: &'a RuleSets,
+    connection: &'a Connection,
+    geo: GeoFiles,
+    // 编译过的正则，无效的正则为None
+    regexes: HashMap<String, Option<Regex>>,
+}
+
+impl<'a> Simulator<'a> {
+    // 依次匹配规则列表，返回命中的规则路径和目标策略
+    // 提供shadowed时命中之后继续匹配，记录被遮蔽的规则
+    fn walk(
+        &mut self,
+        source: &str,
+        rules: &[(usize, String, Rule)],
+        depth: usize,
+        undetermined: &mut Vec<RuleRef>,
+        mut shadowed: Option<&mut Vec<RuleRef>>,
+    ) -> Option<(Vec<RuleRef>, String)> {
+        let mut hit: Option<(Vec<RuleRef>, String)> = None;
+        for (index, text, rule) in rules {
+            if hit.is_some() {
+                match shadowed {
+                    None => break,
+                    Some(_) if rule.rule_type == RuleType::Match => continue,
+                    Some(_) => {}
+                }
+            }
+            let reference = |detail: Option<String>| RuleRef {
+                source: source.to_string(),
+                index: *index,
+                rule: text.clone(),
+                detail,
+            };
+
+            // 命中之后只判断是否被遮蔽，子规则中无法判断的规则不再记录
+            let mut nested = Vec::new();
+            match self.rule(rule, depth, &mut nested) {
+                Outcome::Hit(detail, sub_rules, target) => match (&hit, shadowed.as_deref_mut()) {
+                    (None, _) => {
+                        undetermined.append(&mut nested);
+                        let mut matched = vec![reference(detail)];
+                        matched.extend(sub_rules);
+                        hit = Some((matched, target));
+                    }
+                    (Some(_), Some(shadowed)) => shadowed.push(reference(detail)),
+                    (Some(_), None) => {}
+                },
+                Outcome::Unknown(reason) if hit.is_none() => {
+                    undetermined.append(&mut nested);
+                    undetermined.push(reference(Some(reason)));
+                }
+                _ if hit.is_none() => undetermined.append(&mut nested),
+                _ => {}
+            }
+        }
+        hit
+    }
+
+    fn rule(&mut self, rule: &Rule, depth: usize, undetermined: &mut Vec<RuleRef>) -> Outcome {
+        if rule.rule_type != RuleType::SubRule {
+            let params = rule.params.as_deref().unwrap_or_default();
+            return match self.check(rule.rule_type, &rule.payload, params) {
+                Check::Match(detail) => Outcome::Hit(detail, Vec::new(), rule.target.clone()),
+                Check::Miss => Outcome::Miss,
+                Check::Unknown(reason) => Outcome::Unknown(reason),
+            };
+        }
+
+        // SUB-RULE,(条件),子规则名称: 条件命中后依次匹配子规则，子规则都不命中时继续匹配后面的规则
+        match self.nested(&rule.payload) {
+            Check::Match(_) => {}
+            Check::Miss => return Outcome::Miss,
+            Check::Unknown(reason) => return Outcome::Unknown(reason),
+        }
+        let config = self.config;
+        let Some(sub_rule) = config
+            .sub_rules
+            .as_ref()
+            .and_then(|sub_rules| sub_rules.get(&rule.target))
+        else {
+            return Outcome::Unknown(format!("子规则不存在: {}", rule.target));
+        };
+        if depth >= MAX_DEPTH {
+            return Outcome::Unknown("子规则嵌套过深".to_string());
+        }
+        let rules: Vec<(usize, String, Rule)> = sub_rule
+            .rules
+            .iter()
+            .enumerate()
+            .map(|(index, rule)| (index + 1, rule_text(rule), rule.clone()))
+            .collect();
+        match self.walk(&rule.target, &rules, depth + 1, undetermined, None) {
+            Some((matched, target)) => Outcome::Hit(None, matched, target),
+            None => Outcome::Miss,
+        }
+    }
+
+    // 判断一个条件，params为规则的附加参数，如 no-resolve、src
+    fn check(&mut self, rule_type: RuleType, payload: &str, params: &[String]) -> Check {
+        let has_param = |name: &str| params.iter().any(|p| p.eq_ignore_ascii_case(name));
+        let no_resolve = has_param("no-resolve");
+        if has_param("src") {
+            return Check::Unknown("src参数按来源地址匹配，无法模拟".to_string());
+        }
+
+        match rule_type {
+            RuleType::Domain => {
+                let payload = payload.to_lowercase();
+                self.domain(|domain| domain == payload)
+            }
+            RuleType::DomainSuffix => {
+                let payload = payload.to_lowercase();
+                self.domain(|domain| suffix_match(domain, &payload))
+            }
+            RuleType::DomainKeyword => {
+                let payload = payload.to_lowercase();
+                self.domain(|domain| domain.contains(&payload))
+            }
+            RuleType::DomainRegex => {
+                let Some(domain) = &self.connection.domain else {
+                    return Check::Miss;
+                };
+                match self.regex(payload) {
+                    Some(regex) if regex.is_match(domain) => Check::Match(None),
+                    Some(_) => Check::Miss,
+                    None => Check::Unknown(format!("无效的正则表达式: {}", payload)),
+                }
+            }
+            RuleType::Geosite => self.geosite(payload),
+            RuleType::IpCidr | RuleType::IpCidr6 => {
+                self.ip(no_resolve, |ip| match cidr_contains(payload, ip) {
+                    Some(true) => Check::Match(None),
+                    Some(false) => Check::Miss,
+                    None => Check::Unknown(format!("无效的CIDR: {}", payload)),
+                })
+            }
+            RuleType::IpSuffix => self.ip(no_resolve, |ip| match suffix_contains(payload, ip) {
+                Some(true) => Check::Match(None),
+                Some(false) => Check::Miss,
+                None => Check::Unknown(format!("无效的IP-SUFFIX: {}", payload)),
+            }),
+            RuleType::Geoip => self.geoip(payload, no_resolve),
+            RuleType::DstPort => match self.connection.port {
+                Some(port) => match port_match(payload, port) {
+                    true => Check::Match(None),
+                    false => Check::Miss,
+                },
+                None => Check::Unknown("未提供端口".to_string()),
+            },
+            RuleType::Network => {
+                let network = if self.connection.udp { "udp" } else { "tcp" };
+                match payload.eq_ignore_ascii_case(network) {
+                    true => Check::Match(None),
+                    false => Check::Miss,
+                }
+            }
+            RuleType::RuleSet => self.rule_set(payload, no_resolve),
+            RuleType::And | RuleType::Or | RuleType::Not => self.logic(rule_type, payload),
+            RuleType::Match => Check::Match(None),
+            RuleType::SubRule => Check::Unknown("逻辑规则中不能使用SUB-RULE".to_string()),
+            other => Check::Unknown(format!("{}规则依赖客户端信息，无法模拟", type_name(other))),
+        }
+    }
+
+    // 括号中的条件，如 (DOMAIN,example.com) 或 (AND,((NETWORK,UDP),(DST-PORT,443)))
+    fn nested(&mut self, condition: &str) -> Check {
+        let condition = condition.trim();
+        let Some(inner) = condition
+            .strip_prefix('(')
+            .and_then(|inner| inner.strip_suffix(')'))
+        else {
+            return Check::Unknown(format!("条件格式错误: {}", condition));
+        };
+        let parts = rules::split_rule(inner);
+        let Some(rule_type) = rules::parse_rule_type(parts[0]) else {
+            return Check::Unknown(format!("未知的规则类型: {}", parts[0]));
+        };
+        let payload = parts.get(1).copied().unwrap_or_default();
+        let params: Vec<String> = parts.iter().skip(2).map(|p| p.to_string()).collect();
+        self.check(rule_type, payload, &params)
+    }
+
+    // AND/OR/NOT，payload为 ((条件),(条件))
+    fn logic(&mut self, rule_type: RuleType, payload: &str) -> Check {
+        let payload = payload.trim();
+        let Some(inner) = payload
+            .strip_prefix('(')
+            .and_then(|inner| inner.strip_suffix(')'))
+        else {
+            return Check::Unknown(format!("逻辑规则格式错误: {}", payload));
+        };
+        let checks: Vec<Check> = rules::split_rule(inner)
+            .into_iter()
+            .filter(|condition| !condition.is_empty())
+            .map(|condition| self.nested(condition))
+            .collect();
+        let unknown = checks.iter().find_map(|check| match check {
+            Check::Unknown(reason) => Some(reason.clone()),
+            _ => None,
+        });
+        let matched = checks
+            .iter()
+            .filter(|c| matches!(c, Check::Match(_)))
+            .count();
+        let missed = checks.iter().filter(|c| matches!(c, Check::Miss)).count();
+
+        match rule_type {
+            RuleType::And if missed > 0 => Check::Miss,
+            RuleType::And if matched == checks.len() => Check::Match(None),
+            RuleType::Or if matched > 0 => Check::Match(None),
+            RuleType::Or if missed == checks.len() => Check::Miss,
+            RuleType::Not if checks.len() != 1 => {
+                Check::Unknown("NOT规则只能包含一个条件".to_string())
+            }
+            RuleType::Not if matched == 1 => Check::Miss,
+            RuleType::Not if missed == 1 => Check::Match(None),
+            _ => Check::Unknown(unknown.unwrap_or_default()),
+        }
+    }
+
+    // 按域名判断，没有域名时不命中
+    fn domain(&self, matcher: impl Fn(&str) -> bool) -> Check {
+        match &self.connection.domain {
+            Some(domain) if matcher(domain) => Check::Match(None),
+            _ => Check::Miss,
+        }
+    }
+
+    // 按IP判断；没有IP时，有域名且规则未设置no-resolve的情况下客户端会先解析域名，无法判断
+    fn ip(&self, no_resolve: bool, matcher: impl FnOnce(IpAddr) -> Check) -> Check {
+        match (self.connection.ip, &self.connection.domain) {
+            (Some(ip), _) => matcher(ip),
+            (None, Some(_)) if !no_resolve => {
+                Check::Unknown("需要解析域名，请同时提供解析得到的IP".to_string())
+            }
+            (None, _) => Check::Miss,
+        }
+    }
+
+    fn regex(&mut self, pattern: &str) -> Option<&Regex> {
+        self.regexes
+            .entry(pattern.to_string())
+            .or_insert_with(|| Regex::new(pattern).ok())
+            .as_ref()
+    }
+
+    fn geosite(&mut self, category: &str) -> Check {
+        let Some(domain) = self.connection.domain.clone() else {
+            return Check::Miss;
+        };
+        let domains = match self.geo.site(category) {
+            Ok(domains) => domains,
+            Err(e) => return Check::Unknown(e),
+        };
+        for entry in domains {
+            let matched = match entry.kind {
+                DomainKind::Full => domain == entry.value,
+                DomainKind::Suffix => suffix_match(&domain, &entry.value),
+                DomainKind::Keyword => domain.contains(&entry.value),
+                DomainKind::Regex => self
+                    .regex(&entry.value)
+                    .is_some_and(|regex| regex.is_match(&domain)),
+            };
+            if matched {
+                return Check::Match(Some(format!("geosite:{} 中的 {}", category, entry.value)));
+            }
+        }
+        Check::Miss
+    }
+
+    fn geoip(&mut self, category: &str, no_resolve: bool) -> Check {
+        // 没有IP时按是否需要解析域名返回
+        let Some(ip) = self.connection.ip else {
+            return self.ip(no_resolve, |_| Check::Miss);
+        };
+        // LAN为内核内置的局域网地址
+        if category.eq_ignore_ascii_case("lan") {
+            return match is_lan(ip) {
+                true => Check::Match(None),
+                false => Check::Miss,
+            };
+        }
+        let cidrs = match self.geo.ip(category) {
+            Ok(cidrs) => cidrs,
+            Err(e) => return Check::Unknown(e),
+        };
+        match cidrs
+            .iter()
+            .find(|cidr| cidr_contains(cidr, ip) == Some(true))
+        {
+            Some(cidr) => Check::Match(Some(format!("geoip:{} 中的 {}", category, cidr))),
+            None => Check::Miss,
+        }
+    }
+
+    // RULE-SET: inline规则集和托管规则集可以在服务端判断，其他规则集由客户端下载
+    fn rule_set(&mut self, name: &str, no_resolve: bool) -> Check {
+        let config = self.config;
+        let Some(provider) = config
+            .rule_providers
+            .as_ref()
+            .and_then(|providers| providers.get(name))
+        else {
+            return Check::Unknown(format!("rule-provider不存在: {}", name));
+        };
+        let (behavior, payload): (&RuleBehavior, &[String]) = match provider.provider_type {
+            RuleProviderType::Inline => (
+                &provider.behavior,
+                provider.payload.as_deref().unwrap_or_default(),
+            ),
+            _ => match self.hosted.get(name) {
+                Some(rule_set) => (&rule_set.behavior, &rule_set.payload),
+                None => {
+                    return Check::Unknown(format!(
+                        "规则集 {} 由客户端下载，无法在服务端判断",
+                        name
+                    ));
+                }
+            },
+        };
+        let found = |entry: &String| Check::Match(Some(format!("规则集 {} 中的 {}", name, entry)));
+
+        match behavior {
+            RuleBehavior::Domain => {
+                let Some(domain) = &self.connection.domain else {
+                    return Check::Miss;
+                };
+                match payload
+                    .iter()
+                    .find(|entry| domain_entry_match(entry, domain))
+                {
+                    Some(entry) => found(entry),
+                    None => Check::Miss,
+                }
+            }
+            RuleBehavior::Ipcidr => self.ip(no_resolve, |ip| {
+                match payload
+                    .iter()
+                    .find(|entry| cidr_contains(entry, ip) == Some(true))
+                {
+                    Some(entry) => found(entry),
+                    None => Check::Miss,
+                }
+            }),
+            RuleBehavior::Classical => {
+                let mut unknown = None;
+                for entry in payload {
+                    let parts = rules::split_rule(entry);
+                    let Some(rule_type) = rules::parse_rule_type(parts[0]) else {
+                        continue;
+                    };
+                    let entry_payload = parts.get(1).copied().unwrap_or_default();
+                    let params: Vec<String> = parts.iter().skip(2).map(|p| p.to_string()).collect();
+                    match self.check(rule_type, entry_payload, &params) {
+                        Check::Match(_) => return found(entry),
+                        Check::Unknown(reason) => {
+                            unknown.get_or_insert(format!(
+                                "规则集 {} 中的 {}: {}",
+                                name, entry, reason
+                            ));
+                        }
+                        Check::Miss => {}
+                    }
+                }
+                match unknown {
+                    Some(reason) => Check::Unknown(reason),
+                    None => Check::Miss,
+                }
+            }
+        }
+    }
+}
+
+// 从目标策略开始展开代理组: select默认使用第一个成员，其他类型由客户端按测速或负载均衡选择
+fn chain(config: &ClashConfig, target: &str) -> Vec<ChainStep> {
+    let groups: HashMap<&str, _> = config
+        .proxy_groups
+        .iter()
+        .flatten()
+        .map(|group| (group.name.as_str(), group))
+        .collect();
+    let proxies: HashSet<&str> = config
+        .proxies
+        .iter()
+        .flatten()
+        .filter_map(|proxy| proxy.get("name").and_then(|name| name.as_str()))
+        .collect();
+
+    let mut steps = Vec::new();
+    let mut visited = HashSet::new();
+    let mut name = target.to_string();
+    loop {
+        if !visited.insert(name.clone()) {
+            steps.push(ChainStep {
+                name,
+                kind: "missing",
+                members: Vec::new(),
+                note: Some("代理组循环引用".to_string()),
+            });
+            break;
+        }
+        let Some(group) = groups.get(name.as_str()) else {
+            let (kind, note) = if rules::is_builtin_target(&name) {
+                ("builtin", None)
+            } else if proxies.contains(name.as_str()) {
+                ("proxy", None)
+            } else {
+                (
+                    "missing",
+                    Some("不是proxies或proxy-groups中的名称".to_string()),
+                )
+            };
+            steps.push(ChainStep {
+                name,
+                kind,
+                members: Vec::new(),
+                note,
+            });
+            break;
+        };
+
+        let members = group.proxies.clone().unwrap_or_default();
+        let dynamic = group.use_providers.as_ref().is_some_and(|p| !p.is_empty())
+            || group.include_all == Some(true)
+            || group.include_all_proxies == Some(true)
+            || group.include_all_providers == Some(true);
+        let (kind, note) = match group.group_type {
+            ProxyGroupType::Select => ("select", "默认选择第一个成员，客户端可以手动切换"),
+            ProxyGroupType::UrlTest => ("url-test", "由客户端按延迟测试选择"),
+            ProxyGroupType::Fallback => ("fallback", "由客户端选择第一个可用的成员"),
+            ProxyGroupType::LoadBalance => ("load-balance", "由客户端按策略分配"),
+            ProxyGroupType::Relay => ("relay", "依次经过所有成员"),
+        };
+        let mut note = note.to_string();
+        if dynamic {
+            note.push_str("；另有use或include-all引入的节点未列出");
+        }
+        let next = match group.group_type {
+            ProxyGroupType::Select => members.first().cloned(),
+            _ => None,
+        };
+        steps.push(ChainStep {
+            name,
+            kind,
+            members,
+            note: Some(note),
+        });
+        match next {
+            Some(next) => name = next,
+            None => break,
+        }
+    }
+    steps
+}
+
+// 子规则中的规则没有原始文本，按 类型,内容,策略 格式还原
+fn rule_text(rule: &Rule) -> String {
+    let mut parts = vec![type_name(rule.rule_type)];
+    if rule.rule_type != RuleType::Match {
+        parts.push(rule.payload.clone());
+    }
+    parts.push(rule.target.clone());
+    parts.extend(rule.params.iter().flatten().cloned());
+    parts.join(",")
+}
+
+fn type_name(rule_type: RuleType) -> String {
+    serde_json::to_value(rule_type)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+// 域名等于后缀，或是后缀的子域名
+pub fn suffix_match(domain: &str, suffix: &str) -> bool {
+    domain == suffix
+        || domain
+            .strip_suffix(suffix)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+// domain规则集条目: +.example.com 匹配域名及其子域名，.example.com 只匹配子域名，* 匹配一级
+pub fn domain_entry_match(entry: &str, domain: &str) -> bool {
+    let entry = entry.trim().to_lowercase();
+    if let Some(suffix) = entry.strip_prefix("+.") {
+        return suffix_match(domain, suffix);
+    }
+    if entry.starts_with('.') {
+        return domain.len() > entry.len() && domain.ends_with(&entry);
+    }
+    if entry.contains('*') {
+        let labels: Vec<&str> = entry.split('.').collect();
+        let domain_labels: Vec<&str> = domain.split('.').collect();
+        return labels.len() == domain_labels.len()
+            && labels
+                .iter()
+                .zip(&domain_labels)
+                .all(|(label, domain_label)| *label == "*" || label == domain_label);
+    }
+    entry == domain
+}
+
+// 地址转为统一的整数表示: (是否为IPv6, 数值, 位数)
+fn ip_bits(ip: IpAddr) -> (bool, u128, u32) {
+    match ip {
+        IpAddr::V4(v4) => (false, u32::from(v4) as u128, 32),
+        IpAddr::V6(v6) => (true, u128::from(v6), 128),
+    }
+}
+
+pub fn parse_cidr(cidr: &str) -> Option<(IpAddr, u32)> {
+    let (address, prefix) = cidr.trim().split_once('/')?;
+    let address: IpAddr = address.parse().ok()?;
+    let prefix: u32 = prefix.parse().ok()?;
+    let (_, _, bits) = ip_bits(address);
+    (prefix <= bits).then_some((address, prefix))
+}
+
+// CIDR是否包含该地址，CIDR无效时返回None
+pub fn cidr_contains(cidr: &str, ip: IpAddr) -> Option<bool> {
+    let (network, prefix) = parse_cidr(cidr)?;
+    let (network_v6, network, bits) = ip_bits(network);
+    let (ip_v6, ip, _) = ip_bits(ip);
+    if network_v6 != ip_v6 {
+        return Some(false);
+    }
+    let host_bits = bits - prefix;
+    Some(host_bits == 128 || (network >> host_bits) == (ip >> host_bits))
+}
+
+// IP-SUFFIX: 地址的低位与规则中的地址相同，如 8.8.8.8/24 比较最后24位
+fn suffix_contains(suffix: &str, ip: IpAddr) -> Option<bool> {
+    let (address, length) = parse_cidr(suffix)?;
+    let (address_v6, address, _) = ip_bits(address);
+    let (ip_v6, ip, _) = ip_bits(ip);
+    if address_v6 != ip_v6 {
+        return Some(false);
+    }
+    let mask = match length {
+        0 => 0,
+        128 => u128::MAX,
+        length => (1u128 << length) - 1,
+    };
+    Some(address & mask == ip & mask)
+}
+
+// 端口规则: 单个端口、范围或用/分隔的多个，如 80/443/8000-9000
+fn port_match(payload: &str, port: u16) -> bool {
+    payload.split('/').any(|item| match item.split_once('-') {
+        Some((start, end)) => match (start.trim().parse::<u16>(), end.trim().parse::<u16>()) {
+            (Ok(start), Ok(end)) => (start..=end).contains(&port),
+            _ => false,
+        },
+        None => item.trim().parse::<u16>() == Ok(port),
+    })
+}
+
+// 局域网和保留地址
+fn is_lan(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || cidr_contains("100.64.0.0/10", ip) == Some(true)
+        }
+        IpAddr::V6(v6) => {
+            v6.is_loopback()
+                || v6.is_unspecified()
+                || cidr_contains("fc00::/7", ip) == Some(true)
+                || cidr_contains("fe80::/10", ip) == Some(true)
+        }
+    }
+}
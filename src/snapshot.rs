@@ -0,0 +1,266 @@
+use crate::models::ClashConfig;
+use crate::overlay::{self, Overlays};
+use crate::profile::Profiles;
+use crate::provider::{self, RuleSet, RuleSets};
+use crate::render::{Document, RenderedTemplate};
+use crate::ruleset;
+use crate::servers;
+use crate::upstream::{self, Upstreams};
+use crate::users::{self, UsersStore};
+use crate::validation::validate_merged;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+// 模板变体: (命名模板, 套餐名称, 覆盖配置列表)，命名模板为None时使用clash.yml
+type VariantKey = (Option<String>, Option<String>, Vec<String>);
+
+// 一个模板变体的预渲染结果
+struct Rendered {
+    config: RenderedTemplate,
+    proxies: RenderedTemplate,
+    // inline规则集，用作rule-providers
+    inline_rule_sets: RuleSets,
+}
+
+impl Rendered {
+    // RULE-SET引用的托管规则集在渲染前生成对应的rule-providers
+    fn render(config: &ClashConfig, hosted_rule_sets: &RuleSets) -> Result<Self, String> {
+        let mut config = config.clone();
+        ruleset::attach(&mut config, hosted_rule_sets)?;
+        Ok(Self {
+            config: RenderedTemplate::render(&config, Document::Config)?,
+            proxies: RenderedTemplate::render(&config, Document::Proxies)?,
+            inline_rule_sets: provider::inline_rule_sets(&config),
+        })
+    }
+
+    fn template(&self, document: Document) -> &RenderedTemplate {
+        match document {
+            Document::Config => &self.config,
+            Document::Proxies => &self.proxies,
+        }
+    }
+}
+
+// 配置快照: 一个配置版本的解析结果和预渲染输出
+// 发布后不再修改，读取方持有Arc即可在整个请求期间使用同一版本
+pub struct Snapshot {
+    // 配置版本号，启动时为1，每次替换配置加1
+    pub version: u64,
+    pub config: ClashConfig,
+    pub profiles: Profiles,
+    pub users: UsersStore,
+    pub overlays: Overlays,
+    // 上游订阅的节点，渲染时追加到clash.yml和命名模板的proxies末尾
+    pub upstreams: Upstreams,
+    // config/rulesets/下的托管规则集
+    pub hosted_rule_sets: RuleSets,
+    // clash.yml包含全部节点、不合并覆盖配置的模板
+    rendered: Rendered,
+    // 按命名模板、套餐和覆盖配置组合预渲染的模板，只包含用户实际用到或可以选择的组合
+    variants: HashMap<VariantKey, Rendered>,
+}
+
+// 用户使用指定命名模板时对应的模板变体
+fn variant_key(users: &UsersStore, uuid: &str, profile: Option<&str>) -> VariantKey {
+    (
+        profile.map(|profile| profile.to_string()),
+        users.plan_name(uuid).map(|plan| plan.to_string()),
+        users
+            .overlays_for(uuid)
+            .into_iter()
+            .map(|name| name.to_string())
+            .collect(),
+    )
+}
+
+impl Snapshot {
+    // 渲染基础模板和用户用到的每个命名模板、套餐、覆盖配置组合，版本号在发布时分配
+    pub fn build(
+        config: ClashConfig,
+        profiles: Profiles,
+        users: UsersStore,
+        overlays: Overlays,
+        upstreams: Upstreams,
+        hosted_rule_sets: RuleSets,
+    ) -> Result<Self, String> {
+        for (owner, name) in users.overlay_references() {
+            if !overlays.contains_key(name) {
+                return Err(format!("{}引用了不存在的覆盖配置: {}", owner, name));
+            }
+        }
+        for (owner, name) in users.profile_references() {
+            if !profiles.contains_key(name) {
+                return Err(format!("{}引用了不存在的命名模板: {}", owner, name));
+            }
+        }
+
+        // config和profiles保存原始配置，用于变更对比；渲染时使用追加了上游节点的配置
+        let base = upstream::append(&config, &upstreams);
+        let profile_bases: BTreeMap<&str, ClashConfig> = profiles
+            .iter()
+            .map(|(name, profile)| (name.as_str(), upstream::append(profile, &upstreams)))
+            .collect();
+        let rendered = Rendered::render(&base, &hosted_rule_sets)?;
+
+        // 空UUID不会匹配任何用户，得到未列出的用户使用的默认组合
+        // 每个用户除默认模板外，还可能通过profile参数选择允许列表中的模板
+        let mut keys: HashSet<VariantKey> = HashSet::new();
+        for uuid in std::iter::once("").chain(users.users.keys().map(|uuid| uuid.as_str())) {
+            keys.insert(variant_key(&users, uuid, users.profile_for(uuid)));
+            for profile in &users.profiles {
+                let profile = users.select_profile(uuid, Some(profile))?;
+                keys.insert(variant_key(&users, uuid, profile));
+            }
+        }
+
+        let mut variants = HashMap::new();
+        for key in keys {
+            if key.0.is_none() && key.1.is_none() && key.2.is_empty() {
+                continue;
+            }
+            let names: Vec<&str> = key.2.iter().map(|name| name.as_str()).collect();
+            let base = match key.0.as_deref() {
+                Some(profile) => &profile_bases[profile],
+                None => &base,
+            };
+            let variant = merged_config(base, &users, &overlays, key.1.as_deref(), &names)
+                .map_err(|e| match key.0.as_deref() {
+                    Some(profile) => format!("模板 {}: {}", profile, e),
+                    None => e,
+                })?;
+            let template = Rendered::render(&variant, &hosted_rule_sets).map_err(|e| {
+                format!(
+                    "{} 渲染失败: {}",
+                    describe_variant(key.0.as_deref(), key.1.as_deref(), &names),
+                    e
+                )
+            })?;
+            variants.insert(key, template);
+        }
+
+        Ok(Self {
+            version: 0,
+            config,
+            profiles,
+            users,
+            overlays,
+            upstreams,
+            hosted_rule_sets,
+            rendered,
+            variants,
+        })
+    }
+
+    fn rendered_for(&self, uuid: &str, profile: Option<&str>) -> &Rendered {
+        self.variants
+            .get(&variant_key(&self.users, uuid, profile))
+            .unwrap_or(&self.rendered)
+    }
+
+    // 获取用户使用指定命名模板时对应的模板，profile为None时使用clash.yml
+    pub fn template_for(
+        &self,
+        uuid: &str,
+        profile: Option<&str>,
+        document: Document,
+    ) -> &RenderedTemplate {
+        self.rendered_for(uuid, profile).template(document)
+    }
+
+    // 获取用户使用指定命名模板时可以引用的规则集
+    pub fn rule_set_for(&self, uuid: &str, profile: Option<&str>, name: &str) -> Option<&RuleSet> {
+        self.rendered_for(uuid, profile).inline_rule_sets.get(name)
+    }
+
+    // 合并覆盖配置并按套餐过滤后的配置，用于查看用户实际得到的配置
+    pub fn merged(
+        &self,
+        profile: Option<&str>,
+        plan: Option<&str>,
+        overlays: &[&str],
+    ) -> Result<ClashConfig, String> {
+        let base = match profile {
+            Some(profile) => self
+                .profiles
+                .get(profile)
+                .ok_or_else(|| format!("命名模板不存在: {}", profile))?,
+            None => &self.config,
+        };
+        let base = upstream::append(base, &self.upstreams);
+        let mut merged = merged_config(&base, &self.users, &self.overlays, plan, overlays)?;
+        ruleset::attach(&mut merged, &self.hosted_rule_sets)?;
+        Ok(merged)
+    }
+}
+
+// 合并覆盖配置后按套餐过滤，合并结果需要重新通过校验
+fn merged_config(
+    config: &ClashConfig,
+    users: &UsersStore,
+    overlays: &Overlays,
+    plan: Option<&str>,
+    names: &[&str],
+) -> Result<ClashConfig, String> {
+    let mut merged = if names.is_empty() {
+        config.clone()
+    } else {
+        let layers = names
+            .iter()
+            .map(|name| {
+                overlays
+                    .get(*name)
+                    .map(|overlay| (*name, overlay))
+                    .ok_or_else(|| format!("覆盖配置不存在: {}", name))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let mut merged = overlay::merge(config, &layers)?;
+        // 覆盖配置追加的节点同样可以引用服务端配置，基础配置中的节点已在加载时补全
+        servers::expand(&mut merged).map_err(|errors| {
+            let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            format!(
+                "{} 合并后补全节点失败: {}",
+                describe_variant(None, None, names),
+                errors.join("; ")
+            )
+        })?;
+        let yaml =
+            serde_yaml_ng::to_string(&merged).map_err(|e| format!("配置序列化失败: {}", e))?;
+        let report = validate_merged(&merged, &yaml);
+        if !report.valid {
+            return Err(format!(
+                "{} 合并后校验失败: {}",
+                describe_variant(None, None, names),
+                report.error_summary()
+            ));
+        }
+        merged
+    };
+
+    if let Some(plan) = plan {
+        let plan = users
+            .plans
+            .get(plan)
+            .ok_or_else(|| format!("套餐不存在: {}", plan))?;
+        merged = users::apply_plan(&merged, plan);
+    }
+    Ok(merged)
+}
+
+// 用于日志和错误信息的变体描述
+pub fn describe_variant(profile: Option<&str>, plan: Option<&str>, overlays: &[&str]) -> String {
+    let mut parts = Vec::new();
+    if let Some(profile) = profile {
+        parts.push(format!("模板 {}", profile));
+    }
+    if let Some(plan) = plan {
+        parts.push(format!("套餐 {}", plan));
+    }
+    if !overlays.is_empty() {
+        parts.push(format!("覆盖配置 {}", overlays.join(", ")));
+    }
+    if parts.is_empty() {
+        "基础配置".to_string()
+    } else {
+        parts.join("，")
+    }
+}
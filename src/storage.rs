@@ -0,0 +1,67 @@
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::AsyncWriteExt;
+
+// 临时文件序号，避免同一进程内并发写入时冲突
+static TEMP_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+// 计算内容的SHA-256哈希（十六进制），用作ETag
+pub fn content_hash(content: &str) -> String {
+    let digest = Sha256::digest(content.as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// 原子写入文件: 写临时文件 -> fsync -> rename -> fsync目录
+// 写入过程中崩溃只会留下临时文件，目标文件要么是旧内容要么是新内容
+pub async fn write_atomic(path: impl AsRef<Path>, content: &[u8]) -> std::io::Result<()> {
+    let path = path.as_ref();
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "无效的文件路径"))?;
+
+    let temp_path = dir.join(format!(
+        ".{}.tmp-{}-{}",
+        file_name,
+        std::process::id(),
+        TEMP_SEQUENCE.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let result = async {
+        let mut file = tokio::fs::File::create(&temp_path).await?;
+        file.write_all(content).await?;
+        file.sync_all().await?;
+        drop(file);
+
+        tokio::fs::rename(&temp_path, path).await?;
+
+        // 同步目录，确保rename本身已落盘
+        tokio::fs::File::open(dir).await?.sync_all().await
+    }
+    .await;
+
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+    }
+    result
+}
+
+// 解析If-Match头中的哈希值，去掉弱校验前缀和引号
+pub fn parse_etag(value: &str) -> &str {
+    value.trim().trim_start_matches("W/").trim_matches('"')
+}
+
+// 判断If-None-Match头是否匹配给定的ETag，支持逗号分隔的多个值和*
+// If-None-Match使用弱比较，忽略W/前缀
+pub fn if_none_match(header: &str, etag: &str) -> bool {
+    let etag = parse_etag(etag);
+    header
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || parse_etag(candidate) == etag)
+}
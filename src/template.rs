@@ -0,0 +1,133 @@
+use std::fmt;
+
+// 变量的开始和结束标记
+const OPEN: &str = "{{";
+const CLOSE: &str = "}}";
+
+// clash.yml中可以使用的模板变量
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Variable {
+    // 用户名称，users.yml中未设置时使用UUID
+    UserName,
+    UserUuid,
+    // 用户到期时间，users.yml中未设置时为空
+    UserExpire,
+    // 订阅请求的Host
+    RequestHost,
+    // 订阅请求的协议，http或https
+    RequestScheme,
+    // 环境变量，加载配置时读取
+    Env(String),
+}
+
+impl Variable {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "user.name" => Ok(Variable::UserName),
+            "user.uuid" => Ok(Variable::UserUuid),
+            "user.expire" => Ok(Variable::UserExpire),
+            "request.host" => Ok(Variable::RequestHost),
+            "request.scheme" => Ok(Variable::RequestScheme),
+            _ => match name.strip_prefix("env.") {
+                Some(key) if !key.is_empty() => Ok(Variable::Env(key.to_string())),
+                _ => Err(format!("未知的模板变量: {}{}{}", OPEN, name, CLOSE)),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Variable::UserName => "user.name",
+            Variable::UserUuid => "user.uuid",
+            Variable::UserExpire => "user.expire",
+            Variable::RequestHost => "request.host",
+            Variable::RequestScheme => "request.scheme",
+            Variable::Env(key) => return write!(f, "{}env.{}{}", OPEN, key, CLOSE),
+        };
+        write!(f, "{}{}{}", OPEN, name, CLOSE)
+    }
+}
+
+// 模板文本片段
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Part {
+    Literal(String),
+    Variable(Variable),
+}
+
+// 请求时填入的变量值
+#[derive(Default)]
+pub struct Values<'a> {
+    pub user_name: &'a str,
+    pub user_uuid: &'a str,
+    pub user_expire: &'a str,
+    pub request_host: &'a str,
+    pub request_scheme: &'a str,
+}
+
+// 判断字符串中是否包含模板变量
+pub fn has_variables(text: &str) -> bool {
+    text.contains(OPEN)
+}
+
+// 解析包含模板变量的文本
+// 环境变量在解析时直接替换为其值，不存在时返回错误
+pub fn parse(text: &str) -> Result<Vec<Part>, String> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find(OPEN) {
+        literal.push_str(&rest[..start]);
+        let after = &rest[start + OPEN.len()..];
+        let end = after
+            .find(CLOSE)
+            .ok_or_else(|| format!("模板变量缺少结束标记{}: {}", CLOSE, text))?;
+        let variable = Variable::parse(after[..end].trim())?;
+        rest = &after[end + CLOSE.len()..];
+
+        if let Variable::Env(key) = &variable {
+            let value = std::env::var(key)
+                .map_err(|_| format!("模板变量 {} 引用的环境变量不存在", variable))?;
+            literal.push_str(&value);
+            continue;
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(std::mem::take(&mut literal)));
+        }
+        parts.push(Part::Variable(variable));
+    }
+    literal.push_str(rest);
+    if !literal.is_empty() {
+        parts.push(Part::Literal(literal));
+    }
+
+    Ok(parts)
+}
+
+// 模板是否需要在请求时填入变量，只包含环境变量的模板在解析后即为固定文本
+pub fn is_dynamic(parts: &[Part]) -> bool {
+    parts.iter().any(|part| matches!(part, Part::Variable(_)))
+}
+
+// 拼接出最终文本
+pub fn expand(parts: &[Part], values: &Values) -> String {
+    let mut output = String::new();
+    for part in parts {
+        match part {
+            Part::Literal(text) => output.push_str(text),
+            Part::Variable(variable) => output.push_str(match variable {
+                Variable::UserName => values.user_name,
+                Variable::UserUuid => values.user_uuid,
+                Variable::UserExpire => values.user_expire,
+                Variable::RequestHost => values.request_host,
+                Variable::RequestScheme => values.request_scheme,
+                // 环境变量已在解析时替换
+                Variable::Env(_) => "",
+            }),
+        }
+    }
+    output
+}
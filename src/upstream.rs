@@ -0,0 +1,464 @@
+use crate::models::{AppState, ClashConfig, UpstreamConfig};
+use crate::reload::publish;
+use crate::share_link::{self, Proxy};
+use crate::snapshot::Snapshot;
+use crate::storage::write_atomic;
+use crate::users::TAGS_KEY;
+use ntex::http::client::{Client, Connector};
+use serde_yaml_ng::{Mapping, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, error, info, warn};
+
+// 上游订阅的缓存目录，每个上游保存最近一次成功拉取的节点
+pub const UPSTREAMS_DIR: &str = "config/upstreams";
+// 上游节点的标记字段，值为上游名称，渲染时原样输出这些节点并移除该字段
+pub const UPSTREAM_KEY: &str = "upstream";
+// 订阅响应的最大长度
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+// 各上游当前使用的节点（已添加前缀、标签和标记），按名称排序
+pub type Upstreams = BTreeMap<String, Vec<Proxy>>;
+
+// 检查app.yml中的上游订阅设置
+pub fn check(configs: &[UpstreamConfig]) -> Result<(), String> {
+    let mut names = HashSet::new();
+    for upstream in configs {
+        let name = upstream.name.as_str();
+        let valid_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_name {
+            return Err(format!("上游订阅名称只能包含字母、数字、-和_: {}", name));
+        }
+        if !names.insert(name) {
+            return Err(format!("上游订阅名称重复: {}", name));
+        }
+        if !upstream.url.starts_with("http://") && !upstream.url.starts_with("https://") {
+            return Err(format!(
+                "上游订阅 {} 的地址必须以http://或https://开头",
+                name
+            ));
+        }
+        if upstream.interval == 0 || upstream.timeout == 0 {
+            return Err(format!("上游订阅 {} 的interval和timeout必须大于0", name));
+        }
+    }
+    Ok(())
+}
+
+fn cache_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.yml", name))
+}
+
+// 读取上次成功拉取的缓存，缓存不存在或无法解析的上游暂时没有节点
+pub async fn load_cached(configs: &[UpstreamConfig]) -> Upstreams {
+    read_cache(Path::new(UPSTREAMS_DIR), configs).await
+}
+
+async fn read_cache(dir: &Path, configs: &[UpstreamConfig]) -> Upstreams {
+    let mut upstreams = Upstreams::new();
+    for config in configs {
+        let path = cache_path(dir, &config.name);
+        let content = match tokio::fs::read_to_string(&path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                warn!("无法读取上游订阅缓存 {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        match share_link::parse_subscription(&content) {
+            Ok(parsed) => {
+                upstreams.insert(config.name.clone(), apply(config, parsed.proxies));
+            }
+            Err(e) => warn!("上游订阅缓存 {} 解析失败: {}", path.display(), e),
+        }
+    }
+    upstreams
+}
+
+// 为上游节点添加名称前缀、标签和标记，上游自带的标签不参与套餐过滤
+fn apply(config: &UpstreamConfig, proxies: Vec<Proxy>) -> Vec<Proxy> {
+    let prefix = config.prefix();
+    proxies
+        .into_iter()
+        .map(|mut proxy| {
+            let name = proxy
+                .get("name")
+                .and_then(|name| name.as_str())
+                .unwrap_or_default();
+            let name = format!("{}{}", prefix, name);
+            proxy.insert("name".to_string(), Value::from(name));
+            proxy.remove(TAGS_KEY);
+            if !config.tags.is_empty() {
+                let tags = config.tags.iter().map(|tag| Value::from(tag.as_str()));
+                proxy.insert(TAGS_KEY.to_string(), Value::Sequence(tags.collect()));
+            }
+            proxy.insert(UPSTREAM_KEY.to_string(), Value::from(config.name.as_str()));
+            proxy
+        })
+        .collect()
+}
+
+// 将上游节点追加到配置的proxies末尾，与已有节点重名时在名称后加序号
+pub fn append(config: &ClashConfig, upstreams: &Upstreams) -> ClashConfig {
+    let mut config = config.clone();
+    if upstreams.values().all(|proxies| proxies.is_empty()) {
+        return config;
+    }
+
+    let proxies = config.proxies.get_or_insert_with(Vec::new);
+    let mut names: HashSet<String> = proxies
+        .iter()
+        .filter_map(|proxy| proxy.get("name").and_then(|name| name.as_str()))
+        .map(|name| name.to_string())
+        .collect();
+    for proxy in upstreams.values().flatten() {
+        let mut proxy = proxy.clone();
+        let name = proxy
+            .get("name")
+            .and_then(|name| name.as_str())
+            .unwrap_or_default();
+        let name = share_link::unique_name(&mut names, name);
+        proxy.insert("name".to_string(), Value::from(name));
+        proxies.push(proxy);
+    }
+    config
+}
+
+// 拉取订阅内容
+async fn fetch(config: &UpstreamConfig) -> Result<String, String> {
+    let timeout = Duration::from_secs(config.timeout);
+    let client = Client::build()
+        .connector(Connector::default().timeout(timeout).finish())
+        .timeout(timeout)
+        .response_payload_timeout(timeout.into())
+        .header("User-Agent", config.user_agent.as_str())
+        .finish();
+
+    let mut response = client
+        .get(&config.url)
+        .send()
+        .await
+        .map_err(|e| format!("请求失败: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("响应状态码 {}", response.status()));
+    }
+    let body = response
+        .body()
+        .limit(MAX_BODY_SIZE)
+        .await
+        .map_err(|e| format!("读取响应失败: {}", e))?;
+    String::from_utf8(body.to_vec()).map_err(|_| "响应内容不是UTF-8文本".to_string())
+}
+
+// 拉取一个上游并发布新的配置快照，返回节点数量，节点与当前一致时返回None
+async fn update(
+    state: &AppState,
+    dir: &Path,
+    config: &UpstreamConfig,
+) -> Result<Option<usize>, String> {
+    let content = fetch(config).await?;
+    let parsed = share_link::parse_subscription(&content)?;
+    for skipped in &parsed.skipped {
+        debug!("上游订阅 {} 跳过无法解析的节点: {}", config.name, skipped);
+    }
+    if parsed.proxies.is_empty() {
+        return Err("订阅中没有可用的节点".to_string());
+    }
+    if !parsed.skipped.is_empty() {
+        warn!(
+            "上游订阅 {} 有 {} 个节点无法解析，已跳过",
+            config.name,
+            parsed.skipped.len()
+        );
+    }
+
+    // 缓存保存解析后的节点，而不是原始响应
+    let mut cache = Mapping::new();
+    let raw = parsed
+        .proxies
+        .iter()
+        .map(|proxy| serde_yaml_ng::to_value(proxy).map_err(|e| format!("节点序列化失败: {}", e)));
+    cache.insert(
+        "proxies".into(),
+        Value::Sequence(raw.collect::<Result<_, _>>()?),
+    );
+    let cache = serde_yaml_ng::to_string(&cache).map_err(|e| format!("节点序列化失败: {}", e))?;
+
+    let count = parsed.proxies.len();
+    let proxies = apply(config, parsed.proxies);
+
+    // 与其他配置写入串行执行
+    let _write_guard = state.config_write_lock.lock().await;
+    let current = state.snapshot.load_full();
+    let snapshot = match current.upstreams.get(&config.name) == Some(&proxies) {
+        true => None,
+        false => {
+            let mut upstreams = current.upstreams.clone();
+            upstreams.insert(config.name.clone(), proxies);
+            Some(Snapshot::build(
+                current.config.clone(),
+                current.profiles.clone(),
+                current.users.clone(),
+                current.overlays.clone(),
+                upstreams,
+                current.hosted_rule_sets.clone(),
+            )?)
+        }
+    };
+
+    // 快照构建成功后才写入缓存，重启时不会读到无法使用的节点
+    // 内容未变化时也重新写入，缓存的修改时间即上次成功拉取的时间
+    tokio::fs::create_dir_all(dir)
+        .await
+        .map_err(|e| format!("无法创建{}: {}", dir.display(), e))?;
+    let path = cache_path(dir, &config.name);
+    write_atomic(&path, cache.as_bytes())
+        .await
+        .map_err(|e| format!("无法写入{}: {}", path.display(), e))?;
+
+    let Some(snapshot) = snapshot else {
+        return Ok(None);
+    };
+    let version = publish(state, snapshot);
+    debug!("上游订阅 {} 已发布配置版本 {}", config.name, version);
+    Ok(Some(count))
+}
+
+// 缓存距上次成功拉取的时间
+async fn cache_age(dir: &Path, name: &str) -> Option<Duration> {
+    let metadata = tokio::fs::metadata(cache_path(dir, name)).await.ok()?;
+    SystemTime::now()
+        .duration_since(metadata.modified().ok()?)
+        .ok()
+}
+
+// 为每个上游启动定时拉取任务
+// 缓存未过期时等到过期再拉取，重启服务不会立即请求所有上游
+pub fn spawn(state: AppState, configs: &[UpstreamConfig]) {
+    if configs.is_empty() {
+        return;
+    }
+
+    for config in configs.iter().cloned() {
+        let state = state.clone();
+        ntex::rt::spawn(async move {
+            let interval = Duration::from_secs(config.interval);
+            let dir = Path::new(UPSTREAMS_DIR);
+            let delay = match cache_age(dir, &config.name).await {
+                Some(age) => interval.saturating_sub(age),
+                None => Duration::ZERO,
+            };
+            tokio::time::sleep(delay).await;
+            loop {
+                match update(&state, dir, &config).await {
+                    Ok(Some(count)) => {
+                        info!("🔗 上游订阅 {} 已更新: {} 个节点", config.name, count)
+                    }
+                    Ok(None) => debug!("上游订阅 {} 内容未变化", config.name),
+                    Err(e) => error!(
+                        "上游订阅 {} 拉取失败，继续使用上次成功的结果: {}",
+                        config.name, e
+                    ),
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+
+    let names: Vec<&str> = configs.iter().map(|config| config.name.as_str()).collect();
+    info!("🔗 已启用上游订阅: {}", names.join(", "));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::AppConfig;
+    use arc_swap::ArcSwap;
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    const CLASH: &str = "\
+proxies:
+  - {name: hk, type: trojan, server: hk.example.com, port: 443, password: secret, tags: [vip]}
+  - {name: jp, type: ss, server: jp.example.com, port: 8388, cipher: aes-128-gcm, password: secret}
+";
+    const LINKS: &str = "\
+trojan://secret@us.example.com:443?sni=us.example.com#us
+not a link at all
+";
+
+    // 本地模拟的订阅服务，按路径返回不同的响应
+    fn serve() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                std::thread::spawn(move || {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match stream.read(&mut buf) {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => request.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&request);
+                    let path = request.split_whitespace().nth(1).unwrap_or_default();
+                    let (status, body) = match path {
+                        "/clash" => ("200 OK", CLASH.to_string()),
+                        "/links" => ("200 OK", STANDARD.encode(LINKS)),
+                        "/error" => ("500 Internal Server Error", "error".to_string()),
+                        "/garbage" => ("200 OK", "<html>维护中</html>".to_string()),
+                        "/slow" => {
+                            std::thread::sleep(Duration::from_secs(3));
+                            ("200 OK", CLASH.to_string())
+                        }
+                        _ => ("404 Not Found", String::new()),
+                    };
+                    let response = format!(
+                        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    );
+                    let _ = stream.write_all(response.as_bytes());
+                });
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    fn upstream(name: &str, url: String) -> UpstreamConfig {
+        UpstreamConfig {
+            name: name.to_string(),
+            url,
+            prefix: None,
+            tags: vec!["upstream".to_string()],
+            interval: 3600,
+            timeout: 1,
+            user_agent: "clash.meta".to_string(),
+        }
+    }
+
+    fn state() -> AppState {
+        let app_config: AppConfig = serde_yaml_ng::from_str(
+            "{addr: 127.0.0.1, port: 0, encryption_key: '', admin_password: ''}",
+        )
+        .unwrap();
+        let config = serde_yaml_ng::from_str("{}").unwrap();
+        let snapshot = Snapshot::build(
+            config,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        )
+        .unwrap();
+        AppState {
+            app_config: Arc::new(ArcSwap::from_pointee(app_config)),
+            snapshot: Arc::new(ArcSwap::from_pointee(snapshot)),
+            config_write_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("raygo-upstream-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn names(proxies: &[Proxy]) -> Vec<&str> {
+        proxies
+            .iter()
+            .filter_map(|proxy| proxy.get("name").and_then(|name| name.as_str()))
+            .collect()
+    }
+
+    #[ntex::test]
+    async fn update_renames_and_tags_proxies() {
+        let base = serve();
+        let state = state();
+        let dir = temp_dir("rename");
+
+        let clash = upstream("clash", format!("{}/clash", base));
+        assert_eq!(update(&state, &dir, &clash).await, Ok(Some(2)));
+        let mut links = upstream("links", format!("{}/links", base));
+        links.prefix = Some("L-".to_string());
+        links.tags.clear();
+        assert_eq!(update(&state, &dir, &links).await, Ok(Some(1)));
+
+        let snapshot = state.snapshot.load_full();
+        let clash_proxies = &snapshot.upstreams["clash"];
+        assert_eq!(names(clash_proxies), ["[clash] hk", "[clash] jp"]);
+        for proxy in clash_proxies {
+            // 上游自带的标签被替换为配置的标签
+            assert_eq!(proxy[TAGS_KEY], Value::from(vec!["upstream"]));
+            assert_eq!(proxy[UPSTREAM_KEY], Value::from("clash"));
+        }
+        let link_proxies = &snapshot.upstreams["links"];
+        assert_eq!(names(link_proxies), ["L-us"]);
+        assert!(!link_proxies[0].contains_key(TAGS_KEY));
+        assert_eq!(link_proxies[0]["server"], Value::from("us.example.com"));
+
+        // 相同内容再次拉取时不发布新版本
+        let version = snapshot.version;
+        assert_eq!(update(&state, &dir, &clash).await, Ok(None));
+        assert_eq!(state.snapshot.load().version, version);
+
+        // 重启后从缓存读取到相同的节点
+        let cached = read_cache(&dir, &[clash, links]).await;
+        assert_eq!(cached, snapshot.upstreams);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[ntex::test]
+    async fn update_times_out() {
+        let base = serve();
+        let state = state();
+        let dir = temp_dir("timeout");
+
+        let slow = upstream("slow", format!("{}/slow", base));
+        let started = std::time::Instant::now();
+        assert!(update(&state, &dir, &slow).await.is_err());
+        assert!(started.elapsed() < Duration::from_secs(3));
+        assert!(state.snapshot.load().upstreams.is_empty());
+        assert!(!cache_path(&dir, "slow").exists());
+    }
+
+    #[ntex::test]
+    async fn failed_update_keeps_cached_proxies() {
+        let base = serve();
+        let state = state();
+        let dir = temp_dir("failure");
+
+        let good = upstream("main", format!("{}/clash", base));
+        assert_eq!(update(&state, &dir, &good).await, Ok(Some(2)));
+        let cache = std::fs::read_to_string(cache_path(&dir, "main")).unwrap();
+        let snapshot = state.snapshot.load_full();
+
+        for path in ["/error", "/garbage"] {
+            let bad = upstream("main", format!("{}{}", base, path));
+            assert!(update(&state, &dir, &bad).await.is_err(), "{}", path);
+            assert_eq!(state.snapshot.load().version, snapshot.version);
+            assert_eq!(state.snapshot.load().upstreams, snapshot.upstreams);
+            let current = std::fs::read_to_string(cache_path(&dir, "main")).unwrap();
+            assert_eq!(current, cache);
+        }
+
+        let cached = read_cache(&dir, &[good]).await;
+        assert_eq!(cached, snapshot.upstreams);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,322 @@
+use crate::credentials::{Protocol, check_short_id};
+use crate::models::{ClashConfig, RuleType};
+use crate::profile::DEFAULT_PROFILE;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+// 用户列表文件路径
+pub const USERS_PATH: &str = "config/users.yml";
+
+// 代理节点上用于标记分组的自定义字段，输出前会被移除
+pub const TAGS_KEY: &str = "tags";
+
+// 套餐中表示全部节点的标签
+pub const ALL_TAGS: &str = "*";
+
+// 套餐: 决定用户可用的节点标签
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    // 可用的节点标签，包含"*"表示全部节点
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // 套餐使用的覆盖配置，按顺序合并
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overlays: Vec<String>,
+    // 套餐默认使用的命名模板，不设置时使用clash.yml
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+}
+
+impl Plan {
+    fn allows_all(&self) -> bool {
+        self.tags.iter().any(|tag| tag == ALL_TAGS)
+    }
+}
+
+// 用户信息，以UUID为键
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UserEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    // 套餐名称，不设置时使用default_plan
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plan: Option<String>,
+    // 在套餐的覆盖配置之后额外合并的覆盖配置
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overlays: Vec<String>,
+    // 默认使用的命名模板，优先于套餐的设置
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    // 到期时间，原样填入模板变量{{user.expire}}
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expire: Option<String>,
+    // 指定的节点密码，以协议名为键，未指定的协议由UUID生成
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub passwords: HashMap<Protocol, String>,
+    // 指定的reality short-id，不设置时由UUID生成
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub short_id: Option<String>,
+}
+
+// 用户列表 config/users.yml，文件不存在时所有用户均可使用全部节点
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsersStore {
+    #[serde(default)]
+    pub plans: HashMap<String, Plan>,
+    // 未在users中列出或未指定套餐的用户使用的套餐，不设置表示全部节点
+    #[serde(default)]
+    pub default_plan: Option<String>,
+    #[serde(default)]
+    pub users: HashMap<String, UserEntry>,
+    // 订阅链接可以通过profile参数选择的命名模板，default表示clash.yml
+    #[serde(default)]
+    pub profiles: Vec<String>,
+}
+
+impl UsersStore {
+    // 解析并校验用户列表
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let store: UsersStore = serde_yaml_ng::from_str(content)
+            .map_err(|e| format!("{}解析失败: {}", USERS_PATH, e))?;
+        store.check()?;
+        Ok(store)
+    }
+
+    // 从磁盘读取用户列表，文件不存在时返回空列表
+    pub async fn load() -> Result<Self, String> {
+        match tokio::fs::read_to_string(USERS_PATH).await {
+            Ok(content) if content.trim().is_empty() => Ok(Self::default()),
+            Ok(content) => Self::parse(&content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("无法读取{}: {}", USERS_PATH, e)),
+        }
+    }
+
+    // 检查套餐引用和指定的short-id
+    fn check(&self) -> Result<(), String> {
+        if let Some(plan) = &self.default_plan
+            && !self.plans.contains_key(plan)
+        {
+            return Err(format!("default_plan引用了不存在的套餐: {}", plan));
+        }
+        for (uuid, user) in &self.users {
+            if let Some(plan) = &user.plan
+                && !self.plans.contains_key(plan)
+            {
+                return Err(format!("用户 {} 引用了不存在的套餐: {}", uuid, plan));
+            }
+            if let Some(short_id) = &user.short_id {
+                check_short_id(short_id).map_err(|e| format!("用户 {} 的{}", uuid, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    // 查找用户信息，UUID不区分大小写
+    pub fn user(&self, uuid: &str) -> Option<&UserEntry> {
+        self.users.get(uuid).or_else(|| {
+            self.users
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(uuid))
+                .map(|(_, user)| user)
+        })
+    }
+
+    // 用户使用的套餐名称，None表示全部节点
+    pub fn plan_name(&self, uuid: &str) -> Option<&str> {
+        self.user(uuid)
+            .and_then(|user| user.plan.as_deref())
+            .or(self.default_plan.as_deref())
+    }
+
+    // 用户使用的覆盖配置: 先套餐的，再用户自己的，重复的只保留第一次
+    pub fn overlays_for(&self, uuid: &str) -> Vec<&str> {
+        let plan = self.plan_name(uuid).and_then(|name| self.plans.get(name));
+        let mut overlays: Vec<&str> = Vec::new();
+        let names = plan
+            .into_iter()
+            .flat_map(|plan| &plan.overlays)
+            .chain(self.user(uuid).into_iter().flat_map(|user| &user.overlays));
+        for name in names {
+            if !overlays.contains(&name.as_str()) {
+                overlays.push(name);
+            }
+        }
+        overlays
+    }
+
+    // 用户默认使用的命名模板: 先用户的，再套餐的，None表示clash.yml
+    pub fn profile_for(&self, uuid: &str) -> Option<&str> {
+        let plan = self.plan_name(uuid).and_then(|name| self.plans.get(name));
+        self.user(uuid)
+            .and_then(|user| user.profile.as_deref())
+            .or(plan.and_then(|plan| plan.profile.as_deref()))
+            .filter(|profile| *profile != DEFAULT_PROFILE)
+    }
+
+    // 选择订阅使用的命名模板，请求指定的模板必须在profiles允许列表中或是用户的默认模板
+    pub fn select_profile<'a>(
+        &'a self,
+        uuid: &str,
+        requested: Option<&'a str>,
+    ) -> Result<Option<&'a str>, String> {
+        let default = self.profile_for(uuid);
+        match requested.filter(|profile| !profile.is_empty()) {
+            None => Ok(default),
+            Some(profile)
+                if default.unwrap_or(DEFAULT_PROFILE) == profile
+                    || self.profiles.iter().any(|allowed| allowed == profile) =>
+            {
+                Ok(Some(profile).filter(|profile| *profile != DEFAULT_PROFILE))
+            }
+            Some(profile) => Err(format!("模板 {} 不在profiles允许列表中", profile)),
+        }
+    }
+
+    // 所有引用到的命名模板名称及引用方，用于检查模板是否存在
+    pub fn profile_references(&self) -> Vec<(String, &str)> {
+        let plans = self.plans.iter().filter_map(|(name, plan)| {
+            let profile = plan.profile.as_deref()?;
+            Some((format!("套餐 {}", name), profile))
+        });
+        let users = self.users.iter().filter_map(|(uuid, user)| {
+            let profile = user.profile.as_deref()?;
+            Some((format!("用户 {}", uuid), profile))
+        });
+        let allowed = self
+            .profiles
+            .iter()
+            .map(|profile| ("profiles".to_string(), profile.as_str()));
+        plans
+            .chain(users)
+            .chain(allowed)
+            .filter(|(_, profile)| *profile != DEFAULT_PROFILE)
+            .collect()
+    }
+
+    // 所有引用到的覆盖配置名称及引用方，用于检查引用是否存在
+    pub fn overlay_references(&self) -> Vec<(String, &str)> {
+        let plans = self.plans.iter().flat_map(|(name, plan)| {
+            plan.overlays
+                .iter()
+                .map(move |overlay| (format!("套餐 {}", name), overlay.as_str()))
+        });
+        let users = self.users.iter().flat_map(|(uuid, user)| {
+            user.overlays
+                .iter()
+                .map(move |overlay| (format!("用户 {}", uuid), overlay.as_str()))
+        });
+        plans.chain(users).collect()
+    }
+}
+
+// 读取代理节点的标签
+fn proxy_tags(proxy: &HashMap<String, serde_yaml_ng::Value>) -> Vec<&str> {
+    match proxy.get(TAGS_KEY) {
+        Some(serde_yaml_ng::Value::Sequence(tags)) => {
+            tags.iter().filter_map(|tag| tag.as_str()).collect()
+        }
+        Some(serde_yaml_ng::Value::String(tag)) => vec![tag.as_str()],
+        _ => Vec::new(),
+    }
+}
+
+// 移除所有代理节点上的标签字段
+pub fn strip_tags(config: &mut ClashConfig) {
+    for proxy in config.proxies.iter_mut().flatten() {
+        proxy.remove(TAGS_KEY);
+    }
+}
+
+// 按套餐过滤配置: 移除不可用的节点，从代理组中剔除这些节点，
+// 逐级删除因此变空的代理组，并删除指向已删除节点或代理组的规则和子规则，
+// MATCH指向已删除的代理组时改为第一个保留的代理组或节点，不能让未命中的连接变成直连
+pub fn apply_plan(config: &ClashConfig, plan: &Plan) -> ClashConfig {
+    let mut config = config.clone();
+    if plan.allows_all() {
+        return config;
+    }
+
+    let allowed: HashSet<&str> = plan.tags.iter().map(|tag| tag.as_str()).collect();
+    let mut removed: HashSet<String> = HashSet::new();
+
+    if let Some(proxies) = config.proxies.as_mut() {
+        proxies.retain(|proxy| {
+            let keep = proxy_tags(proxy).iter().any(|tag| allowed.contains(tag));
+            if !keep && let Some(name) = proxy.get("name").and_then(|v| v.as_str()) {
+                removed.insert(name.to_string());
+            }
+            keep
+        });
+    }
+
+    // 删除代理组会让引用它的代理组也可能变空，循环直到没有新的删除
+    // 原本就没有节点的代理组不属于过滤造成的结果，保持原样
+    if let Some(groups) = config.proxy_groups.as_mut() {
+        let originally_empty: HashSet<String> = groups
+            .iter()
+            .filter(|group| group.has_no_members())
+            .map(|group| group.name.clone())
+            .collect();
+        loop {
+            for group in groups.iter_mut() {
+                if let Some(members) = group.proxies.as_mut() {
+                    members.retain(|member| !removed.contains(member));
+                }
+            }
+            let before = groups.len();
+            groups.retain(|group| {
+                let empty = group.has_no_members() && !originally_empty.contains(&group.name);
+                if empty {
+                    removed.insert(group.name.clone());
+                }
+                !empty
+            });
+            if groups.len() == before {
+                break;
+            }
+        }
+    }
+
+    let fallback = config
+        .proxy_groups
+        .iter()
+        .flatten()
+        .map(|group| group.name.clone())
+        .chain(
+            config
+                .proxies
+                .iter()
+                .flatten()
+                .filter_map(|proxy| proxy.get("name").and_then(|v| v.as_str()))
+                .map(str::to_string),
+        )
+        .next()
+        .unwrap_or_else(|| "DIRECT".to_string());
+
+    if let Some(rules) = config.rules.as_mut() {
+        rules.retain_mut(|line| match crate::rules::parse_rule(line) {
+            Ok(rule) if !removed.contains(&rule.target) => true,
+            Ok(rule) if rule.rule_type == RuleType::Match => {
+                *line = format!("MATCH,{}", fallback);
+                true
+            }
+            Ok(_) => false,
+            Err(_) => true,
+        });
+    }
+
+    for sub_rule in config.sub_rules.iter_mut().flat_map(|s| s.values_mut()) {
+        sub_rule.rules.retain_mut(|rule| {
+            if !removed.contains(&rule.target) {
+                return true;
+            }
+            if rule.rule_type == RuleType::Match {
+                rule.target = fallback.clone();
+            }
+            rule.rule_type == RuleType::Match
+        });
+    }
+
+    config
+}
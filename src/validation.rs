@@ -0,0 +1,888 @@
+use crate::geo::{DomainKind, GeoFiles, SiteDomain};
+use crate::include;
+use crate::models::{
+    ClashConfig, ProxyProviderType, Rule, RuleBehavior, RuleProviderType, RuleType,
+};
+use crate::provider::RuleSet;
+use crate::rules::{self, RuleError};
+use crate::ruleset::{self, EntryError};
+use crate::servers;
+use crate::simulate::{cidr_contains, parse_cidr, suffix_match};
+use crate::template;
+use crate::upstream::UPSTREAM_KEY;
+use crate::users::TAGS_KEY;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+// 诊断严重级别
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// 单条诊断信息，行列号从1开始
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+// 校验报告
+#[derive(Debug, Serialize)]
+pub struct ValidationReport {
+    pub valid: bool,
+    pub errors: usize,
+    pub warnings: usize,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ValidationReport {
+    fn new(diagnostics: Vec<Diagnostic>) -> Self {
+        let errors = diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .count();
+        Self {
+            valid: errors == 0,
+            errors,
+            warnings: diagnostics.len() - errors,
+            diagnostics,
+        }
+    }
+
+    // 拼接所有错误信息，用于日志和错误页面
+    pub fn error_summary(&self) -> String {
+        self.messages(Severity::Error).join("; ")
+    }
+
+    // 带行号的诊断信息，用于日志
+    pub fn messages(&self, severity: Severity) -> Vec<String> {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == severity)
+            .map(|d| match d.line {
+                Some(line) => format!("第{}行: {}", line, d.message),
+                None => d.message.clone(),
+            })
+            .collect()
+    }
+}
+
+// 在原始文本中查找内容所在的位置，用于给语义错误标注行列号
+struct SourceIndex<'a> {
+    lines: Vec<&'a str>,
+}
+
+impl<'a> SourceIndex<'a> {
+    fn new(content: &'a str) -> Self {
+        Self {
+            lines: content.lines().collect(),
+        }
+    }
+
+    // 从指定行（从1开始）之后查找第一个包含needle的非注释行
+    fn find_from(&self, needle: &str, from_line: usize) -> Option<(usize, usize)> {
+        self.lines
+            .iter()
+            .enumerate()
+            .skip(from_line.saturating_sub(1))
+            .filter(|(_, line)| !line.trim_start().starts_with('#'))
+            .find_map(|(index, line)| {
+                line.find(needle)
+                    .map(|byte| (index + 1, line[..byte].chars().count() + 1))
+            })
+    }
+
+    fn find(&self, needle: &str) -> Option<(usize, usize)> {
+        self.find_from(needle, 1)
+    }
+
+    // 查找顶层键所在的行，避免匹配到 respect-rules: 这样包含相同文字的键
+    fn find_key(&self, key: &str) -> Option<(usize, usize)> {
+        self.lines
+            .iter()
+            .position(|line| {
+                line.strip_prefix(key)
+                    .is_some_and(|rest| rest.trim_start().starts_with(':'))
+            })
+            .map(|index| (index + 1, 1))
+    }
+}
+
+struct Collector<'a> {
+    source: SourceIndex<'a>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Collector<'_> {
+    fn push(&mut self, severity: Severity, position: Option<(usize, usize)>, message: String) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            line: position.map(|(line, _)| line),
+            column: position.map(|(_, column)| column),
+            message,
+        });
+    }
+
+    fn error(&mut self, position: Option<(usize, usize)>, message: String) {
+        self.push(Severity::Error, position, message);
+    }
+
+    fn warning(&mut self, position: Option<(usize, usize)>, message: String) {
+        self.push(Severity::Warning, position, message);
+    }
+}
+
+// 解析并校验配置内容，解析成功时同时返回配置
+pub fn validate_content(content: &str) -> (Option<ClashConfig>, ValidationReport) {
+    validate_files(content, None)
+}
+
+// 解析并校验主配置及其引用的文件
+// edited 为编辑器中正在修改的被引用文件（文件名，内容），代替磁盘上的内容参与校验，
+// 此时错误位置标注在该文件中
+pub fn validate_files(
+    content: &str,
+    edited: Option<(&str, &str)>,
+) -> (Option<ClashConfig>, ValidationReport) {
+    let overrides: Vec<(&str, &str)> = edited.into_iter().collect();
+    validate_with(content, edited, &overrides, Lint::Full)
+}
+
+// 编辑器实时校验，参数与validate_files相同
+// 跳过需要读取geosite.dat的规则覆盖检查，避免每次输入都读取和解析整个文件，保存和重载时仍会检查
+pub fn validate_live(
+    content: &str,
+    edited: Option<(&str, &str)>,
+) -> (Option<ClashConfig>, ValidationReport) {
+    let overrides: Vec<(&str, &str)> = edited.into_iter().collect();
+    validate_with(content, edited, &overrides, Lint::SkipGeosite)
+}
+
+// 校验历史版本: 引用的文件使用版本中保存的内容，不读取磁盘上的当前文件
+pub fn validate_version(
+    content: &str,
+    files: &[(String, String)],
+) -> (Option<ClashConfig>, ValidationReport) {
+    let overrides: Vec<(&str, &str)> = files
+        .iter()
+        .map(|(name, content)| (name.as_str(), content.as_str()))
+        .collect();
+    validate_with(content, None, &overrides, Lint::Full)
+}
+
+// 规则列表的警告检查范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lint {
+    Full,
+    // 跳过需要读取geosite.dat的检查
+    SkipGeosite,
+    // 不检查，只报告错误
+    Off,
+}
+
+fn validate_with(
+    content: &str,
+    edited: Option<(&str, &str)>,
+    overrides: &[(&str, &str)],
+    lint: Lint,
+) -> (Option<ClashConfig>, ValidationReport) {
+    let source = edited.map_or(content, |(_, text)| text);
+    let value = match serde_yaml_ng::from_str::<serde_yaml_ng::Value>(source) {
+        Ok(value) => value,
+        Err(e) => return (None, yaml_error(e)),
+    };
+
+    // 未使用引用时直接解析原文，类型错误也能标注位置
+    let parsed = if edited.is_none() && !include::has_includes(&value) {
+        serde_yaml_ng::from_str::<ClashConfig>(content).map_err(yaml_error)
+    } else {
+        resolve_includes(content, edited, overrides).map_err(|message| {
+            ValidationReport::new(vec![Diagnostic {
+                severity: Severity::Error,
+                line: None,
+                column: None,
+                message,
+            }])
+        })
+    };
+    let mut config = match parsed {
+        Ok(config) => config,
+        Err(report) => return (None, report),
+    };
+
+    // 补全引用了服务端配置的节点，失败时标注在节点名称处
+    if let Err(errors) = servers::expand(&mut config) {
+        let mut collector = Collector {
+            source: SourceIndex::new(source),
+            diagnostics: Vec::new(),
+        };
+        for error in errors {
+            let position = match error.proxy.is_empty() {
+                true => None,
+                false => collector.source.find(&error.proxy),
+            };
+            collector.error(position, error.to_string());
+        }
+        return (None, ValidationReport::new(collector.diagnostics));
+    }
+    let diagnostics = check_with(&config, source, lint);
+    (Some(config), ValidationReport::new(diagnostics))
+}
+
+// 解析并校验托管规则集文件，错误位置标注在出错的条目处
+pub fn validate_rule_set(content: &str) -> (Option<RuleSet>, ValidationReport) {
+    if let Err(e) = serde_yaml_ng::from_str::<serde_yaml_ng::Value>(content) {
+        return (None, yaml_error(e));
+    }
+    let mut collector = Collector {
+        source: SourceIndex::new(content),
+        diagnostics: Vec::new(),
+    };
+    let position = |collector: &Collector, error: &EntryError| {
+        error
+            .entry
+            .as_deref()
+            .and_then(|entry| collector.source.find(entry))
+    };
+    match ruleset::parse(content, &mut GeoFiles::default()) {
+        Ok(parsed) => {
+            for warning in &parsed.warnings {
+                collector.warning(position(&collector, warning), warning.to_string());
+            }
+            (
+                Some(parsed.rule_set),
+                ValidationReport::new(collector.diagnostics),
+            )
+        }
+        Err(errors) => {
+            for error in &errors {
+                collector.error(position(&collector, error), error.to_string());
+            }
+            (None, ValidationReport::new(collector.diagnostics))
+        }
+    }
+}
+
+// 展开引用后解析配置，错误信息不带位置
+fn resolve_includes(
+    content: &str,
+    edited: Option<(&str, &str)>,
+    overrides: &[(&str, &str)],
+) -> Result<ClashConfig, String> {
+    let main = serde_yaml_ng::from_str(content)
+        .map_err(|e| format!("{}: YAML解析失败: {}", include::MAIN_FILE, e))?;
+    let resolved = include::resolve(include::MAIN_FILE, main, overrides)?;
+    if let Some((file, _)) = edited
+        && !resolved.files.iter().any(|name| name == file)
+    {
+        return Err(format!("{}未被{}引用", file, include::MAIN_FILE));
+    }
+    serde_yaml_ng::from_value(resolved.value).map_err(|e| format!("配置格式错误: {}", e))
+}
+
+fn yaml_error(e: serde_yaml_ng::Error) -> ValidationReport {
+    let location = e.location();
+    ValidationReport::new(vec![Diagnostic {
+        severity: Severity::Error,
+        line: location.as_ref().map(|l| l.line()),
+        column: location.as_ref().map(|l| l.column()),
+        message: format!("YAML解析失败: {}", e),
+    }])
+}
+
+// 校验已解析的配置，content为其原文，用于标注错误位置
+pub fn validate_config(config: &ClashConfig, content: &str) -> ValidationReport {
+    ValidationReport::new(check_config(config, content))
+}
+
+// 校验合并覆盖配置后的结果，content为其序列化结果
+// 每次构建快照时每个变体都会校验，不检查规则列表的警告，这些警告在重载基础配置时已经给出
+pub fn validate_merged(config: &ClashConfig, content: &str) -> ValidationReport {
+    ValidationReport::new(check_with(config, content, Lint::Off))
+}
+
+// 对已解析的配置进行语义校验
+pub fn check_config(config: &ClashConfig, content: &str) -> Vec<Diagnostic> {
+    check_with(config, content, Lint::Full)
+}
+
+fn check_with(config: &ClashConfig, content: &str, lint: Lint) -> Vec<Diagnostic> {
+    let mut collector = Collector {
+        source: SourceIndex::new(content),
+        diagnostics: Vec::new(),
+    };
+
+    let proxy_names = check_proxies(config, &mut collector);
+    let group_names = check_proxy_groups(config, &proxy_names, &mut collector);
+    check_providers(config, &mut collector);
+    check_rules(config, &proxy_names, &group_names, &mut collector);
+    if lint != Lint::Off {
+        lint_rules(config, lint == Lint::Full, &mut collector);
+    }
+    check_template_variables(config, &mut collector);
+
+    collector.diagnostics
+}
+
+// 校验模板变量: 未知变量和不存在的环境变量在加载时报错，而不是等到请求时
+fn check_template_variables(config: &ClashConfig, collector: &mut Collector) {
+    let Ok(value) = serde_yaml_ng::to_value(config) else {
+        return;
+    };
+    let mut texts = Vec::new();
+    collect_template_texts(&value, &mut texts);
+
+    let mut reported = HashSet::new();
+    for text in texts {
+        if let Err(e) = template::parse(text)
+            && reported.insert(e.clone())
+        {
+            let position = collector.source.find(text);
+            collector.error(position, e);
+        }
+    }
+}
+
+// 收集所有包含模板变量的字符串（包括映射的键）
+fn collect_template_texts<'a>(value: &'a serde_yaml_ng::Value, texts: &mut Vec<&'a str>) {
+    match value {
+        serde_yaml_ng::Value::String(text) if template::has_variables(text) => texts.push(text),
+        serde_yaml_ng::Value::Sequence(items) => {
+            for item in items {
+                collect_template_texts(item, texts);
+            }
+        }
+        // 上游订阅的节点不替换模板变量
+        serde_yaml_ng::Value::Mapping(mapping) if mapping.contains_key(UPSTREAM_KEY) => {}
+        serde_yaml_ng::Value::Mapping(mapping) => {
+            for (key, item) in mapping {
+                collect_template_texts(key, texts);
+                collect_template_texts(item, texts);
+            }
+        }
+        serde_yaml_ng::Value::Tagged(tagged) => collect_template_texts(&tagged.value, texts),
+        _ => {}
+    }
+}
+
+// 校验代理节点，返回所有节点名称
+fn check_proxies(config: &ClashConfig, collector: &mut Collector) -> HashSet<String> {
+    let mut names = HashSet::new();
+    let Some(proxies) = &config.proxies else {
+        return names;
+    };
+
+    for (index, proxy) in proxies.iter().enumerate() {
+        let name = match proxy.get("name").and_then(|v| v.as_str()) {
+            Some(name) => name.to_string(),
+            None => {
+                let position = collector.source.find("proxies:");
+                collector.error(position, format!("第{}个代理节点缺少name字段", index + 1));
+                continue;
+            }
+        };
+        let position = collector.source.find(&name);
+
+        if !names.insert(name.clone()) {
+            collector.error(position, format!("代理节点名称重复: {}", name));
+        }
+
+        let proxy_type = proxy.get("type").and_then(|v| v.as_str());
+        match proxy_type {
+            None => collector.error(position, format!("代理节点 {} 缺少type字段", name)),
+            Some("direct") | Some("dns") => {}
+            Some(_) => {
+                for key in ["server", "port"] {
+                    if !proxy.contains_key(key) {
+                        collector.error(position, format!("代理节点 {} 缺少{}字段", name, key));
+                    }
+                }
+            }
+        }
+
+        // tags只用于套餐过滤，格式错误时该节点不会出现在任何按标签过滤的套餐中
+        let tags_valid = match proxy.get(TAGS_KEY) {
+            None | Some(serde_yaml_ng::Value::String(_)) => true,
+            Some(serde_yaml_ng::Value::Sequence(tags)) => tags.iter().all(|tag| tag.is_string()),
+            Some(_) => false,
+        };
+        if !tags_valid {
+            collector.warning(
+                position,
+                format!("代理节点 {} 的{}字段应为字符串列表", name, TAGS_KEY),
+            );
+        }
+    }
+
+    names
+}
+
+// 校验代理组引用关系，返回所有代理组名称
+fn check_proxy_groups(
+    config: &ClashConfig,
+    proxy_names: &HashSet<String>,
+    collector: &mut Collector,
+) -> HashSet<String> {
+    let mut names = HashSet::new();
+    let Some(groups) = &config.proxy_groups else {
+        return names;
+    };
+
+    for group in groups {
+        let position = collector.source.find(&format!("name: {}", group.name));
+        if !names.insert(group.name.clone()) {
+            collector.error(position, format!("代理组名称重复: {}", group.name));
+        }
+        if proxy_names.contains(&group.name) {
+            collector.error(
+                position,
+                format!("代理组名称与代理节点名称冲突: {}", group.name),
+            );
+        }
+    }
+
+    let providers: HashSet<&String> = config
+        .proxy_providers
+        .as_ref()
+        .map(|p| p.keys().collect())
+        .unwrap_or_default();
+
+    for group in groups {
+        let group_line = collector
+            .source
+            .find(&format!("name: {}", group.name))
+            .map(|(line, _)| line)
+            .unwrap_or(1);
+
+        for member in group.proxies.iter().flatten() {
+            if !proxy_names.contains(member)
+                && !names.contains(member)
+                && !rules::is_builtin_target(member)
+            {
+                let position = collector.source.find_from(member, group_line + 1);
+                collector.error(
+                    position,
+                    format!(
+                        "代理组 {} 引用了不存在的节点或代理组: {}",
+                        group.name, member
+                    ),
+                );
+            }
+        }
+
+        for provider in group.use_providers.iter().flatten() {
+            if !providers.contains(provider) {
+                let position = collector.source.find_from(provider, group_line + 1);
+                collector.error(
+                    position,
+                    format!(
+                        "代理组 {} 引用了不存在的proxy-provider: {}",
+                        group.name, provider
+                    ),
+                );
+            }
+        }
+
+        if group.has_no_members() {
+            collector.warning(
+                Some((group_line, 1)),
+                format!("代理组 {} 没有任何可用节点", group.name),
+            );
+        }
+    }
+
+    check_group_cycles(config, &names, collector);
+
+    names
+}
+
+// 检查代理组之间的循环引用
+fn check_group_cycles(
+    config: &ClashConfig,
+    group_names: &HashSet<String>,
+    collector: &mut Collector,
+) {
+    let Some(groups) = &config.proxy_groups else {
+        return;
+    };
+
+    let edges: HashMap<&str, Vec<&str>> = groups
+        .iter()
+        .map(|group| {
+            let members = group
+                .proxies
+                .iter()
+                .flatten()
+                .filter(|m| group_names.contains(*m))
+                .map(|m| m.as_str())
+                .collect();
+            (group.name.as_str(), members)
+        })
+        .collect();
+
+    // 0: 未访问, 1: 访问中, 2: 已完成
+    let mut state: HashMap<&str, u8> = HashMap::new();
+    let mut reported = HashSet::new();
+
+    fn visit<'a>(
+        node: &'a str,
+        edges: &HashMap<&'a str, Vec<&'a str>>,
+        state: &mut HashMap<&'a str, u8>,
+        cycles: &mut Vec<&'a str>,
+    ) {
+        state.insert(node, 1);
+        for next in edges.get(node).into_iter().flatten() {
+            match state.get(next).copied().unwrap_or(0) {
+                0 => visit(next, edges, state, cycles),
+                1 => cycles.push(next),
+                _ => {}
+            }
+        }
+        state.insert(node, 2);
+    }
+
+    for group in groups {
+        let mut cycles = Vec::new();
+        if state.get(group.name.as_str()).copied().unwrap_or(0) == 0 {
+            visit(&group.name, &edges, &mut state, &mut cycles);
+        }
+        for name in cycles {
+            if reported.insert(name) {
+                let position = collector.source.find(&format!("name: {}", name));
+                collector.error(position, format!("代理组存在循环引用: {}", name));
+            }
+        }
+    }
+}
+
+// 校验proxy-providers和rule-providers的必填字段
+fn check_providers(config: &ClashConfig, collector: &mut Collector) {
+    for (name, provider) in config.proxy_providers.iter().flatten() {
+        let missing = match provider.provider_type {
+            ProxyProviderType::Http if provider.url.is_none() => Some("url"),
+            ProxyProviderType::File if provider.path.is_none() => Some("path"),
+            ProxyProviderType::Inline if provider.proxies.is_none() => Some("proxies"),
+            _ => None,
+        };
+        if let Some(field) = missing {
+            let position = collector.source.find(&format!("{}:", name));
+            collector.error(
+                position,
+                format!("proxy-provider {} 缺少{}字段", name, field),
+            );
+        }
+    }
+
+    for (name, provider) in config.rule_providers.iter().flatten() {
+        let missing = match provider.provider_type {
+            RuleProviderType::Http if provider.url.is_none() => Some("url"),
+            RuleProviderType::File if provider.path.is_none() => Some("path"),
+            RuleProviderType::Inline if provider.payload.is_none() => Some("payload"),
+            _ => None,
+        };
+        if let Some(field) = missing {
+            let position = collector.source.find(&format!("{}:", name));
+            collector.error(
+                position,
+                format!("rule-provider {} 缺少{}字段", name, field),
+            );
+        }
+    }
+}
+
+// 校验规则格式与引用的策略、规则集、子规则
+fn check_rules(
+    config: &ClashConfig,
+    proxy_names: &HashSet<String>,
+    group_names: &HashSet<String>,
+    collector: &mut Collector,
+) {
+    let Some(rule_lines) = &config.rules else {
+        return;
+    };
+
+    let rules_line = collector
+        .source
+        .find_key("rules")
+        .map(|(line, _)| line)
+        .unwrap_or(1);
+
+    for line in rule_lines {
+        let position = collector.source.find_from(line, rules_line);
+        let rule = match rules::parse_rule(line) {
+            Ok(rule) => rule,
+            Err(e @ RuleError::UnknownType(_)) => {
+                collector.warning(position, e.to_string());
+                continue;
+            }
+            Err(e) => {
+                collector.error(position, e.to_string());
+                continue;
+            }
+        };
+
+        match rule.rule_type {
+            RuleType::SubRule => {
+                let exists = config
+                    .sub_rules
+                    .as_ref()
+                    .is_some_and(|s| s.contains_key(&rule.target));
+                if !exists {
+                    collector.error(position, format!("引用了不存在的子规则: {}", rule.target));
+                }
+                continue;
+            }
+            RuleType::RuleSet => {
+                // config/rulesets/下的托管规则集在渲染时自动生成rule-provider
+                let exists = config
+                    .rule_providers
+                    .as_ref()
+                    .is_some_and(|p| p.contains_key(&rule.payload))
+                    || ruleset::exists(&rule.payload);
+                if !exists {
+                    collector.error(
+                        position,
+                        format!("引用了不存在的rule-provider或规则集: {}", rule.payload),
+                    );
+                }
+            }
+            _ => {}
+        }
+
+        if !proxy_names.contains(&rule.target)
+            && !group_names.contains(&rule.target)
+            && !rules::is_builtin_target(&rule.target)
+        {
+            collector.error(position, format!("规则引用了不存在的策略: {}", rule.target));
+        }
+    }
+}
+
+// 检查规则列表中不会生效或影响性能的规则，只给出警告:
+// 重复的规则、被前面更宽泛的规则覆盖的规则、MATCH之后的规则、缺少MATCH，
+// 以及后面还有域名规则、却没有no-resolve的IP-CIDR规则（域名连接经过时需要先解析域名）
+// geosite为false时不读取geosite.dat，跳过被GEOSITE规则覆盖的检查
+fn lint_rules(config: &ClashConfig, geosite: bool, collector: &mut Collector) {
+    let Some(rule_lines) = config.rules.as_ref().filter(|rules| !rules.is_empty()) else {
+        return;
+    };
+
+    let rules_line = collector.source.find_key("rules");
+    let mut from = rules_line.map(|(line, _)| line).unwrap_or(1);
+    let mut linter = RuleLinter {
+        geosite,
+        ..Default::default()
+    };
+    let mut seen: HashMap<String, (&str, String)> = HashMap::new();
+    let mut unresolved: Vec<(Option<(usize, usize)>, &str)> = Vec::new();
+    let mut match_rule: Option<&str> = None;
+
+    for line in rule_lines {
+        // 按顺序查找位置，重复的规则标注在各自所在的行
+        let position = collector.source.find_from(line, from);
+        if let Some((line_number, _)) = position {
+            from = line_number + 1;
+        }
+        // 格式错误已在check_rules中报告
+        let Ok(rule) = rules::parse_rule(line) else {
+            continue;
+        };
+
+        if let Some(match_rule) = match_rule {
+            collector.warning(
+                position,
+                format!("规则在 {} 之后，永远不会命中: {}", match_rule, line),
+            );
+            continue;
+        }
+        if rule.rule_type == RuleType::Match {
+            match_rule = Some(line);
+            continue;
+        }
+
+        if is_domain_rule(config, &rule) {
+            for (ip_position, ip_line) in unresolved.drain(..) {
+                collector.warning(
+                    ip_position,
+                    format!(
+                        "{} 没有no-resolve，后面的域名规则（如 {}）匹配前都需要先解析域名",
+                        ip_line, line
+                    ),
+                );
+            }
+        }
+        if matches!(rule.rule_type, RuleType::IpCidr | RuleType::IpCidr6)
+            && !has_param(&rule, "no-resolve")
+            && !has_param(&rule, "src")
+        {
+            unresolved.push((position, line));
+        }
+
+        match seen.get(&lint_key(&rule)) {
+            Some((previous, target)) if *target == rule.target => {
+                collector.warning(position, format!("与前面的规则 {} 重复", previous));
+                continue;
+            }
+            Some((previous, _)) => {
+                collector.warning(
+                    position,
+                    format!("条件与前面的规则 {} 相同，永远不会命中: {}", previous, line),
+                );
+                continue;
+            }
+            None => {
+                seen.insert(lint_key(&rule), (line, rule.target.clone()));
+            }
+        }
+        if let Some(previous) = linter.shadowed_by(&rule) {
+            collector.warning(
+                position,
+                format!("已被前面的规则 {} 覆盖，永远不会命中: {}", previous, line),
+            );
+        }
+        linter.push(line, rule);
+    }
+
+    if match_rule.is_none() {
+        collector.warning(
+            rules_line,
+            "规则列表最后没有MATCH，未命中任何规则的连接将直接连接".to_string(),
+        );
+    }
+}
+
+// 前面的规则中可能覆盖后面规则的部分: 域名后缀、关键字、geosite分类和IP段
+#[derive(Default)]
+struct RuleLinter<'a> {
+    rules: Vec<(&'a str, Rule)>,
+    // 是否检查GEOSITE规则的覆盖
+    geosite: bool,
+    geo: GeoFiles,
+    // geosite分类中的域名，读取失败的分类为空，不参与检查
+    sites: HashMap<String, Vec<SiteDomain>>,
+}
+
+impl<'a> RuleLinter<'a> {
+    fn push(&mut self, line: &'a str, rule: Rule) {
+        let broad = matches!(
+            rule.rule_type,
+            RuleType::DomainSuffix
+                | RuleType::DomainKeyword
+                | RuleType::Geosite
+                | RuleType::IpCidr
+                | RuleType::IpCidr6
+        );
+        if broad && !has_param(&rule, "src") {
+            self.rules.push((line, rule));
+        }
+    }
+
+    // 返回覆盖该规则的前一条规则
+    fn shadowed_by(&mut self, rule: &Rule) -> Option<&'a str> {
+        if has_param(rule, "src") {
+            return None;
+        }
+        let payload = rule.payload.to_lowercase();
+        for index in 0..self.rules.len() {
+            let (line, earlier) = &self.rules[index];
+            let line = *line;
+            let earlier_payload = earlier.payload.to_lowercase();
+            let covered = match (earlier.rule_type, rule.rule_type) {
+                (RuleType::DomainSuffix, RuleType::Domain | RuleType::DomainSuffix) => {
+                    suffix_match(&payload, &earlier_payload)
+                }
+                (
+                    RuleType::DomainKeyword,
+                    RuleType::Domain | RuleType::DomainSuffix | RuleType::DomainKeyword,
+                ) => payload.contains(&earlier_payload),
+                (RuleType::Geosite, RuleType::Domain | RuleType::DomainSuffix) if self.geosite => {
+                    let full = rule.rule_type == RuleType::Domain;
+                    self.site(&earlier_payload)
+                        .iter()
+                        .any(|domain| site_covers(domain, &payload, full))
+                }
+                (RuleType::IpCidr | RuleType::IpCidr6, RuleType::IpCidr | RuleType::IpCidr6) => {
+                    // 前面的规则有no-resolve而后面的没有时，后面的规则仍可能在解析域名后命中
+                    (!has_param(earlier, "no-resolve") || has_param(rule, "no-resolve"))
+                        && cidr_covers(&earlier_payload, &payload)
+                }
+                _ => false,
+            };
+            if covered {
+                return Some(line);
+            }
+        }
+        None
+    }
+
+    fn site(&mut self, category: &str) -> &[SiteDomain] {
+        self.sites
+            .entry(category.to_string())
+            .or_insert_with(|| self.geo.site(category).unwrap_or_default())
+    }
+}
+
+// geosite中的域名是否一定命中该域名（full）或该后缀下的所有域名，正则不参与检查
+fn site_covers(domain: &SiteDomain, payload: &str, full: bool) -> bool {
+    match domain.kind {
+        DomainKind::Full => full && domain.value == payload,
+        DomainKind::Suffix => suffix_match(payload, &domain.value),
+        DomainKind::Keyword => payload.contains(&domain.value),
+        DomainKind::Regex => false,
+    }
+}
+
+// 前一个IP段是否包含后一个IP段
+fn cidr_covers(outer: &str, inner: &str) -> bool {
+    let (Some((_, outer_prefix)), Some((inner_address, inner_prefix))) =
+        (parse_cidr(outer), parse_cidr(inner))
+    else {
+        return false;
+    };
+    inner_prefix >= outer_prefix && cidr_contains(outer, inner_address) == Some(true)
+}
+
+// 按域名匹配的规则，包括引用domain规则集的RULE-SET
+fn is_domain_rule(config: &ClashConfig, rule: &Rule) -> bool {
+    match rule.rule_type {
+        RuleType::Domain
+        | RuleType::DomainSuffix
+        | RuleType::DomainKeyword
+        | RuleType::DomainRegex
+        | RuleType::Geosite => true,
+        RuleType::RuleSet => config
+            .rule_providers
+            .as_ref()
+            .and_then(|providers| providers.get(&rule.payload))
+            .is_some_and(|provider| provider.behavior == RuleBehavior::Domain),
+        _ => false,
+    }
+}
+
+fn has_param(rule: &Rule, name: &str) -> bool {
+    rule.params
+        .iter()
+        .flatten()
+        .any(|param| param.eq_ignore_ascii_case(name))
+}
+
+// 判断重复规则使用的键: 类型、内容和参数，不含策略
+fn lint_key(rule: &Rule) -> String {
+    let payload = match rule.rule_type {
+        RuleType::DomainRegex => rule.payload.clone(),
+        _ => rule.payload.to_lowercase(),
+    };
+    let mut params: Vec<String> = rule
+        .params
+        .iter()
+        .flatten()
+        .map(|param| param.to_lowercase())
+        .collect();
+    params.sort();
+    format!("{:?},{},{}", rule.rule_type, payload, params.join(","))
+}
@@ -204,8 +204,8 @@ fn encrypt_data(data_str: &str, key_base64: &str) -> Result<String, String> {
     let mut nonce_bytes = [0u8; 12];
     // 使用时间戳和数据前几个字符生成nonce
     let data_bytes = data_str.as_bytes();
-    for i in 0..12 {
-        nonce_bytes[i] = ((timestamp >> (i * 5)) as u8) ^ data_bytes.get(i).unwrap_or(&0);
+    for (i, byte) in nonce_bytes.iter_mut().enumerate() {
+        *byte = ((timestamp >> (i * 5)) as u8) ^ data_bytes.get(i).unwrap_or(&0);
     }
     let nonce = Nonce::from_slice(&nonce_bytes);
 